@@ -1,9 +1,10 @@
 use serde::Serialize;
 
 use picocaml::{
-    analysis::{parser::parse, tokenizer::tokenize},
-    execution::{environment::Environment, evaluation::eval},
-    type_system::{inference::infer, type_environment::TypeEnvironment},
+    analysis::{parser::parse_program, tokenizer::tokenize},
+    builtin,
+    execution::evaluation::{eval_top_level, with_evaluation_stack},
+    type_system::inference::infer_top_level,
 };
 
 #[derive(Debug, Serialize)]
@@ -11,35 +12,68 @@ pub struct LabReport {
     pub source: String,
     pub ast: Option<String>,
     pub ty: Option<String>,
+    pub warnings: Vec<String>,
     pub value: Option<String>,
     pub phase: Option<String>,
     pub error: Option<String>,
 }
 
-fn evaluate(source: &str) -> LabReport {
+// プログラムの宣言を順に型検査・評価し、最後の宣言の型と値を報告する
+// prelude が false なら標準ライブラリを読み込まずに始める
+fn evaluate(source: &str, prelude: bool) -> LabReport {
     let source = source.trim().to_owned();
-    let expression = match parse(tokenize(source.clone())) {
-        Ok(expression) => expression,
+    let program = match tokenize(source.clone()).and_then(parse_program) {
+        Ok(program) => program,
         Err(error) => return error_report(source, "parse", error.to_string(), None, None),
     };
 
-    let ast = Some(format!("{expression:#?}"));
-    let (_type_environment, ty) = match infer(TypeEnvironment::default(), expression.clone()) {
-        Ok(result) => result,
-        Err(error) => return error_report(source, "type", error.to_string(), ast, None),
+    let environments = if prelude {
+        builtin::environments()
+    } else {
+        builtin::bare_environments()
     };
+    let (mut environment, mut type_environment) =
+        environments.expect("built-in definitions should be well-typed");
 
-    let ty = ty.to_string();
-    match eval(Environment::default(), expression) {
-        Ok((_, value)) => LabReport {
-            source,
-            ast,
-            ty: Some(ty),
-            value: Some(value.to_string()),
-            phase: None,
-            error: None,
-        },
-        Err(error) => error_report(source, "evaluation", error.to_string(), ast, Some(ty)),
+    let ast = Some(format!("{program:#?}"));
+    let mut ty = None;
+    let mut warnings = Vec::new();
+    let mut value = None;
+    for top_level in program.top_levels {
+        let (inferred_environment, t) = match infer_top_level(type_environment, top_level.clone()) {
+            Ok(result) => result,
+            Err(error) => {
+                return LabReport {
+                    warnings,
+                    ..error_report(source, "type", error.to_string(), ast, ty)
+                };
+            }
+        };
+        type_environment = inferred_environment;
+        ty = Some(t.to_string());
+        warnings.extend(type_environment.warnings().iter().map(ToString::to_string));
+
+        let (evaluated_environment, v) = match eval_top_level(environment, top_level) {
+            Ok(result) => result,
+            Err(error) => {
+                return LabReport {
+                    warnings,
+                    ..error_report(source, "evaluation", error.to_string(), ast, ty)
+                };
+            }
+        };
+        environment = evaluated_environment;
+        value = Some(v.to_string());
+    }
+
+    LabReport {
+        source,
+        ast,
+        ty,
+        warnings,
+        value,
+        phase: None,
+        error: None,
     }
 }
 
@@ -54,6 +88,7 @@ fn error_report(
         source,
         ast,
         ty,
+        warnings: Vec::new(),
         value: None,
         phase: Some(phase.to_owned()),
         error: Some(error),
@@ -61,6 +96,7 @@ fn error_report(
 }
 
 #[wasm_bindgen::prelude::wasm_bindgen]
-pub fn run(source: String) -> String {
-    serde_json::to_string(&evaluate(&source)).expect("LabReport should be serializable")
+pub fn run(source: String, prelude: bool) -> String {
+    let report = with_evaluation_stack(|| evaluate(&source, prelude));
+    serde_json::to_string(&report).expect("LabReport should be serializable")
 }
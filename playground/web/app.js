@@ -3,7 +3,10 @@ import init, { run } from "./pkg/picocaml_playground.js";
 const examples = [
   ["arithmetic", "let twice = fun x -> x * 2 in twice 21"],
   ["polymorphic identity", "let id = fun x -> x in id 7"],
-  ["list", "match (1 :: (2 :: [])) with [] -> 0 | h :: t -> h"],
+  ["list", "match [1; 2] @ [3] with [] -> 0 | h :: t -> h"],
+  ["prelude", "[1; 2; 3] |> List.map (fun x -> x * x) |> List.fold_left ( + ) 0"],
+  ["declarations", "let rec fact = fun n -> if n < 1 then 1 else n * fact (n - 1)\nlet x = fact 5;;\nx + 1"],
+  ["strings", "let greet = fun name -> \"Hello, \" ^ name ^ \"!\\n\" in greet \"\\\"picocaml\\\"\""],
   ["type error", "1 + true"],
 ];
 
@@ -12,10 +15,13 @@ const type = document.querySelector("#type");
 const value = document.querySelector("#value");
 const ast = document.querySelector("#ast");
 const status = document.querySelector("#status");
+const warningCard = document.querySelector("#warning-card");
+const warnings = document.querySelector("#warnings");
 const errorCard = document.querySelector("#error-card");
 const errorTitle = document.querySelector("#error-title");
 const error = document.querySelector("#error");
 const examplesPanel = document.querySelector("#examples");
+const prelude = document.querySelector("#prelude");
 
 function encode(value) {
   return btoa(unescape(encodeURIComponent(value)));
@@ -34,10 +40,12 @@ function execute() {
   const program = source.value.trim();
   if (!program) return;
   status.textContent = "running";
-  const report = JSON.parse(run(program));
+  const report = JSON.parse(run(program, prelude.checked));
   setOutput(type, report.ty, !report.ty);
   setOutput(value, report.value, !report.value);
   setOutput(ast, report.ast, !report.ast);
+  warningCard.classList.toggle("hidden", report.warnings.length === 0);
+  warnings.textContent = report.warnings.join("\n");
   errorCard.classList.toggle("hidden", !report.error);
   if (report.error) {
     errorTitle.textContent = `${report.phase} error`;
@@ -1,14 +1,53 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use picocaml::{
-    analysis::{parser::parse, tokenizer::tokenize},
-    execution::{environment::Environment, evaluation::eval},
-    type_system::{inference::infer, type_environment::TypeEnvironment},
+    analysis::{parser::parse_program, tokenizer::tokenize},
+    builtin,
+    execution::{
+        environment::Environment,
+        evaluation::{eval_top_level, with_evaluation_stack},
+    },
+    loader::load_files,
+    syntax::{
+        ast::{Program, TopLevel},
+        value::Value,
+    },
+    type_system::{inference::infer_top_level, type_environment::TypeEnvironment, types::Type},
 };
 use rustyline::{DefaultEditor, error::ReadlineError};
 
 fn main() -> Result<()> {
-    let mut global_type_environment = TypeEnvironment::default();
-    let mut global_environment = Environment::default();
+    with_evaluation_stack(repl)
+}
+
+fn repl() -> Result<()> {
+    // --no-prelude を渡すと標準ライブラリを読み込まずに始める
+    let (flags, arguments): (Vec<_>, Vec<_>) =
+        std::env::args().skip(1).partition(|a| a.starts_with("--"));
+    let (global_environment, global_type_environment) =
+        if flags.iter().any(|flag| flag == "--no-prelude") {
+            builtin::bare_environments()?
+        } else {
+            builtin::environments()?
+        };
+
+    // 引数に渡したファイルは、それぞれモジュールとして読み込んでから対話を始める
+    let paths = arguments.into_iter().map(PathBuf::from).collect::<Vec<_>>();
+    let (mut global_environment, mut global_type_environment, modules) =
+        match load_files(global_environment, global_type_environment, &paths) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+    for module in modules {
+        for warning in module.warnings {
+            eprintln!("File \"{}\": Warning: {}", module.path.display(), warning);
+        }
+        println!("module {}", module.name);
+    }
 
     let mut rl = DefaultEditor::new()?;
 
@@ -19,36 +58,20 @@ fn main() -> Result<()> {
                 code.push_str(line.as_ref());
                 code.push('\n');
             }
+            Err(ReadlineError::Eof) if code.trim().is_empty() => {
+                println!("Bye ;)");
+                break;
+            }
             Err(ReadlineError::Eof) => {
                 rl.add_history_entry(code.as_str())?;
 
-                match parse(tokenize(code.clone())) {
-                    Ok(expression) => {
-                        let infered = infer(global_type_environment.clone(), expression.clone());
-                        if let Err(e) = infered {
-                            eprintln!("{}", e);
-                            continue;
-                        }
-                        let (type_environment, ty) = infered.unwrap();
-                        global_type_environment = type_environment;
-                        println!("Type: {}", ty);
-
-                        let evaluated = eval(global_environment.clone(), expression.clone());
-                        if let Err(e) = evaluated {
-                            eprintln!("{}", e);
-                            continue;
-                        }
-                        let (environment, value) = evaluated.unwrap();
-                        global_environment = environment;
-                        println!("Value: {}", value);
-                    }
-                    Err(e) => {
-                        eprintln!("{}", e);
-                        continue;
+                match tokenize(std::mem::take(&mut code)).and_then(parse_program) {
+                    Ok(program) => {
+                        (global_environment, global_type_environment) =
+                            run(global_environment, global_type_environment, program);
                     }
+                    Err(e) => eprintln!("{}", e),
                 }
-
-                code.clear();
             }
             Err(ReadlineError::Interrupted) => {
                 println!("Bye ;)");
@@ -63,3 +86,93 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+// 宣言を先頭から順に型検査・評価し、エラーが起きたらそこで止める
+// エラーより前の宣言は大域環境に残る
+fn run(
+    mut environment: Environment,
+    mut type_environment: TypeEnvironment,
+    program: Program,
+) -> (Environment, TypeEnvironment) {
+    for top_level in program.top_levels {
+        let infered = infer_top_level(type_environment.clone(), top_level.clone());
+        if let Err(e) = infered {
+            eprintln!("{}", e);
+            break;
+        }
+        let (new_type_environment, ty) = infered.unwrap();
+        for warning in new_type_environment.warnings() {
+            eprintln!("Warning: {}", warning);
+        }
+
+        let evaluated = eval_top_level(environment.clone(), top_level.clone());
+        if let Err(e) = evaluated {
+            eprintln!("{}", e);
+            break;
+        }
+        let (new_environment, value) = evaluated.unwrap();
+        type_environment = new_type_environment;
+        environment = new_environment;
+
+        match top_level {
+            TopLevel::Expression(_) => {
+                println!("Type: {}", ty);
+                println!("Value: {}", value);
+            }
+            TopLevel::Let { variable, .. } => {
+                println!("val {} : {} = {}", value_name(&variable), ty, value);
+            }
+            // 変数を束縛しない let () = e や let _ = e は、式と同じく表示する
+            TopLevel::LetPattern { pattern, .. } => match pattern.variables().as_slice() {
+                [] => {
+                    println!("Type: {}", ty);
+                    println!("Value: {}", value);
+                }
+                variables => print_values(variables, ty, value),
+            },
+            TopLevel::LetRec { bindings } => {
+                let variables = bindings
+                    .into_iter()
+                    .map(|(variable, _)| variable)
+                    .collect::<Vec<_>>();
+                print_values(&variables, ty, value);
+            }
+            TopLevel::TypeDefinition(definitions) => {
+                for definition in definitions {
+                    println!("type {}", definition.name);
+                }
+            }
+            TopLevel::Exception { name, .. } => println!("exception {}", name),
+            TopLevel::Module { name, .. } => println!("module {}", name),
+            TopLevel::ModuleType { name, .. } => println!("module type {}", name),
+            TopLevel::Open(_) => {}
+        }
+    }
+
+    (environment, type_environment)
+}
+
+// let rec ... and ... や let (a, b) = e の型と値は、束縛した名前ごとの組になっている
+fn print_values(variables: &[String], ty: Type, value: Value) {
+    match (ty, value) {
+        (Type::Tuple(types), Value::Tuple(values)) if variables.len() > 1 => {
+            for ((variable, ty), value) in variables.iter().zip(types).zip(values) {
+                println!("val {} : {} = {}", value_name(variable), ty, value);
+            }
+        }
+        (ty, value) => println!("val {} : {} = {}", value_name(&variables[0]), ty, value),
+    }
+}
+
+// 演算子を束縛した名前は ( op ) の形で表示する
+fn value_name(variable: &str) -> String {
+    if variable != "mod"
+        && variable
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+    {
+        variable.to_string()
+    } else {
+        format!("( {} )", variable)
+    }
+}
@@ -1,6 +1,7 @@
 use std::{
+    cmp,
     collections::HashSet,
-    ops::{Add, Mul, Sub},
+    ops::Add,
     sync::atomic::{AtomicUsize, Ordering},
 };
 
@@ -8,27 +9,132 @@ use crate::type_system::types::Type;
 
 pub(crate) type RInteger = isize;
 pub(crate) type RBool = bool;
+pub(crate) type RString = String;
+pub(crate) type RChar = char;
 
 pub(crate) type Symbol = String;
 
-pub(crate) type RArithmeticOperation = fn(RInteger, RInteger) -> RInteger;
+// OCamlと同じく桁あふれは折り返す。0での除算はNoneを返す
+pub(crate) type RArithmeticOperation = fn(RInteger, RInteger) -> Option<RInteger>;
 
-pub(crate) fn r_plus(lhs: RInteger, rhs: RInteger) -> RInteger {
+pub(crate) fn r_plus(lhs: RInteger, rhs: RInteger) -> Option<RInteger> {
+    Some(lhs.wrapping_add(rhs))
+}
+
+pub(crate) fn r_minus(lhs: RInteger, rhs: RInteger) -> Option<RInteger> {
+    Some(lhs.wrapping_sub(rhs))
+}
+
+pub(crate) fn r_times(lhs: RInteger, rhs: RInteger) -> Option<RInteger> {
+    Some(lhs.wrapping_mul(rhs))
+}
+
+pub(crate) fn r_divide(lhs: RInteger, rhs: RInteger) -> Option<RInteger> {
+    (rhs != 0).then(|| lhs.wrapping_div(rhs))
+}
+
+// 剰余の符号は割られる数に合わせる
+pub(crate) fn r_modulo(lhs: RInteger, rhs: RInteger) -> Option<RInteger> {
+    (rhs != 0).then(|| lhs.wrapping_rem(rhs))
+}
+
+pub(crate) fn r_negate(operand: RInteger) -> RInteger {
+    operand.wrapping_neg()
+}
+
+// 比較はどの型の値でも構造的に行うので、比較結果の順序から真偽値を決める
+pub(crate) type RComparisonOperation = fn(cmp::Ordering) -> RBool;
+
+pub(crate) fn r_eq(ordering: cmp::Ordering) -> RBool {
+    ordering.is_eq()
+}
+
+pub(crate) fn r_ne(ordering: cmp::Ordering) -> RBool {
+    ordering.is_ne()
+}
+
+pub(crate) fn r_lt(ordering: cmp::Ordering) -> RBool {
+    ordering.is_lt()
+}
+
+pub(crate) fn r_le(ordering: cmp::Ordering) -> RBool {
+    ordering.is_le()
+}
+
+pub(crate) fn r_gt(ordering: cmp::Ordering) -> RBool {
+    ordering.is_gt()
+}
+
+pub(crate) fn r_ge(ordering: cmp::Ordering) -> RBool {
+    ordering.is_ge()
+}
+
+pub(crate) fn r_concat(lhs: RString, rhs: &str) -> RString {
     lhs.add(rhs)
 }
 
-pub(crate) fn r_minus(lhs: RInteger, rhs: RInteger) -> RInteger {
-    lhs.sub(rhs)
+// OCamlのString.escaped/Char.escapedと同じ規則でエスケープする
+pub(crate) fn escape_string(s: &str) -> String {
+    s.chars().map(|c| escape(c, '"')).collect()
 }
 
-pub(crate) fn r_times(lhs: RInteger, rhs: RInteger) -> RInteger {
-    lhs.mul(rhs)
+pub(crate) fn escape_char(c: RChar) -> String {
+    escape(c, '\'')
 }
 
-pub(crate) type RComparisonOperation = fn(RInteger, RInteger) -> RBool;
+fn escape(c: RChar, quote: char) -> String {
+    match c {
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\u{8}' => "\\b".to_string(),
+        c if c == quote => format!("\\{}", c),
+        c if c.is_control() => format!("\\{:03}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+// M.N.x のような経路を、先頭のモジュールの名前と残りに分ける
+// 演算子の名前にも . は含まれうるので、大文字で始まるものだけをモジュールの名前とみなす
+pub(crate) fn split_path(name: &str) -> Option<(&str, &str)> {
+    let (module, rest) = name.split_once('.')?;
+    module
+        .starts_with(|c: char| c.is_ascii_uppercase())
+        .then_some((module, rest))
+}
+
+// 経路の最後の名前
+pub(crate) fn base_name(name: &str) -> &str {
+    match split_path(name) {
+        Some((_, rest)) => base_name(rest),
+        None => name,
+    }
+}
 
-pub(crate) fn r_lt(lhs: RInteger, rhs: RInteger) -> RBool {
-    lhs.lt(&rhs)
+// 構文木の決まった節になる組み込みの演算子。環境に束縛した定義は ( op ) と書いたときにだけ使われる
+pub(crate) fn is_builtin_operator(name: &str) -> bool {
+    matches!(
+        name,
+        "+" | "-"
+            | "*"
+            | "/"
+            | "mod"
+            | "="
+            | "<>"
+            | "<"
+            | "<="
+            | ">"
+            | ">="
+            | "&&"
+            | "||"
+            | "^"
+            | "@"
+            | "|>"
+            | "@@"
+            | "!"
+            | ":="
+    )
 }
 
 pub(crate) fn unique_symbol() -> Symbol {
@@ -38,5 +144,13 @@ pub(crate) fn unique_symbol() -> Symbol {
     format!("_t{next_id}")
 }
 
+// 見えなくなった型には t/1 のように番号をつけた名前を与え、同じ名前の別の型と区別する
+pub(crate) fn unique_type_name(name: &str) -> Symbol {
+    static NEXT_TYPE_ID: AtomicUsize = AtomicUsize::new(1);
+
+    let next_id = NEXT_TYPE_ID.fetch_add(1, Ordering::Relaxed);
+    format!("{name}/{next_id}")
+}
+
 pub(crate) type SymbolTraverseHistory = HashSet<Symbol>;
 pub(crate) type TypeTraverseHistory = HashSet<Type>;
@@ -1,9 +1,16 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
 
 use anyhow::{Result, bail, ensure};
 use thiserror::Error;
 
-use crate::syntax::ast::Expression;
+use crate::{
+    adapter::base_name,
+    syntax::ast::{
+        Expression, ModuleExpression, ModuleType, Pattern, Program, RecordField, Specification,
+        TopLevel, TypeDefinition, TypeKind,
+    },
+    type_system::types::{BaseType, Type},
+};
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 enum Assoc {
@@ -21,6 +28,12 @@ enum ParseError {
     InvalidSyntax(String),
     #[error("Unclosed input")]
     Unclosed,
+    #[error("Variable {0} is bound several times in this matching")]
+    DuplicateBinding(String),
+    #[error("Type parameter {0} occurs several times")]
+    DuplicateTypeParameter(String),
+    #[error("This kind of expression is not allowed as right-hand side of let rec")]
+    RecursiveNonFunction,
 }
 
 pub fn parse(mut tokens: VecDeque<String>) -> Result<Expression> {
@@ -32,6 +45,124 @@ pub fn parse(mut tokens: VecDeque<String>) -> Result<Expression> {
     Ok(expr)
 }
 
+pub fn parse_type_expression(mut tokens: VecDeque<String>) -> Result<Type> {
+    let t = parse_type(&mut tokens)?;
+    ensure!(
+        tokens.is_empty(),
+        ParseError::Unexpected(tokens.pop_front().unwrap())
+    );
+    Ok(t)
+}
+
+// プログラムはトップレベルの宣言と式の列。式の前の宣言とは `;;` で区切る
+pub fn parse_program(mut tokens: VecDeque<String>) -> Result<Program> {
+    let top_levels = parse_top_levels(&mut tokens)?;
+    match next(&mut tokens) {
+        None => Ok(Program { top_levels }),
+        Some(t) => bail!(ParseError::Unexpected(t)),
+    }
+}
+
+// インターフェースファイルはシグネチャの sig と end の間と同じく、宣言の並び
+pub fn parse_interface(mut tokens: VecDeque<String>) -> Result<Vec<Specification>> {
+    let mut specifications = Vec::new();
+    while peek(&tokens).is_some() {
+        specifications.push(parse_specification(&mut tokens)?);
+    }
+    Ok(specifications)
+}
+
+// トップレベルの宣言の列を、入力の終わりか struct ... end の end の手前まで読む
+fn parse_top_levels(tokens: &mut VecDeque<String>) -> Result<Vec<TopLevel>> {
+    let mut top_levels = Vec::new();
+    loop {
+        while matches!(peek(tokens), Some(";;")) {
+            next(tokens);
+        }
+        if matches!(peek(tokens), None | Some("end")) {
+            return Ok(top_levels);
+        }
+
+        top_levels.push(parse_top_level(tokens)?);
+        match peek(tokens) {
+            None | Some(";;" | "let" | "type" | "exception" | "module" | "open" | "end") => {}
+            Some(_) => bail!(ParseError::Unexpected(tokens.pop_front().unwrap())),
+        }
+    }
+}
+
+fn parse_top_level(tokens: &mut VecDeque<String>) -> Result<TopLevel> {
+    match peek(tokens) {
+        Some("let") if tokens.get(1).is_some_and(|t| t == "open") => {
+            Ok(TopLevel::Expression(parse_expr(tokens, 0)?))
+        }
+        Some("let") => {
+            let binding = parse_let_binding(tokens)?;
+            if matches!(peek(tokens), Some("in")) {
+                next(tokens);
+                let body = parse_expr(tokens, 0)?;
+                return Ok(TopLevel::Expression(let_expression(binding, body)));
+            }
+
+            match binding {
+                LetBinding::Recursive(bindings) => Ok(TopLevel::LetRec { bindings }),
+                LetBinding::Pattern(Pattern::Variable(variable), bound) => {
+                    Ok(TopLevel::Let { variable, bound })
+                }
+                LetBinding::Pattern(pattern, bound) => Ok(TopLevel::LetPattern { pattern, bound }),
+            }
+        }
+        Some("type") => {
+            let definitions = parse_type_bindings(tokens)?;
+            if matches!(peek(tokens), Some("in")) {
+                next(tokens);
+                let body = parse_expr(tokens, 0)?;
+                return Ok(TopLevel::Expression(Expression::TypeDefinition {
+                    definitions,
+                    body: Box::new(body),
+                }));
+            }
+
+            Ok(TopLevel::TypeDefinition(definitions))
+        }
+        Some("exception") => {
+            next(tokens);
+            let (name, argument) = parse_constructor_declaration(tokens)?;
+            Ok(TopLevel::Exception { name, argument })
+        }
+        Some("module") if tokens.get(1).is_some_and(|t| t == "type") => {
+            next(tokens);
+            next(tokens);
+            let name = parse_module_name(tokens)?;
+            expect(tokens, "=")?;
+            let module_type = parse_module_type(tokens)?;
+            Ok(TopLevel::ModuleType { name, module_type })
+        }
+        Some("module") => {
+            next(tokens);
+            let name = parse_module_name(tokens)?;
+            let parameters = parse_functor_parameters(tokens)?;
+            let module_type = if matches!(peek(tokens), Some(":")) {
+                next(tokens);
+                Some(parse_module_type(tokens)?)
+            } else {
+                None
+            };
+            expect(tokens, "=")?;
+            let module = ascribe(parse_module_expression(tokens)?, module_type);
+            Ok(TopLevel::Module {
+                name,
+                module: functor(parameters, module),
+            })
+        }
+        Some("open") => {
+            next(tokens);
+            Ok(TopLevel::Open(parse_module_path(tokens)?))
+        }
+        _ => Ok(TopLevel::Expression(parse_expr(tokens, 0)?)),
+    }
+}
+
 fn peek(tokens: &VecDeque<String>) -> Option<&str> {
     tokens.front().map(|s| s.as_str())
 }
@@ -48,34 +179,191 @@ fn expect(tokens: &mut VecDeque<String>, expected: &str) -> Result<()> {
     Ok(())
 }
 
+const KEYWORDS: &[&str] = &[
+    "if",
+    "then",
+    "else",
+    "let",
+    "rec",
+    "in",
+    "fun",
+    "function",
+    "match",
+    "with",
+    "true",
+    "false",
+    "type",
+    "of",
+    "and",
+    "mutable",
+    "ref",
+    "exception",
+    "raise",
+    "try",
+    "assert",
+    "mod",
+    "module",
+    "struct",
+    "sig",
+    "end",
+    "open",
+    "val",
+    "functor",
+];
+
 fn is_identifier(tok: &str) -> bool {
+    if KEYWORDS.contains(&tok) {
+        return false;
+    }
     let mut chars = tok.chars();
     match chars.next() {
         Some(c) if c.is_ascii_lowercase() => {}
+        Some('_') if tok.len() > 1 => {}
         _ => return false,
     }
     chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
 }
 
+fn is_constructor(tok: &str) -> bool {
+    let mut chars = tok.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_uppercase() => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// M.N.x や M.( + ) のように . でつないだ名前を読む。first は読み終えた先頭の名前
+// モジュールの名前(大文字で始まる名前)の後にだけ . を続けられる
+fn parse_path(tokens: &mut VecDeque<String>, first: String) -> String {
+    let mut path = first;
+    while is_constructor(base_name(&path)) && matches!(peek(tokens), Some(".")) {
+        let name = match tokens.get(1) {
+            Some(t) if is_constructor(t) || is_identifier(t) => {
+                let name = t.clone();
+                tokens.drain(..2);
+                name
+            }
+            Some(t) if t == "(" && tokens.get(2).is_some_and(|op| is_operator_name(op)) => {
+                next(tokens);
+                match parse_operator_name(tokens) {
+                    Some(op) => op,
+                    None => {
+                        tokens.push_front(".".to_string());
+                        break;
+                    }
+                }
+            }
+            _ => break,
+        };
+        path.push('.');
+        path.push_str(&name);
+    }
+    path
+}
+
+fn is_type_variable(tok: &str) -> bool {
+    tok.strip_prefix('\'').is_some_and(is_identifier)
+}
+
+// トークナイザは文字列リテラルを "内容" 、文字リテラルを '文字' の形にしている
+fn string_literal(tok: &str) -> Option<String> {
+    let content = tok.strip_prefix('"')?.strip_suffix('"')?;
+    Some(content.to_string())
+}
+
+fn char_literal(tok: &str) -> Option<char> {
+    let mut chars = tok.strip_prefix('\'')?.strip_suffix('\'')?.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
 fn starts_primary(tokens: &VecDeque<String>) -> bool {
     match peek(tokens) {
-        Some("(") | Some("[]") | Some("true") | Some("false") => true,
+        Some("(") | Some("[") | Some("[]") | Some("{") | Some("true") | Some("false") => true,
+        Some("ref") | Some("!") | Some("raise") | Some("assert") => true,
         Some(s) if s.parse::<isize>().is_ok() => true,
-        Some(s) if is_identifier(s) => true,
+        Some(s) if string_literal(s).is_some() || char_literal(s).is_some() => true,
+        Some(s) if is_identifier(s) || is_constructor(s) => true,
         _ => false,
     }
 }
 
+fn starts_pattern(tokens: &VecDeque<String>) -> bool {
+    matches!(peek(tokens), Some("_")) || starts_primary(tokens)
+}
+
+// e1; e2 は最も弱く結合する。if の枝やレコードのフィールドはこれより強い結合力で読む
+const SEQUENCE_BP: i32 = 10;
+// 単項のマイナスは二項演算子より強く、関数適用より弱く結合する
+const NEGATION_BP: i32 = 80;
+
+// OCamlの演算子の優先順位と結合性に合わせる
+// 利用者が定義する演算子は、OCamlと同じく先頭の文字で優先順位と結合性が決まる
 fn precedence(op: &str) -> Option<(i32, Assoc)> {
     match op {
-        "*" => Some((70, Assoc::Left)),
-        "+" | "-" => Some((60, Assoc::Left)),
-        "<" => Some((50, Assoc::Left)),
-        "::" => Some((40, Assoc::Right)),
+        "mod" => Some((70, Assoc::Left)),
+        "::" => Some((55, Assoc::Right)),
+        "&" | "&&" => Some((40, Assoc::Right)),
+        "||" => Some((35, Assoc::Right)),
+        "," => Some((30, Assoc::Left)),
+        "<-" | ":=" => Some((20, Assoc::Right)),
+        ";" => Some((SEQUENCE_BP, Assoc::Right)),
+        _ if op.starts_with("**") => Some((85, Assoc::Right)),
+        _ if !is_infix_operator(op) => None,
+        _ => match op.chars().next()? {
+            '*' | '/' | '%' => Some((70, Assoc::Left)),
+            '+' | '-' => Some((60, Assoc::Left)),
+            '@' | '^' => Some((52, Assoc::Right)),
+            '=' | '<' | '>' | '|' | '&' | '$' => Some((50, Assoc::Left)),
+            _ => None,
+        },
+    }
+}
+
+// ( op ) と書いて関数として使える演算子
+fn is_operator_name(tok: &str) -> bool {
+    is_infix_operator(tok)
+        || is_binding_operator("let", tok)
+        || is_binding_operator("and", tok)
+        || matches!(tok, "mod" | "!" | ":=")
+}
+
+// let* や and+ のように、キーワードの直後に演算子の文字が続く束縛演算子
+fn is_binding_operator(keyword: &str, tok: &str) -> bool {
+    tok.strip_prefix(keyword)
+        .is_some_and(|op| op.starts_with(|c: char| "$&*+-/=>@^|<".contains(c)))
+}
+
+// ( op ) を読み、演算子の名前を返す
+fn parse_operator_name(tokens: &mut VecDeque<String>) -> Option<String> {
+    match (tokens.front(), tokens.get(1), tokens.get(2)) {
+        (Some(open), Some(op), Some(close))
+            if open == "(" && is_operator_name(op) && close == ")" =>
+        {
+            next(tokens);
+            let op = next(tokens);
+            next(tokens);
+            op
+        }
         _ => None,
     }
 }
 
+// トークナイザが演算子の文字の列として読んだトークンのうち、中置演算子として使えるもの
+// -> と、match の腕を区切る | は演算子ではない
+fn is_infix_operator(tok: &str) -> bool {
+    const OPERATOR_START: &str = "$&*+-/=>@^|%<";
+    tok != "->"
+        && tok != "|"
+        && tok
+            .chars()
+            .next()
+            .is_some_and(|c| OPERATOR_START.contains(c))
+}
+
 fn build_binop(op: &str, lhs: Expression, rhs: Expression) -> Result<Expression> {
     Ok(match op {
         "+" => Expression::Plus {
@@ -94,10 +382,87 @@ fn build_binop(op: &str, lhs: Expression, rhs: Expression) -> Result<Expression>
             expression1: Box::new(lhs),
             expression2: Box::new(rhs),
         },
+        "/" => Expression::Divide {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "mod" => Expression::Modulo {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "=" => Expression::Equal {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "<>" => Expression::NotEqual {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "<=" => Expression::LessEqual {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        ">" => Expression::GreaterThan {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        ">=" => Expression::GreaterEqual {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "&&" => Expression::And {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "||" => Expression::Or {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "^" => Expression::Concat {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        "@" => Expression::Append {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        // x |> f と f @@ x はどちらも関数適用 f x として扱う
+        "|>" => Expression::App {
+            function: Box::new(rhs),
+            argument: Box::new(lhs),
+        },
+        "@@" => Expression::App {
+            function: Box::new(lhs),
+            argument: Box::new(rhs),
+        },
         "::" => Expression::Cons {
             car: Box::new(lhs),
             cdr: Box::new(rhs),
         },
+        "<-" => match lhs {
+            Expression::Field { record, label } => Expression::FieldAssignment {
+                record,
+                label,
+                value: Box::new(rhs),
+            },
+            lhs => bail!(ParseError::InvalidSyntax(format!("{} <- {}", lhs, rhs))),
+        },
+        ":=" => Expression::Assign {
+            reference: Box::new(lhs),
+            value: Box::new(rhs),
+        },
+        ";" => Expression::Sequence {
+            expression1: Box::new(lhs),
+            expression2: Box::new(rhs),
+        },
+        // 組み込みでない演算子は、その名前の変数に束縛された関数の適用として扱う
+        op if is_infix_operator(op) => Expression::App {
+            function: Box::new(Expression::App {
+                function: Box::new(Expression::Variable(op.to_string())),
+                argument: Box::new(lhs),
+            }),
+            argument: Box::new(rhs),
+        },
         _ => bail!(ParseError::InvalidSyntax(op.to_owned())),
     })
 }
@@ -106,8 +471,13 @@ fn parse_expr(tokens: &mut VecDeque<String>, min_bp: i32) -> Result<Expression>
     let mut lhs = match peek(tokens) {
         Some("if") => parse_if(tokens)?,
         Some("let") => parse_let(tokens)?,
+        Some(t) if is_binding_operator("let", t) => parse_let_operator(tokens)?,
         Some("fun") => parse_fun(tokens)?,
+        Some("function") => parse_function(tokens)?,
         Some("match") => parse_match(tokens)?,
+        Some("try") => parse_try(tokens)?,
+        Some("type") => parse_type_definition(tokens)?,
+        Some("-") => parse_negation(tokens)?,
         _ => parse_application(tokens)?,
     };
     loop {
@@ -120,6 +490,10 @@ fn parse_expr(tokens: &mut VecDeque<String>, min_bp: i32) -> Result<Expression>
             break;
         }
         let next_min = if assoc == Assoc::Left { bp + 1 } else { bp };
+        if op_s == "," {
+            lhs = parse_tuple(tokens, lhs, next_min)?;
+            continue;
+        }
         next(tokens);
         let rhs = parse_expr(tokens, next_min)?;
         lhs = build_binop(&op_s, lhs, rhs)?;
@@ -127,13 +501,40 @@ fn parse_expr(tokens: &mut VecDeque<String>, min_bp: i32) -> Result<Expression>
     Ok(lhs)
 }
 
+// 整数リテラルの符号はここで数値に含める
+fn parse_negation(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    expect(tokens, "-")?;
+    match parse_expr(tokens, NEGATION_BP)? {
+        Expression::Integer(n) => Ok(Expression::Integer(-n)),
+        operand => Ok(Expression::Negate(Box::new(operand))),
+    }
+}
+
+fn parse_tuple(
+    tokens: &mut VecDeque<String>,
+    first: Expression,
+    min_bp: i32,
+) -> Result<Expression> {
+    let mut components = vec![first];
+    while matches!(peek(tokens), Some(",")) {
+        next(tokens);
+        components.push(parse_expr(tokens, min_bp)?);
+    }
+    Ok(Expression::Tuple(components))
+}
+
 fn parse_if(tokens: &mut VecDeque<String>) -> Result<Expression> {
     expect(tokens, "if")?;
     let pred = parse_expr(tokens, 0)?;
     expect(tokens, "then")?;
-    let cons = parse_expr(tokens, 0)?;
-    expect(tokens, "else")?;
-    let alt = parse_expr(tokens, 0)?;
+    let cons = parse_expr(tokens, SEQUENCE_BP + 1)?;
+    // else を省略した if の値は ()
+    let alt = if matches!(peek(tokens), Some("else")) {
+        next(tokens);
+        parse_expr(tokens, SEQUENCE_BP + 1)?
+    } else {
+        Expression::Unit
+    };
     Ok(Expression::If {
         predicate: Box::new(pred),
         consequent: Box::new(cons),
@@ -142,112 +543,449 @@ fn parse_if(tokens: &mut VecDeque<String>) -> Result<Expression> {
 }
 
 fn parse_let(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    if tokens.get(1).is_some_and(|t| t == "open") {
+        return parse_let_open(tokens);
+    }
+    let binding = parse_let_binding(tokens)?;
+    expect(tokens, "in")?;
+    let body = parse_expr(tokens, 0)?;
+    Ok(let_expression(binding, body))
+}
+
+// let open M in e
+fn parse_let_open(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    expect(tokens, "let")?;
+    expect(tokens, "open")?;
+    let module = parse_module_path(tokens)?;
+    expect(tokens, "in")?;
+    let body = parse_expr(tokens, 0)?;
+    Ok(Expression::Open {
+        module,
+        body: Box::new(body),
+    })
+}
+
+// `let [rec] x = e` までを読む。`let rec` の右辺は関数に限る
+// 型注釈の付いた関数 (fun x -> e : t) も関数として扱う
+fn is_function(expression: &Expression) -> bool {
+    match expression {
+        Expression::Fun { .. } => true,
+        Expression::Annotation { expression, .. } => is_function(expression),
+        _ => false,
+    }
+}
+
+enum LetBinding {
+    // let rec f = e1 and g = e2
+    Recursive(Vec<(String, Expression)>),
+    // let p = e。p が変数でなければ、e の値をパターン p で分解して束縛する
+    Pattern(Pattern, Expression),
+}
+
+// let rec だけは and で複数の束縛をつなげられる。rec のない let の束縛は常に1つ
+fn parse_let_binding(tokens: &mut VecDeque<String>) -> Result<LetBinding> {
     expect(tokens, "let")?;
 
-    if matches!(peek(tokens), Some("rec")) {
+    if !matches!(peek(tokens), Some("rec")) {
+        return parse_pattern_binding(tokens);
+    }
+    next(tokens);
+
+    let mut bindings = vec![parse_binding(tokens)?];
+    while matches!(peek(tokens), Some("and")) {
         next(tokens);
+        bindings.push(parse_binding(tokens)?);
+    }
 
-        let name = next(tokens).ok_or(ParseError::Empty)?;
-        ensure!(
-            is_identifier(&name),
-            ParseError::InvalidSyntax(name.clone()).to_string()
-        );
-        expect(tokens, "=")?;
+    // let rec で束縛できるのは関数だけ
+    if !bindings.iter().all(|(_, bound)| is_function(bound)) {
+        bail!(ParseError::RecursiveNonFunction);
+    }
+    check_duplicates(
+        bindings
+            .iter()
+            .map(|(variable, _)| variable.clone())
+            .collect(),
+    )?;
 
-        expect(tokens, "fun")?;
-        let param = next(tokens).ok_or(ParseError::Empty)?;
-        ensure!(
-            is_identifier(&param),
-            ParseError::InvalidSyntax(param.clone()).to_string()
-        );
-        expect(tokens, "->")?;
-        let body_fun = parse_expr(tokens, 0)?;
-        let fun_expr = Expression::Fun {
-            parameter: param,
-            body: Box::new(body_fun),
-        };
+    Ok(LetBinding::Recursive(bindings))
+}
 
-        expect(tokens, "in")?;
-        let body = parse_expr(tokens, 0)?;
-        return Ok(Expression::LetRec {
-            variable: name,
-            bound_function: Box::new(fun_expr),
-            body: Box::new(body),
-        });
+// let (q, r) = e や let () = e、let _ = e のように変数の代わりにパターンを書ける
+fn parse_pattern_binding(tokens: &mut VecDeque<String>) -> Result<LetBinding> {
+    if !is_pattern_binding(tokens) {
+        let (variable, bound) = parse_binding(tokens)?;
+        return Ok(LetBinding::Pattern(Pattern::Variable(variable), bound));
     }
 
-    let name = next(tokens).ok_or(ParseError::Empty)?;
-    ensure!(
-        is_identifier(&name),
-        ParseError::InvalidSyntax(name.clone()).to_string()
-    );
+    let pattern = parse_pattern(tokens)?;
+    check_duplicates(pattern.variables())?;
+    let annotation = parse_annotation(tokens)?;
     expect(tokens, "=")?;
-    let bound = parse_expr(tokens, 0)?;
+    let bound = annotate(parse_expr(tokens, 0)?, annotation);
+
+    Ok(LetBinding::Pattern(pattern, bound))
+}
+
+// 関数や ( op ) の定義でなく、パターンを束縛する let か
+fn is_pattern_binding(tokens: &VecDeque<String>) -> bool {
+    match peek(tokens) {
+        Some(t) if is_identifier(t) => tokens.get(1).is_some_and(|t| t == ","),
+        Some("(") => {
+            !(tokens.get(1).is_some_and(|t| is_operator_name(t))
+                && tokens.get(2).is_some_and(|t| t == ")"))
+        }
+        _ => starts_pattern(tokens),
+    }
+}
+
+// 同じ名前を2度束縛していればエラーにする
+fn check_duplicates(mut variables: Vec<String>) -> Result<()> {
+    variables.sort();
+    if let Some(duplicated) = variables.windows(2).find(|pair| pair[0] == pair[1]) {
+        bail!(ParseError::DuplicateBinding(duplicated[0].clone()));
+    }
+    Ok(())
+}
+
+// f x y = e は f = fun x -> fun y -> e と同じ。( op ) a b = e で演算子を定義できる
+fn parse_binding(tokens: &mut VecDeque<String>) -> Result<(String, Expression)> {
+    let name = match parse_operator_name(tokens) {
+        Some(op) => op,
+        None => {
+            let name = next(tokens).ok_or(ParseError::Empty)?;
+            ensure!(
+                is_identifier(&name),
+                ParseError::InvalidSyntax(name.clone()).to_string()
+            );
+            name
+        }
+    };
+    let parameters = parse_parameters(tokens)?;
+    let annotation = parse_annotation(tokens)?;
+    expect(tokens, "=")?;
+    let bound = curry(parameters, annotate(parse_expr(tokens, 0)?, annotation));
+
+    Ok((name, bound))
+}
+
+// 関数の仮引数の並び。_ は使わない引数を表し、(x : t) で引数の型を注釈できる
+// () や (a, b) のような括弧で始まるパターンも引数に書ける
+fn parse_parameters(tokens: &mut VecDeque<String>) -> Result<Vec<(Pattern, Option<Type>)>> {
+    let mut parameters = Vec::new();
+    loop {
+        match peek(tokens) {
+            Some(t) if is_parameter(t) => {
+                parameters.push((parse_atomic_pattern(tokens)?, None));
+            }
+            Some("(")
+                if tokens.get(1).is_some_and(|t| is_parameter(t))
+                    && tokens.get(2).is_some_and(|t| t == ":") =>
+            {
+                next(tokens);
+                let parameter = parse_atomic_pattern(tokens)?;
+                expect(tokens, ":")?;
+                let annotation = parse_type(tokens)?;
+                expect(tokens, ")")?;
+                parameters.push((parameter, Some(annotation)));
+            }
+            Some("(" | "[" | "[]" | "{") => {
+                let pattern = parse_atomic_pattern(tokens)?;
+                check_duplicates(pattern.variables())?;
+                parameters.push((pattern, None));
+            }
+            _ => return Ok(parameters),
+        }
+    }
+}
+
+fn is_parameter(tok: &str) -> bool {
+    is_identifier(tok) || tok == "_"
+}
+
+// : t があれば型注釈として読む
+fn parse_annotation(tokens: &mut VecDeque<String>) -> Result<Option<Type>> {
+    if !matches!(peek(tokens), Some(":")) {
+        return Ok(None);
+    }
+    next(tokens);
+    Ok(Some(parse_type(tokens)?))
+}
+
+fn annotate(expression: Expression, annotation: Option<Type>) -> Expression {
+    match annotation {
+        Some(annotation) => Expression::Annotation {
+            expression: Box::new(expression),
+            annotation,
+        },
+        None => expression,
+    }
+}
+
+// 型注釈のある引数 (x : t) は fun x -> let x = (x : t) in ... として注釈を本体に移す
+// 変数でないパターンの引数 p は function p -> ... として match で分解する
+fn curry(parameters: Vec<(Pattern, Option<Type>)>, body: Expression) -> Expression {
+    parameters
+        .into_iter()
+        .rev()
+        .fold(body, |body, (parameter, annotation)| {
+            let parameter = match parameter {
+                Pattern::Variable(parameter) => parameter,
+                Pattern::Wildcard => "_".to_string(),
+                pattern => return match_function(vec![(pattern, body)]),
+            };
+            let body = match annotation {
+                Some(annotation) => Expression::Let {
+                    variable: parameter.clone(),
+                    bound: Box::new(annotate(
+                        Expression::Variable(parameter.clone()),
+                        Some(annotation),
+                    )),
+                    body: Box::new(body),
+                },
+                None => body,
+            };
+            Expression::Fun {
+                parameter,
+                body: Box::new(body),
+            }
+        })
+}
+
+// let p = e in body は match e with p -> body と同じ
+fn let_expression(binding: LetBinding, body: Expression) -> Expression {
+    match binding {
+        LetBinding::Recursive(bindings) => Expression::LetRec {
+            bindings,
+            body: Box::new(body),
+        },
+        LetBinding::Pattern(Pattern::Variable(variable), bound) => Expression::Let {
+            variable,
+            bound: Box::new(bound),
+            body: Box::new(body),
+        },
+        LetBinding::Pattern(pattern, bound) => Expression::Match {
+            scrutinee: Box::new(bound),
+            arms: vec![(pattern, body)],
+        },
+    }
+}
+
+// let* p1 = e1 and* p2 = e2 in e は ( let* ) (( and* ) e1 e2) (fun (p1, p2) -> e) と同じ
+fn parse_let_operator(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    let operator = next(tokens).ok_or(ParseError::Empty)?;
+    let (mut pattern, mut bound) = parse_operator_binding(tokens)?;
+    while peek(tokens).is_some_and(|t| is_binding_operator("and", t)) {
+        let and_operator = next(tokens).unwrap();
+        let (next_pattern, next_bound) = parse_operator_binding(tokens)?;
+        pattern = Pattern::Tuple(vec![pattern, next_pattern]);
+        bound = apply_operator(and_operator, bound, next_bound);
+    }
+    check_duplicates(pattern.variables())?;
     expect(tokens, "in")?;
     let body = parse_expr(tokens, 0)?;
-    Ok(Expression::Let {
-        variable: name,
-        bound: Box::new(bound),
-        body: Box::new(body),
-    })
+
+    let function = match pattern {
+        Pattern::Variable(parameter) => Expression::Fun {
+            parameter,
+            body: Box::new(body),
+        },
+        pattern => match_function(vec![(pattern, body)]),
+    };
+    Ok(apply_operator(operator, bound, function))
+}
+
+fn parse_operator_binding(tokens: &mut VecDeque<String>) -> Result<(Pattern, Expression)> {
+    let pattern = parse_pattern(tokens)?;
+    expect(tokens, "=")?;
+    let bound = parse_expr(tokens, 0)?;
+    Ok((pattern, bound))
+}
+
+// ( op ) e1 e2
+fn apply_operator(operator: String, lhs: Expression, rhs: Expression) -> Expression {
+    Expression::App {
+        function: Box::new(Expression::App {
+            function: Box::new(Expression::Variable(operator)),
+            argument: Box::new(lhs),
+        }),
+        argument: Box::new(rhs),
+    }
 }
 
 fn parse_fun(tokens: &mut VecDeque<String>) -> Result<Expression> {
     expect(tokens, "fun")?;
-    let param = next(tokens).ok_or(ParseError::Empty)?;
-    ensure!(
-        is_identifier(&param),
-        ParseError::InvalidSyntax(param.clone()).to_string()
-    );
+    let parameters = parse_parameters(tokens)?;
+    if parameters.is_empty() {
+        bail!(ParseError::Unexpected(
+            next(tokens).ok_or(ParseError::Empty)?
+        ));
+    }
     expect(tokens, "->")?;
     let body = parse_expr(tokens, 0)?;
-    Ok(Expression::Fun {
-        parameter: param,
-        body: Box::new(body),
-    })
+    Ok(curry(parameters, body))
+}
+
+// function の引数の名前。識別子として書けない名前にして、腕の中の変数と衝突しないようにする
+const FUNCTION_PARAMETER: &str = "%function";
+
+// function p1 -> e1 | ... は fun x -> match x with p1 -> e1 | ... と同じ
+fn parse_function(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    expect(tokens, "function")?;
+    let arms = parse_arms(tokens)?;
+    Ok(match_function(arms))
+}
+
+fn match_function(arms: Vec<(Pattern, Expression)>) -> Expression {
+    Expression::Fun {
+        parameter: FUNCTION_PARAMETER.to_string(),
+        body: Box::new(Expression::Match {
+            scrutinee: Box::new(Expression::Variable(FUNCTION_PARAMETER.to_string())),
+            arms,
+        }),
+    }
 }
 
 fn parse_match(tokens: &mut VecDeque<String>) -> Result<Expression> {
     expect(tokens, "match")?;
     let scrutinee = parse_expr(tokens, 0)?;
     expect(tokens, "with")?;
+    let arms = parse_arms(tokens)?;
 
-    expect(tokens, "[]")?;
-    expect(tokens, "->")?;
-    let nil_case = parse_expr(tokens, 0)?;
-    expect(tokens, "|")?;
-    let hd = next(tokens).ok_or(ParseError::Empty)?;
-    ensure!(
-        is_identifier(&hd),
-        ParseError::InvalidSyntax(hd.clone()).to_string()
-    );
-    expect(tokens, "::")?;
-    let tl = next(tokens).ok_or(ParseError::Empty)?;
-    ensure!(
-        is_identifier(&tl),
-        ParseError::InvalidSyntax(tl.clone()).to_string()
-    );
-    expect(tokens, "->")?;
-    let cons_body = parse_expr(tokens, 0)?;
     Ok(Expression::Match {
         scrutinee: Box::new(scrutinee),
-        nil_case: Box::new(nil_case),
-        cons_pattern: (hd, tl, Box::new(cons_body)),
+        arms,
     })
 }
 
-fn parse_application(tokens: &mut VecDeque<String>) -> Result<Expression> {
-    let mut func = parse_atom(tokens)?;
+fn parse_try(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    expect(tokens, "try")?;
+    let body = parse_expr(tokens, 0)?;
+    expect(tokens, "with")?;
+    let arms = parse_arms(tokens)?;
+
+    Ok(Expression::Try {
+        body: Box::new(body),
+        arms,
+    })
+}
+
+// [|] p1 -> e1 | ... | pn -> en
+fn parse_arms(tokens: &mut VecDeque<String>) -> Result<Vec<(Pattern, Expression)>> {
+    if matches!(peek(tokens), Some("|")) {
+        next(tokens);
+    }
+    let mut arms = Vec::new();
     loop {
-        match peek(tokens) {
-            Some("then" | "else" | "in" | "|" | "->" | "with") => break,
+        let pattern = parse_pattern(tokens)?;
+        check_duplicates(pattern.variables())?;
+        expect(tokens, "->")?;
+        let body = parse_expr(tokens, 0)?;
+        arms.push((pattern, body));
+
+        if !matches!(peek(tokens), Some("|")) {
+            break;
+        }
+        next(tokens);
+    }
+
+    Ok(arms)
+}
+
+fn parse_pattern(tokens: &mut VecDeque<String>) -> Result<Pattern> {
+    let first = parse_cons_pattern(tokens)?;
+    if !matches!(peek(tokens), Some(",")) {
+        return Ok(first);
+    }
+
+    let mut components = vec![first];
+    while matches!(peek(tokens), Some(",")) {
+        next(tokens);
+        components.push(parse_cons_pattern(tokens)?);
+    }
+    Ok(Pattern::Tuple(components))
+}
+
+fn parse_cons_pattern(tokens: &mut VecDeque<String>) -> Result<Pattern> {
+    let car = parse_atomic_pattern(tokens)?;
+    if !matches!(peek(tokens), Some("::")) {
+        return Ok(car);
+    }
+
+    next(tokens);
+    let cdr = parse_cons_pattern(tokens)?;
+    Ok(Pattern::Cons {
+        car: Box::new(car),
+        cdr: Box::new(cdr),
+    })
+}
+
+fn parse_atomic_pattern(tokens: &mut VecDeque<String>) -> Result<Pattern> {
+    match next(tokens).ok_or(ParseError::Empty)? {
+        t if t == "_" => Ok(Pattern::Wildcard),
+        t if t.parse::<isize>().is_ok() => Ok(Pattern::Integer(t.parse::<isize>().unwrap())),
+        t if t == "-" && peek(tokens).is_some_and(|s| s.parse::<isize>().is_ok()) => {
+            let n = next(tokens).unwrap().parse::<isize>().unwrap();
+            Ok(Pattern::Integer(-n))
+        }
+        t if string_literal(&t).is_some() => Ok(Pattern::String(string_literal(&t).unwrap())),
+        t if char_literal(&t).is_some() => Ok(Pattern::Char(char_literal(&t).unwrap())),
+        t if t == "true" => Ok(Pattern::Bool(true)),
+        t if t == "false" => Ok(Pattern::Bool(false)),
+        t if t == "[]" => Ok(Pattern::Nil),
+        t if t == "[" => parse_list(tokens, parse_pattern, Pattern::Nil, |car, cdr| {
+            Pattern::Cons {
+                car: Box::new(car),
+                cdr: Box::new(cdr),
+            }
+        }),
+        t if t == "(" && matches!(peek(tokens), Some(")")) => {
+            next(tokens);
+            Ok(Pattern::Unit)
+        }
+        t if t == "(" => {
+            let pattern = parse_pattern(tokens)?;
+            match next(tokens) {
+                Some(s) if s == ")" => Ok(pattern),
+                Some(s) => bail!(ParseError::Unexpected(s)),
+                None => bail!(ParseError::Unclosed),
+            }
+        }
+        t if t == "{" => {
+            let fields = parse_fields(tokens, true, parse_pattern, Pattern::Variable)?;
+            Ok(Pattern::Record(fields))
+        }
+        t if is_identifier(&t) => Ok(Pattern::Variable(t)),
+        t if is_constructor(&t) => {
+            let t = parse_path(tokens, t);
+            ensure!(
+                is_constructor(base_name(&t)),
+                ParseError::InvalidSyntax(t.clone()).to_string()
+            );
+            let argument = if starts_pattern(tokens) {
+                Some(Box::new(parse_atomic_pattern(tokens)?))
+            } else {
+                None
+            };
+            Ok(Pattern::Constructor { name: t, argument })
+        }
+        other => bail!(ParseError::Unexpected(other)),
+    }
+}
+
+fn parse_application(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    let mut func = parse_atom(tokens, true)?;
+    loop {
+        match peek(tokens) {
+            Some("then" | "else" | "in" | "|" | "->" | "with") => break,
             Some(op) if precedence(op).is_some() => break,
             None => break,
             _ => {
                 if !starts_primary(tokens) {
                     break;
                 }
-                let arg = parse_atom(tokens)?;
+                // 引数の位置の構築子は引数をとらない。g A 1 は g に A と 1 を渡す
+                let arg = parse_atom(tokens, false)?;
                 func = Expression::App {
                     function: Box::new(func),
                     argument: Box::new(arg),
@@ -258,13 +996,42 @@ fn parse_application(tokens: &mut VecDeque<String>) -> Result<Expression> {
     Ok(func)
 }
 
-fn parse_atom(tokens: &mut VecDeque<String>) -> Result<Expression> {
+// constructor_argument が false なら、構築子は後ろの式を引数にとらない
+fn parse_atom(tokens: &mut VecDeque<String>, constructor_argument: bool) -> Result<Expression> {
+    let mut atom = parse_primary(tokens, constructor_argument)?;
+    while matches!(peek(tokens), Some(".")) {
+        next(tokens);
+        let label = next(tokens).ok_or(ParseError::Empty)?;
+        ensure!(
+            is_identifier(&label),
+            ParseError::InvalidSyntax(label.clone()).to_string()
+        );
+        atom = Expression::Field {
+            record: Box::new(atom),
+            label,
+        };
+    }
+    Ok(atom)
+}
+
+fn parse_primary(tokens: &mut VecDeque<String>, constructor_argument: bool) -> Result<Expression> {
+    if let Some(op) = parse_operator_name(tokens) {
+        return Ok(Expression::Variable(op));
+    }
+
     match next(tokens).ok_or(ParseError::Empty)? {
         t if t.parse::<isize>().is_ok() => Ok(Expression::Integer(t.parse::<isize>().unwrap())),
+        t if string_literal(&t).is_some() => Ok(Expression::String(string_literal(&t).unwrap())),
+        t if char_literal(&t).is_some() => Ok(Expression::Char(char_literal(&t).unwrap())),
         t if t == "true" => Ok(Expression::Bool(true)),
         t if t == "false" => Ok(Expression::Bool(false)),
+        t if t == "(" && matches!(peek(tokens), Some(")")) => {
+            next(tokens);
+            Ok(Expression::Unit)
+        }
         t if t == "(" => {
             let e = parse_expr(tokens, 0)?;
+            let e = annotate(e, parse_annotation(tokens)?);
             match next(tokens) {
                 Some(s) if s == ")" => Ok(e),
                 Some(s) => bail!(ParseError::Unexpected(s)),
@@ -272,7 +1039,1122 @@ fn parse_atom(tokens: &mut VecDeque<String>) -> Result<Expression> {
             }
         }
         t if t == "[]" => Ok(Expression::Nil),
+        t if t == "[" => parse_list(
+            tokens,
+            |tokens| parse_expr(tokens, SEQUENCE_BP + 1),
+            Expression::Nil,
+            |car, cdr| Expression::Cons {
+                car: Box::new(car),
+                cdr: Box::new(cdr),
+            },
+        ),
+        t if t == "{" => parse_record(tokens),
+        // ref e は構築子と同じく引数を1つとる。!r はどの演算子よりも強く結合する
+        t if t == "ref" => Ok(Expression::Ref(Box::new(parse_atom(tokens, true)?))),
+        t if t == "!" => Ok(Expression::Deref(Box::new(parse_primary(tokens, true)?))),
+        t if t == "raise" => Ok(Expression::Raise(Box::new(parse_atom(tokens, true)?))),
+        t if t == "assert" => Ok(Expression::Assert(Box::new(parse_atom(tokens, true)?))),
         t if is_identifier(&t) => Ok(Expression::Variable(t)),
+        t if is_constructor(&t) => {
+            // M.x や M.( + ) はモジュールの値、M.C はモジュールの構築子
+            let t = parse_path(tokens, t);
+            if !is_constructor(base_name(&t)) {
+                return Ok(Expression::Variable(t));
+            }
+            let argument = if constructor_argument && starts_primary(tokens) {
+                Some(Box::new(parse_atom(tokens, true)?))
+            } else {
+                None
+            };
+            Ok(Expression::Constructor { name: t, argument })
+        }
         other => bail!(ParseError::Unexpected(other)),
     }
 }
+
+// [e1; e2; ...] は e1 :: e2 :: ... :: [] と同じ。最後の ; は省略できる
+fn parse_list<T>(
+    tokens: &mut VecDeque<String>,
+    parse_element: fn(&mut VecDeque<String>) -> Result<T>,
+    nil: T,
+    cons: fn(T, T) -> T,
+) -> Result<T> {
+    let mut elements = Vec::new();
+    while !matches!(peek(tokens), Some("]")) {
+        elements.push(parse_element(tokens)?);
+        match peek(tokens) {
+            Some(";") => {
+                next(tokens);
+            }
+            Some("]") => {}
+            Some(s) => bail!(ParseError::Unexpected(s.to_string())),
+            None => bail!(ParseError::Unclosed),
+        }
+    }
+    expect(tokens, "]")?;
+
+    Ok(elements
+        .into_iter()
+        .rev()
+        .fold(nil, |cdr, car| cons(car, cdr)))
+}
+
+// { l1 = e1; ... } または { e with l1 = e1; ... } を、開き括弧の後から読む
+fn parse_record(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    let is_literal = matches!(tokens.get(1).map(String::as_str), Some("=" | ";" | "}"));
+    if is_literal {
+        let fields = parse_fields(
+            tokens,
+            false,
+            |tokens| parse_expr(tokens, SEQUENCE_BP + 1),
+            Expression::Variable,
+        )?;
+        return Ok(Expression::Record(fields));
+    }
+
+    let record = parse_atom(tokens, true)?;
+    expect(tokens, "with")?;
+    let fields = parse_fields(
+        tokens,
+        false,
+        |tokens| parse_expr(tokens, SEQUENCE_BP + 1),
+        Expression::Variable,
+    )?;
+    Ok(Expression::RecordUpdate {
+        record: Box::new(record),
+        fields,
+    })
+}
+
+// `l1 = v1; ...; ln = vn }` を読む。`= v` を省略するとラベルと同名の変数を値にする
+// パターンでは最後に `_` を書いて残りのフィールドを省略したことを明示できる
+fn parse_fields<T>(
+    tokens: &mut VecDeque<String>,
+    allow_rest: bool,
+    parse_value: impl Fn(&mut VecDeque<String>) -> Result<T>,
+    punned: impl Fn(String) -> T,
+) -> Result<Vec<(String, T)>> {
+    let mut fields = Vec::new();
+    loop {
+        let label = next(tokens).ok_or(ParseError::Unclosed)?;
+        if allow_rest && label == "_" && !fields.is_empty() {
+            if matches!(peek(tokens), Some(";")) {
+                next(tokens);
+            }
+            expect(tokens, "}")?;
+            return Ok(fields);
+        }
+        ensure!(
+            is_identifier(&label),
+            ParseError::InvalidSyntax(label.clone()).to_string()
+        );
+
+        let value = if matches!(peek(tokens), Some("=")) {
+            next(tokens);
+            parse_value(tokens)?
+        } else {
+            punned(label.clone())
+        };
+        fields.push((label, value));
+
+        if close_fields(tokens)? {
+            return Ok(fields);
+        }
+    }
+}
+
+fn parse_type_definition(tokens: &mut VecDeque<String>) -> Result<Expression> {
+    let definitions = parse_type_bindings(tokens)?;
+    expect(tokens, "in")?;
+    let body = parse_expr(tokens, 0)?;
+    Ok(Expression::TypeDefinition {
+        definitions,
+        body: Box::new(body),
+    })
+}
+
+fn parse_type_bindings(tokens: &mut VecDeque<String>) -> Result<Vec<TypeDefinition>> {
+    expect(tokens, "type")?;
+    let mut definitions = vec![parse_type_binding(tokens)?];
+    while matches!(peek(tokens), Some("and")) {
+        next(tokens);
+        definitions.push(parse_type_binding(tokens)?);
+    }
+    Ok(definitions)
+}
+
+fn parse_type_binding(tokens: &mut VecDeque<String>) -> Result<TypeDefinition> {
+    let parameters = parse_type_parameters(tokens)?;
+    let name = next(tokens).ok_or(ParseError::Empty)?;
+    ensure!(
+        is_identifier(&name),
+        ParseError::InvalidSyntax(name.clone()).to_string()
+    );
+    // = 以降を省略した型は抽象型
+    if !matches!(peek(tokens), Some("=")) {
+        return Ok(TypeDefinition {
+            name,
+            parameters,
+            kind: TypeKind::Abstract,
+        });
+    }
+    expect(tokens, "=")?;
+
+    if matches!(peek(tokens), Some("{")) {
+        next(tokens);
+        let fields = parse_record_fields(tokens)?;
+        return Ok(TypeDefinition {
+            name,
+            parameters,
+            kind: TypeKind::Record(fields),
+        });
+    }
+
+    // 構築子で始まらなければ型の別名。M.t のような経路は構築子と区別する
+    let starts_variant = match peek(tokens) {
+        Some("|") => true,
+        Some(t) => is_constructor(t) && tokens.get(1).is_none_or(|t| t != "."),
+        None => false,
+    };
+    if !starts_variant {
+        return Ok(TypeDefinition {
+            name,
+            parameters,
+            kind: TypeKind::Alias(parse_type(tokens)?),
+        });
+    }
+
+    if matches!(peek(tokens), Some("|")) {
+        next(tokens);
+    }
+    let mut constructors = Vec::new();
+    loop {
+        constructors.push(parse_constructor_declaration(tokens)?);
+
+        if !matches!(peek(tokens), Some("|")) {
+            break;
+        }
+        next(tokens);
+    }
+
+    Ok(TypeDefinition {
+        name,
+        parameters,
+        kind: TypeKind::Variant(constructors),
+    })
+}
+
+// C または C of t
+fn parse_constructor_declaration(tokens: &mut VecDeque<String>) -> Result<(String, Option<Type>)> {
+    let constructor = next(tokens).ok_or(ParseError::Empty)?;
+    ensure!(
+        is_constructor(&constructor),
+        ParseError::InvalidSyntax(constructor.clone()).to_string()
+    );
+    let argument_type = if matches!(peek(tokens), Some("of")) {
+        next(tokens);
+        Some(parse_type(tokens)?)
+    } else {
+        None
+    };
+
+    Ok((constructor, argument_type))
+}
+
+// フィールドの後の `;` と `}` を読み、フィールドの列が閉じたかどうかを返す
+fn close_fields(tokens: &mut VecDeque<String>) -> Result<bool> {
+    match next(tokens) {
+        Some(s) if s == "}" => Ok(true),
+        Some(s) if s == ";" => {
+            let closed = matches!(peek(tokens), Some("}"));
+            if closed {
+                next(tokens);
+            }
+            Ok(closed)
+        }
+        Some(s) => bail!(ParseError::Unexpected(s)),
+        None => bail!(ParseError::Unclosed),
+    }
+}
+
+// `[mutable] l1 : t1; ... }` を読む
+fn parse_record_fields(tokens: &mut VecDeque<String>) -> Result<Vec<RecordField>> {
+    let mut fields = Vec::new();
+    loop {
+        let mutable = matches!(peek(tokens), Some("mutable"));
+        if mutable {
+            next(tokens);
+        }
+        let label = next(tokens).ok_or(ParseError::Unclosed)?;
+        ensure!(
+            is_identifier(&label),
+            ParseError::InvalidSyntax(label.clone()).to_string()
+        );
+        expect(tokens, ":")?;
+        let field_type = parse_type(tokens)?;
+        fields.push(RecordField {
+            label,
+            mutable,
+            field_type,
+        });
+
+        if close_fields(tokens)? {
+            return Ok(fields);
+        }
+    }
+}
+
+// 'a または ('a, 'b, ...)
+fn parse_type_parameters(tokens: &mut VecDeque<String>) -> Result<Vec<String>> {
+    let parameter = |tokens: &mut VecDeque<String>| {
+        let parameter = next(tokens).ok_or(ParseError::Empty)?;
+        ensure!(
+            is_type_variable(&parameter),
+            ParseError::InvalidSyntax(parameter.clone()).to_string()
+        );
+        Ok(parameter)
+    };
+
+    match peek(tokens) {
+        Some(s) if is_type_variable(s) => Ok(vec![parameter(tokens)?]),
+        Some("(") => {
+            next(tokens);
+            let mut parameters = vec![parameter(tokens)?];
+            while matches!(peek(tokens), Some(",")) {
+                next(tokens);
+                parameters.push(parameter(tokens)?);
+            }
+            expect(tokens, ")")?;
+
+            let mut seen = HashSet::new();
+            for parameter in &parameters {
+                ensure!(
+                    seen.insert(parameter),
+                    ParseError::DuplicateTypeParameter(parameter.clone())
+                );
+            }
+            Ok(parameters)
+        }
+        _ => Ok(vec![]),
+    }
+}
+
+fn parse_type(tokens: &mut VecDeque<String>) -> Result<Type> {
+    let domain = parse_tuple_type(tokens)?;
+    if !matches!(peek(tokens), Some("->")) {
+        return Ok(domain);
+    }
+
+    next(tokens);
+    let range = parse_type(tokens)?;
+    Ok(Type::Function {
+        domain: domain.into(),
+        range: range.into(),
+    })
+}
+
+fn parse_tuple_type(tokens: &mut VecDeque<String>) -> Result<Type> {
+    let first = parse_applied_type(tokens)?;
+    if !matches!(peek(tokens), Some("*")) {
+        return Ok(first);
+    }
+
+    let mut components = vec![first];
+    while matches!(peek(tokens), Some("*")) {
+        next(tokens);
+        components.push(parse_applied_type(tokens)?);
+    }
+    Ok(Type::Tuple(components))
+}
+
+fn parse_applied_type(tokens: &mut VecDeque<String>) -> Result<Type> {
+    let mut args = parse_type_arguments(tokens)?;
+    while starts_type_name(tokens) {
+        let name = parse_type_name(tokens)?;
+        args = vec![named_type(name, args)];
+    }
+
+    match <[Type; 1]>::try_from(args) {
+        Ok([t]) => Ok(t),
+        Err(args) => bail!(ParseError::InvalidSyntax(
+            args.iter()
+                .map(Type::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+// 型構築子に与える引数の列。 (t1, t2) のように複数書ける
+fn parse_type_arguments(tokens: &mut VecDeque<String>) -> Result<Vec<Type>> {
+    match next(tokens).ok_or(ParseError::Empty)? {
+        t if t == "(" => {
+            let mut args = vec![parse_type(tokens)?];
+            while matches!(peek(tokens), Some(",")) {
+                next(tokens);
+                args.push(parse_type(tokens)?);
+            }
+            match next(tokens) {
+                Some(s) if s == ")" => Ok(args),
+                Some(s) => bail!(ParseError::Unexpected(s)),
+                None => bail!(ParseError::Unclosed),
+            }
+        }
+        t if is_identifier(&t) => Ok(vec![named_type(t, vec![])]),
+        t if is_constructor(&t) => {
+            tokens.push_front(t);
+            Ok(vec![named_type(parse_type_name(tokens)?, vec![])])
+        }
+        t if is_type_variable(&t) => Ok(vec![Type::Variable { name: t }]),
+        other => bail!(ParseError::Unexpected(other)),
+    }
+}
+
+fn starts_type_name(tokens: &VecDeque<String>) -> bool {
+    match peek(tokens) {
+        Some(t) if is_identifier(t) || t == "ref" => true,
+        Some(t) if is_constructor(t) => tokens.get(1).is_some_and(|t| t == "."),
+        _ => false,
+    }
+}
+
+// t または M.t のような型の名前。ref はキーワードだが型の名前には使える
+fn parse_type_name(tokens: &mut VecDeque<String>) -> Result<String> {
+    let first = next(tokens).ok_or(ParseError::Empty)?;
+    if first == "ref" {
+        return Ok(first);
+    }
+    let name = parse_path(tokens, first);
+    ensure!(
+        is_identifier(base_name(&name)),
+        ParseError::InvalidSyntax(name.clone()).to_string()
+    );
+    Ok(name)
+}
+
+fn named_type(name: String, mut args: Vec<Type>) -> Type {
+    match (name.as_str(), args.len()) {
+        ("int", 0) => Type::Base(BaseType::Integer),
+        ("bool", 0) => Type::Base(BaseType::Bool),
+        ("string", 0) => Type::Base(BaseType::String),
+        ("char", 0) => Type::Base(BaseType::Char),
+        ("unit", 0) => Type::Base(BaseType::Unit),
+        ("list", 1) => Type::List(args.remove(0).into()),
+        ("ref", 1) => Type::Ref(args.remove(0).into()),
+        _ => Type::Constructor { name, args },
+    }
+}
+
+fn parse_module_name(tokens: &mut VecDeque<String>) -> Result<String> {
+    let name = next(tokens).ok_or(ParseError::Empty)?;
+    ensure!(
+        is_constructor(&name),
+        ParseError::InvalidSyntax(name.clone()).to_string()
+    );
+    Ok(name)
+}
+
+// M または M.N のようなモジュールの経路
+fn parse_module_path(tokens: &mut VecDeque<String>) -> Result<String> {
+    let first = parse_module_name(tokens)?;
+    let path = parse_path(tokens, first);
+    ensure!(
+        is_constructor(base_name(&path)),
+        ParseError::InvalidSyntax(path.clone()).to_string()
+    );
+    Ok(path)
+}
+
+// module M : S = m は module M = (m : S) と同じ
+fn ascribe(module: ModuleExpression, module_type: Option<ModuleType>) -> ModuleExpression {
+    match module_type {
+        Some(module_type) => ModuleExpression::Ascription {
+            module: Box::new(module),
+            module_type,
+        },
+        None => module,
+    }
+}
+
+// (X : S) (Y : T) ... を読む
+fn parse_functor_parameters(tokens: &mut VecDeque<String>) -> Result<Vec<(String, ModuleType)>> {
+    let mut parameters = Vec::new();
+    while matches!(peek(tokens), Some("(")) {
+        next(tokens);
+        let parameter = parse_module_name(tokens)?;
+        expect(tokens, ":")?;
+        let parameter_type = parse_module_type(tokens)?;
+        expect(tokens, ")")?;
+        parameters.push((parameter, parameter_type));
+    }
+    Ok(parameters)
+}
+
+// module F (X : S) (Y : T) = m は module F = functor (X : S) -> functor (Y : T) -> m と同じ
+fn functor(parameters: Vec<(String, ModuleType)>, body: ModuleExpression) -> ModuleExpression {
+    parameters
+        .into_iter()
+        .rev()
+        .fold(body, |body, (parameter, parameter_type)| {
+            ModuleExpression::Functor {
+                parameter,
+                parameter_type,
+                body: Box::new(body),
+            }
+        })
+}
+
+// F (A) (B) は左から順に適用する
+fn parse_module_expression(tokens: &mut VecDeque<String>) -> Result<ModuleExpression> {
+    if matches!(peek(tokens), Some("functor")) {
+        next(tokens);
+        let parameters = parse_functor_parameters(tokens)?;
+        ensure!(
+            !parameters.is_empty(),
+            ParseError::InvalidSyntax("functor".to_string()).to_string()
+        );
+        expect(tokens, "->")?;
+        let body = parse_module_expression(tokens)?;
+        return Ok(functor(parameters, body));
+    }
+
+    let mut module = parse_atomic_module_expression(tokens)?;
+    while matches!(peek(tokens), Some("(")) {
+        let argument = parse_atomic_module_expression(tokens)?;
+        module = ModuleExpression::Application {
+            functor: Box::new(module),
+            argument: Box::new(argument),
+        };
+    }
+    Ok(module)
+}
+
+fn parse_atomic_module_expression(tokens: &mut VecDeque<String>) -> Result<ModuleExpression> {
+    match peek(tokens) {
+        Some("struct") => {
+            next(tokens);
+            let top_levels = parse_top_levels(tokens)?;
+            expect(tokens, "end")?;
+            Ok(ModuleExpression::Structure(top_levels))
+        }
+        Some("(") => {
+            next(tokens);
+            let module = parse_module_expression(tokens)?;
+            let module_type = if matches!(peek(tokens), Some(":")) {
+                next(tokens);
+                Some(parse_module_type(tokens)?)
+            } else {
+                None
+            };
+            expect(tokens, ")")?;
+            Ok(ascribe(module, module_type))
+        }
+        _ => Ok(ModuleExpression::Path(parse_module_path(tokens)?)),
+    }
+}
+
+// S with type t = u and type ... の制約は左から順に当てはめる
+fn parse_module_type(tokens: &mut VecDeque<String>) -> Result<ModuleType> {
+    let module_type = parse_atomic_module_type(tokens)?;
+    if !matches!(peek(tokens), Some("with")) {
+        return Ok(module_type);
+    }
+    next(tokens);
+
+    let mut constraints = vec![parse_type_constraint(tokens)?];
+    while matches!(peek(tokens), Some("and")) {
+        next(tokens);
+        constraints.push(parse_type_constraint(tokens)?);
+    }
+    Ok(ModuleType::With {
+        module_type: Box::new(module_type),
+        constraints,
+    })
+}
+
+// type ('a, ...) t = 型式
+fn parse_type_constraint(tokens: &mut VecDeque<String>) -> Result<TypeDefinition> {
+    expect(tokens, "type")?;
+    let parameters = parse_type_parameters(tokens)?;
+    let name = next(tokens).ok_or(ParseError::Empty)?;
+    ensure!(
+        is_identifier(&name),
+        ParseError::InvalidSyntax(name.clone()).to_string()
+    );
+    expect(tokens, "=")?;
+    Ok(TypeDefinition {
+        name,
+        parameters,
+        kind: TypeKind::Alias(parse_type(tokens)?),
+    })
+}
+
+fn parse_atomic_module_type(tokens: &mut VecDeque<String>) -> Result<ModuleType> {
+    match peek(tokens) {
+        Some("sig") => {
+            next(tokens);
+            let mut specifications = Vec::new();
+            while !matches!(peek(tokens), Some("end")) {
+                specifications.push(parse_specification(tokens)?);
+            }
+            expect(tokens, "end")?;
+            Ok(ModuleType::Signature(specifications))
+        }
+        Some("(") => {
+            next(tokens);
+            let module_type = parse_module_type(tokens)?;
+            expect(tokens, ")")?;
+            Ok(module_type)
+        }
+        _ => Ok(ModuleType::Path(parse_module_path(tokens)?)),
+    }
+}
+
+// val x : t、type t [= ...]、exception E [of t]
+fn parse_specification(tokens: &mut VecDeque<String>) -> Result<Specification> {
+    match peek(tokens) {
+        Some("val") => {
+            next(tokens);
+            let name = match parse_operator_name(tokens) {
+                Some(op) => op,
+                None => {
+                    let name = next(tokens).ok_or(ParseError::Unclosed)?;
+                    ensure!(
+                        is_identifier(&name),
+                        ParseError::InvalidSyntax(name.clone()).to_string()
+                    );
+                    name
+                }
+            };
+            expect(tokens, ":")?;
+            let value_type = parse_type(tokens)?;
+            Ok(Specification::Value { name, value_type })
+        }
+        Some("type") => Ok(Specification::Type(parse_type_bindings(tokens)?)),
+        Some("exception") => {
+            next(tokens);
+            let (name, argument) = parse_constructor_declaration(tokens)?;
+            Ok(Specification::Exception { name, argument })
+        }
+        Some(t) => bail!(ParseError::Unexpected(t.to_string())),
+        None => bail!(ParseError::Unclosed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::tokenizer::tokenize;
+
+    fn parse_source(source: &str) -> Result<Program> {
+        parse_program(tokenize(source.to_string())?)
+    }
+
+    fn variable(name: &str) -> Expression {
+        Expression::Variable(name.to_string())
+    }
+
+    fn parse_expression(source: &str) -> Expression {
+        parse(tokenize(source.to_string()).unwrap()).unwrap()
+    }
+
+    // 演算子の式 e1 op e2 を、( op ) e1 e2 の関数適用として作る
+    fn operator(op: &str, lhs: Expression, rhs: Expression) -> Expression {
+        Expression::App {
+            function: Expression::App {
+                function: variable(op).into(),
+                argument: lhs.into(),
+            }
+            .into(),
+            argument: rhs.into(),
+        }
+    }
+
+    #[test]
+    fn test_parse_ref_type_annotation() {
+        let result = parse_source("let r : int ref = ref 1");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap().top_levels,
+            vec![TopLevel::Let {
+                variable: "r".to_string(),
+                bound: Expression::Annotation {
+                    expression: Expression::Ref(Expression::Integer(1).into()).into(),
+                    annotation: Type::Ref(Type::Base(BaseType::Integer).into()),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_polymorphic_ref_parameter() {
+        let result = parse_source("let get (r : 'a ref) = !r");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap().top_levels,
+            vec![TopLevel::Let {
+                variable: "get".to_string(),
+                bound: Expression::Fun {
+                    parameter: "r".to_string(),
+                    body: Expression::Let {
+                        variable: "r".to_string(),
+                        bound: Expression::Annotation {
+                            expression: variable("r").into(),
+                            annotation: Type::Ref(
+                                Type::Variable {
+                                    name: "'a".to_string(),
+                                }
+                                .into()
+                            ),
+                        }
+                        .into(),
+                        body: Expression::Deref(variable("r").into()).into(),
+                    }
+                    .into(),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_ref_record_field() {
+        let result = parse_source("type t = { r : int list ref }");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap().top_levels,
+            vec![TopLevel::TypeDefinition(vec![TypeDefinition {
+                name: "t".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Record(vec![RecordField {
+                    label: "r".to_string(),
+                    mutable: false,
+                    field_type: Type::Ref(Type::List(Type::Base(BaseType::Integer).into()).into()),
+                }]),
+            }])]
+        );
+    }
+
+    #[test]
+    fn test_parse_annotated_let_rec() {
+        let result = parse_source("let rec f : int -> int = fun n -> f n");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap().top_levels,
+            vec![TopLevel::LetRec {
+                bindings: vec![(
+                    "f".to_string(),
+                    Expression::Annotation {
+                        expression: Expression::Fun {
+                            parameter: "n".to_string(),
+                            body: Expression::App {
+                                function: variable("f").into(),
+                                argument: variable("n").into(),
+                            }
+                            .into(),
+                        }
+                        .into(),
+                        annotation: Type::Function {
+                            domain: Type::Base(BaseType::Integer).into(),
+                            range: Type::Base(BaseType::Integer).into(),
+                        },
+                    },
+                )],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_let_rec_non_function() {
+        let result = parse_source("let rec x : int = 1");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_let_with_tuple_pattern() {
+        let result = parse(tokenize("let (q, r) = x in q".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Expression::Match {
+                scrutinee: variable("x").into(),
+                arms: vec![(
+                    Pattern::Tuple(vec![
+                        Pattern::Variable("q".to_string()),
+                        Pattern::Variable("r".to_string()),
+                    ]),
+                    variable("q"),
+                )],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_top_level_let_with_pattern() {
+        let result = parse_source("let () = f ();; let _ = x;; let a, b = x");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap().top_levels,
+            vec![
+                TopLevel::LetPattern {
+                    pattern: Pattern::Unit,
+                    bound: Expression::App {
+                        function: variable("f").into(),
+                        argument: Expression::Unit.into(),
+                    },
+                },
+                TopLevel::LetPattern {
+                    pattern: Pattern::Wildcard,
+                    bound: variable("x"),
+                },
+                TopLevel::LetPattern {
+                    pattern: Pattern::Tuple(vec![
+                        Pattern::Variable("a".to_string()),
+                        Pattern::Variable("b".to_string()),
+                    ]),
+                    bound: variable("x"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_let_operator_definition_is_not_pattern() {
+        let result = parse_source("let ( +++ ) a b = a");
+
+        assert!(result.is_ok());
+        assert!(matches!(
+            result.unwrap().top_levels.as_slice(),
+            [TopLevel::Let { variable, .. }] if variable == "+++"
+        ));
+    }
+
+    #[test]
+    fn test_parse_constructor_in_argument_position() {
+        let cases = [
+            ("g A 1", "(app (app g A) 1)"),
+            ("Option.value None 3", "(app (app Option.value None) 3)"),
+            (
+                "List.fold_left (fun acc x -> Some x) None [1; 2]",
+                "(app (app (app List.fold_left (fun acc (fun x (Some x)))) None) (cons 1 (cons 2 nil)))",
+            ),
+            ("Some (A 1)", "(Some (A 1))"),
+            ("f (Some 1) B", "(app (app f (Some 1)) B)"),
+        ];
+
+        for (source, expected) in cases {
+            let result = parse(tokenize(source.to_string()).unwrap());
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_parse_curried_fun() {
+        let result = parse(tokenize("fun x y z -> x".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Expression::Fun {
+                parameter: "x".to_string(),
+                body: Expression::Fun {
+                    parameter: "y".to_string(),
+                    body: Expression::Fun {
+                        parameter: "z".to_string(),
+                        body: variable("x").into(),
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_let_with_parameters() {
+        let result = parse(tokenize("let f x y = x in f".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Expression::Let {
+                variable: "f".to_string(),
+                bound: Expression::Fun {
+                    parameter: "x".to_string(),
+                    body: Expression::Fun {
+                        parameter: "y".to_string(),
+                        body: variable("x").into(),
+                    }
+                    .into(),
+                }
+                .into(),
+                body: variable("f").into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_let_rec_with_parameters() {
+        let result = parse(tokenize("let rec f x y = f y x in f".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Expression::LetRec {
+                bindings: vec![(
+                    "f".to_string(),
+                    Expression::Fun {
+                        parameter: "x".to_string(),
+                        body: Expression::Fun {
+                            parameter: "y".to_string(),
+                            body: Expression::App {
+                                function: Expression::App {
+                                    function: variable("f").into(),
+                                    argument: variable("y").into(),
+                                }
+                                .into(),
+                                argument: variable("x").into(),
+                            }
+                            .into(),
+                        }
+                        .into(),
+                    },
+                )],
+                body: variable("f").into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_function() {
+        let result = parse(tokenize("function 0 -> 1 | _ -> 2".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            match_function(vec![
+                (Pattern::Integer(0), Expression::Integer(1)),
+                (Pattern::Wildcard, Expression::Integer(2)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_pattern_parameters() {
+        let result = parse(tokenize("fun () (a, b) -> a".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            match_function(vec![(
+                Pattern::Unit,
+                match_function(vec![(
+                    Pattern::Tuple(vec![
+                        Pattern::Variable("a".to_string()),
+                        Pattern::Variable("b".to_string()),
+                    ]),
+                    variable("a"),
+                )]),
+            )])
+        );
+    }
+
+    #[test]
+    fn test_parse_duplicate_pattern_parameter() {
+        let result = parse(tokenize("fun (a, a) -> a".to_string()).unwrap());
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_pipeline_is_left_associative() {
+        let result = parse(tokenize("x |> f |> g".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Expression::App {
+                function: variable("g").into(),
+                argument: Expression::App {
+                    function: variable("f").into(),
+                    argument: variable("x").into(),
+                }
+                .into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_application_operator_is_right_associative() {
+        let result = parse(tokenize("f @@ g @@ h x".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Expression::App {
+                function: variable("f").into(),
+                argument: Expression::App {
+                    function: variable("g").into(),
+                    argument: Expression::App {
+                        function: variable("h").into(),
+                        argument: variable("x").into(),
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_let_operator_with_three_bindings() {
+        let source = "let* a = x and* b = y and* c = z in a";
+
+        let result = parse(tokenize(source.to_string()).unwrap());
+
+        // ( let* ) (( and* ) (( and* ) x y) z) (fun ((a, b), c) -> a)
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            apply_operator(
+                "let*".to_string(),
+                apply_operator(
+                    "and*".to_string(),
+                    apply_operator("and*".to_string(), variable("x"), variable("y")),
+                    variable("z"),
+                ),
+                match_function(vec![(
+                    Pattern::Tuple(vec![
+                        Pattern::Tuple(vec![
+                            Pattern::Variable("a".to_string()),
+                            Pattern::Variable("b".to_string()),
+                        ]),
+                        Pattern::Variable("c".to_string()),
+                    ]),
+                    variable("a"),
+                )]),
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_let_operator_with_variable() {
+        let result = parse(tokenize("let+ a = x in a".to_string()).unwrap());
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            apply_operator(
+                "let+".to_string(),
+                variable("x"),
+                Expression::Fun {
+                    parameter: "a".to_string(),
+                    body: variable("a").into(),
+                },
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_user_defined_operator() {
+        assert_eq!(
+            parse_expression("a +++ b"),
+            operator("+++", variable("a"), variable("b"))
+        );
+        assert_eq!(
+            parse_expression("a =. b"),
+            operator("=.", variable("a"), variable("b"))
+        );
+    }
+
+    #[test]
+    fn test_parse_operator_precedence_by_first_character() {
+        // 定義した演算子の結合の強さと結合性は、先頭の文字が同じ組み込みの演算子と同じ
+        let cases = [
+            ("a ** b ** c", "a ** (b ** c)"),
+            ("a **. b * c", "(a **. b) * c"),
+            ("a * b **. c", "a * (b **. c)"),
+            ("a *. b *. c", "(a *. b) *. c"),
+            ("a +. b *. c", "a +. (b *. c)"),
+            ("a %. b + c", "(a %. b) + c"),
+            ("a -. b -. c", "(a -. b) -. c"),
+            ("a @. b @. c", "a @. (b @. c)"),
+            ("a ^^ b + c", "a ^^ (b + c)"),
+            ("a ^^ b = c", "(a ^^ b) = c"),
+            ("a :: b @. c", "(a :: b) @. c"),
+            ("a @. b :: c", "a @. (b :: c)"),
+            ("a =. b +. c", "a =. (b +. c)"),
+            ("a <. b <. c", "(a <. b) <. c"),
+            ("a |. b && c", "(a |. b) && c"),
+            ("a $. b && c", "(a $. b) && c"),
+            ("a &. b ^ c", "a &. (b ^ c)"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(
+                parse_expression(source),
+                parse_expression(expected),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_operator_section() {
+        assert_eq!(
+            parse_expression("( * ) 2 3"),
+            Expression::App {
+                function: Expression::App {
+                    function: variable("*").into(),
+                    argument: Expression::Integer(2).into(),
+                }
+                .into(),
+                argument: Expression::Integer(3).into(),
+            }
+        );
+        assert_eq!(parse_expression("( mod )"), variable("mod"));
+        assert_eq!(parse_expression("( +++ )"), variable("+++"));
+        assert_eq!(parse_expression("M.( +++ )"), variable("M.+++"));
+    }
+
+    #[test]
+    fn test_parse_operator_definition() {
+        let result = parse_source("let ( ** ) a b = a * b");
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap().top_levels,
+            vec![TopLevel::Let {
+                variable: "**".to_string(),
+                bound: Expression::Fun {
+                    parameter: "a".to_string(),
+                    body: Expression::Fun {
+                        parameter: "b".to_string(),
+                        body: Expression::Times {
+                            expression1: variable("a").into(),
+                            expression2: variable("b").into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_comment_is_not_operator_section() {
+        // (* は演算子 * を囲む括弧ではなく、コメントの始まり
+        assert_eq!(parse_expression("(* ( * ) *) 1"), Expression::Integer(1));
+        assert!(tokenize("(*) 1".to_string()).is_err());
+    }
+}
@@ -1,6 +1,28 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, iter::Peekable, str::Chars};
 
-pub fn tokenize(input: String) -> VecDeque<String> {
+use anyhow::{Result, bail};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+enum TokenizeError {
+    #[error("String literal not terminated")]
+    UnterminatedString,
+    #[error("Character literal not terminated")]
+    UnterminatedChar,
+    #[error("Illegal backslash escape in string or character ({0})")]
+    IllegalEscape(String),
+    #[error("Comment not terminated (comment started at line {0}, character {1})")]
+    UnterminatedComment(usize, usize),
+}
+
+// 中置演算子の先頭に来る文字と、2文字目以降に来る文字
+const OPERATOR_START: &str = "$&*+-/=>@^|%<";
+const OPERATOR_CHARS: &str = "$&*+-/=>@^|%<~!?:.";
+// let や and の直後に来て束縛演算子になる文字
+const BINDING_OPERATOR_START: &str = "$&*+-/=>@^|<";
+
+// 文字列リテラルは "内容" 、文字リテラルは '文字' の形で、エスケープを解釈した後のトークンにする
+pub fn tokenize(input: String) -> Result<VecDeque<String>> {
     let mut out = VecDeque::new();
     let mut it = input.chars().peekable();
 
@@ -9,13 +31,42 @@ pub fn tokenize(input: String) -> VecDeque<String> {
             continue;
         }
 
-        if it.next_if_eq(&':').is_some() && it.next_if_eq(&':').is_some() {
-            out.push_back("::".into());
+        if it.next_if_eq(&':').is_some() {
+            if it.next_if_eq(&':').is_some() {
+                out.push_back("::".into());
+            } else if it.next_if_eq(&'=').is_some() {
+                out.push_back(":=".into());
+            } else {
+                out.push_back(":".into());
+            }
+            continue;
+        }
+
+        if it.next_if_eq(&'[').is_some() {
+            if it.next_if_eq(&']').is_some() {
+                out.push_back("[]".into());
+            } else {
+                out.push_back("[".into());
+            }
+            continue;
+        }
+
+        if it.next_if_eq(&';').is_some() {
+            if it.next_if_eq(&';').is_some() {
+                out.push_back(";;".into());
+            } else {
+                out.push_back(";".into());
+            }
             continue;
         }
 
-        if it.next_if_eq(&'[').is_some() && it.next_if_eq(&']').is_some() {
-            out.push_back("[]".into());
+        if it.peek() == Some(&'(') && it.clone().nth(1) == Some('*') {
+            let start = it.clone();
+            it.nth(1);
+            if !skip_comment(&mut it) {
+                let (line, column) = position(&input, start);
+                bail!(TokenizeError::UnterminatedComment(line, column));
+            }
             continue;
         }
 
@@ -29,22 +80,19 @@ pub fn tokenize(input: String) -> VecDeque<String> {
             continue;
         }
 
-        if it.next_if_eq(&'-').is_some() {
-            if it.next_if_eq(&'>').is_some() {
-                out.push_back("->".into());
-                continue;
-            }
-
-            let mut integer_literal = String::from("-");
-            while it.peek().is_some_and(|c| c.is_ascii_digit()) {
-                integer_literal.push(it.next().unwrap());
+        // 演算子はOCamlと同じく演算子の文字の列を最長で読む(-> や <- もここで読む)
+        // 符号は数値リテラルに含めず、単項または二項の演算子として構文解析で扱う
+        if it.peek().is_some_and(|&c| OPERATOR_START.contains(c)) {
+            let mut operator = String::new();
+            while let Some(c) = it.next_if(|&c| OPERATOR_CHARS.contains(c)) {
+                operator.push(c);
             }
-            out.push_back(integer_literal);
+            out.push_back(operator);
             continue;
         }
 
-        if it.next_if_eq(&'+').is_some() {
-            let mut integer_literal = String::from("+");
+        if it.peek().is_some_and(|c| c.is_ascii_digit()) {
+            let mut integer_literal = String::new();
             while it.peek().is_some_and(|c| c.is_ascii_digit()) {
                 integer_literal.push(it.next().unwrap());
             }
@@ -52,25 +100,56 @@ pub fn tokenize(input: String) -> VecDeque<String> {
             continue;
         }
 
-        if let Some(c) = it.next_if(|&c| "|=*<".contains(c)) {
-            out.push_back(c.into());
+        if it.next_if_eq(&'"').is_some() {
+            let mut string_literal = String::from("\"");
+            loop {
+                match it.next() {
+                    Some('"') => break,
+                    Some('\\') => string_literal.extend(read_escape(&mut it, true)?),
+                    Some(c) => string_literal.push(c),
+                    None => bail!(TokenizeError::UnterminatedString),
+                }
+            }
+            string_literal.push('"');
+            out.push_back(string_literal);
             continue;
         }
 
-        if it.peek().is_some_and(|c| c.is_ascii_digit()) {
-            let mut integer_literal = String::new();
-            while it.peek().is_some_and(|c| c.is_ascii_digit()) {
-                integer_literal.push(it.next().unwrap());
+        // 文字リテラル 'c' または型変数 'a
+        if it.next_if_eq(&'\'').is_some() {
+            let mut lookahead = it.clone();
+            if let Some(c) = read_char_literal(&mut lookahead)? {
+                it = lookahead;
+                out.push_back(format!("'{}'", c));
+                continue;
             }
-            out.push_back(integer_literal);
+
+            let mut type_variable = String::from("'");
+            while it.peek().is_some_and(|&c| c.is_alphanumeric() || c == '_') {
+                type_variable.push(it.next().unwrap());
+            }
+            out.push_back(type_variable);
             continue;
         }
 
-        if it.peek().is_some_and(|c| c.is_ascii_lowercase()) {
+        if it
+            .peek()
+            .is_some_and(|&c| c.is_ascii_alphabetic() || c == '_')
+        {
             let mut identifier = String::new();
             while it.peek().is_some_and(|&c| c.is_alphanumeric() || c == '_') {
                 identifier.push(it.next().unwrap());
             }
+            // let* や and+ のような束縛演算子は、キーワードに続く演算子の文字までを1つのトークンにする
+            if (identifier == "let" || identifier == "and")
+                && it
+                    .peek()
+                    .is_some_and(|&c| BINDING_OPERATOR_START.contains(c))
+            {
+                while let Some(c) = it.next_if(|&c| OPERATOR_CHARS.contains(c)) {
+                    identifier.push(c);
+                }
+            }
             out.push_back(identifier);
             continue;
         }
@@ -78,5 +157,180 @@ pub fn tokenize(input: String) -> VecDeque<String> {
         out.push_back(it.next().unwrap().into());
     }
 
-    out
+    Ok(out)
+}
+
+// (* の後からコメントを読み飛ばし、閉じていればtrueを返す
+// コメントは入れ子にでき、コメント中の文字列リテラルの中の *) ではコメントは閉じない
+// OCamlと同じく '"' のような文字リテラルも読み飛ばし、その中の " を文字列の始まりとみなさない
+fn skip_comment(it: &mut Peekable<Chars>) -> bool {
+    let mut depth = 1;
+    while let Some(c) = it.next() {
+        match c {
+            '(' if it.next_if_eq(&'*').is_some() => depth += 1,
+            '*' if it.next_if_eq(&')').is_some() => {
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            }
+            '"' => loop {
+                match it.next() {
+                    Some('"') => break,
+                    Some('\\') => {
+                        it.next();
+                    }
+                    Some(_) => {}
+                    None => return false,
+                }
+            },
+            '\'' => {
+                let mut literal = it.clone();
+                if matches!(read_char_literal(&mut literal), Ok(Some(_))) {
+                    *it = literal;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+// 残りの入力restが始まる位置を、1から数えた行と文字の番号で返す
+fn position(input: &str, rest: Peekable<Chars>) -> (usize, usize) {
+    let offset = input.chars().count() - rest.count();
+    let consumed = input.chars().take(offset).collect::<String>();
+    let line = consumed.matches('\n').count() + 1;
+    let column = consumed.chars().rev().take_while(|&c| c != '\n').count() + 1;
+
+    (line, column)
+}
+
+// 開き引用符の後から文字リテラルを読む。文字リテラルでなければ(型変数なら)Noneを返す
+fn read_char_literal(it: &mut Peekable<Chars>) -> Result<Option<char>> {
+    match it.next() {
+        Some('\\') => {
+            let c = read_escape(it, false)?;
+            match (c, it.next()) {
+                (Some(c), Some('\'')) => Ok(Some(c)),
+                _ => bail!(TokenizeError::UnterminatedChar),
+            }
+        }
+        Some(c) if c != '\'' && it.next_if_eq(&'\'').is_some() => Ok(Some(c)),
+        _ => Ok(None),
+    }
+}
+
+// バックスラッシュの後からエスケープシーケンスを読む
+// 文字列中の行末のバックスラッシュは、次の行の先頭の空白とともに読み飛ばす
+fn read_escape(it: &mut Peekable<Chars>, in_string: bool) -> Result<Option<char>> {
+    let c = match it.next() {
+        Some('\\') => '\\',
+        Some('"') => '"',
+        Some('\'') => '\'',
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('b') => '\u{8}',
+        Some('r') => '\r',
+        Some(' ') => ' ',
+        Some('\n') if in_string => {
+            while it.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+            return Ok(None);
+        }
+        Some(d) if d.is_ascii_digit() => read_code(it, d.to_string(), 10, 2)?,
+        Some('x') => read_code(it, "x".to_string(), 16, 2)?,
+        Some('o') => read_code(it, "o".to_string(), 8, 3)?,
+        Some('u') if in_string && it.next_if_eq(&'{').is_some() => {
+            let mut sequence = String::from("u{");
+            while let Some(d) = it.next_if(char::is_ascii_hexdigit) {
+                sequence.push(d);
+            }
+            let code = u32::from_str_radix(&sequence[2..], 16).ok();
+            match (code.and_then(char::from_u32), it.next_if_eq(&'}')) {
+                (Some(c), Some(_)) => c,
+                _ => bail!(TokenizeError::IllegalEscape(format!("\\{}", sequence))),
+            }
+        }
+        Some(other) => bail!(TokenizeError::IllegalEscape(format!("\\{}", other))),
+        None if in_string => bail!(TokenizeError::UnterminatedString),
+        None => bail!(TokenizeError::UnterminatedChar),
+    };
+
+    Ok(Some(c))
+}
+
+// \ddd, \xhh, \oooo の数字部分を読み、0から255の文字コードとして解釈する
+fn read_code(
+    it: &mut Peekable<Chars>,
+    mut sequence: String,
+    radix: u32,
+    digits: usize,
+) -> Result<char> {
+    for _ in 0..digits {
+        match it.next_if(|c| c.is_digit(radix)) {
+            Some(d) => sequence.push(d),
+            None => bail!(TokenizeError::IllegalEscape(format!("\\{}", sequence))),
+        }
+    }
+
+    let number = sequence.trim_start_matches(['x', 'o']);
+    match u8::from_str_radix(number, radix) {
+        Ok(code) => Ok(char::from(code)),
+        Err(_) => bail!(TokenizeError::IllegalEscape(format!("\\{}", sequence))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<String> {
+        tokenize(source.to_string()).unwrap().into()
+    }
+
+    #[test]
+    fn test_symbolic_operator() {
+        assert_eq!(tokens("a +++ b"), vec!["a", "+++", "b"]);
+        assert_eq!(tokens("a|>f"), vec!["a", "|>", "f"]);
+        assert_eq!(tokens("a **. b"), vec!["a", "**.", "b"]);
+        assert_eq!(tokens("a <$?> b"), vec!["a", "<$?>", "b"]);
+        // 記号の列は最長で読むので、x=-1 の =- は1つの演算子になる
+        assert_eq!(tokens("x=-1"), vec!["x", "=-", "1"]);
+    }
+
+    #[test]
+    fn test_operator_section() {
+        assert_eq!(tokens("( * ) 2 3"), vec!["(", "*", ")", "2", "3"]);
+        assert_eq!(tokens("( +++ )"), vec!["(", "+++", ")"]);
+    }
+
+    #[test]
+    fn test_nested_comment() {
+        assert_eq!(tokens("(* a (* b *) c *) 1"), vec!["1"]);
+    }
+
+    #[test]
+    fn test_comment_with_string_literal() {
+        assert_eq!(tokens("(* \"*)\" *) 1"), vec!["1"]);
+        assert_eq!(tokens("(* \"\\\"*)\" *) 1"), vec!["1"]);
+    }
+
+    #[test]
+    fn test_comment_with_char_literal() {
+        assert_eq!(tokens("(* '\"' *) 1"), vec!["1"]);
+        assert_eq!(tokens("(* '\\\"' *) 1"), vec!["1"]);
+        assert_eq!(tokens("(* 'a *) 1"), vec!["1"]);
+    }
+
+    #[test]
+    fn test_unterminated_comment() {
+        let result = tokenize("1\n  (* a (* b *)\n2".to_string());
+
+        assert!(result.is_err());
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<TokenizeError>(),
+            Some(TokenizeError::UnterminatedComment(2, 3))
+        ));
+    }
 }
@@ -0,0 +1,345 @@
+use std::io::Write;
+
+use anyhow::{Result, bail};
+use thiserror::Error;
+
+use crate::{
+    analysis::{
+        parser::{parse_program, parse_type_expression},
+        tokenizer::tokenize,
+    },
+    execution::{
+        environment::Environment,
+        evaluation::{eval_top_level, raise},
+    },
+    syntax::value::{PrimitiveFunction, Value},
+    type_system::{
+        inference::{declare_value, infer_top_level},
+        type_environment::TypeEnvironment,
+        types::Type,
+    },
+};
+
+#[derive(Debug, Error)]
+enum BuiltinError {
+    #[error("Primitive {0} is applied to invalid arguments")]
+    InvalidArguments(String),
+    #[error("Built-in exception {0} is not declared")]
+    MissingException(String),
+}
+
+// 評価器や組み込みの関数が送出する例外。組み込みの定義を読み込んだときの番号を環境に控えておく
+const RAISED_EXCEPTIONS: [&str; 5] = [
+    "Match_failure",
+    "Failure",
+    "Invalid_argument",
+    "Assert_failure",
+    "Division_by_zero",
+];
+
+// picocaml自身で定義する組み込みの例外と関数
+// Assert_failureはassertが失敗したとき、Division_by_zeroは0で割ったとき、
+// Match_failureはどのパターンにも照合しなかったときに評価器が送出する
+const DEFINITIONS: &str = "
+exception Not_found
+exception Match_failure
+exception Failure of string
+exception Invalid_argument of string
+exception Assert_failure
+exception Division_by_zero
+
+(* ( + ) などで組み込みの演算子を関数として使うための定義 *)
+let ( + ) a b = a + b
+let ( - ) a b = a - b
+let ( * ) a b = a * b
+let ( / ) a b = a / b
+let ( mod ) a b = a mod b
+let ( = ) a b = a = b
+let ( <> ) a b = a <> b
+let ( < ) a b = a < b
+let ( <= ) a b = a <= b
+let ( > ) a b = a > b
+let ( >= ) a b = a >= b
+let ( && ) a b = a && b
+let ( || ) a b = a || b
+let ( ^ ) a b = a ^ b
+let ( @ ) a b = a @ b
+let ( |> ) x f = f x
+let ( @@ ) f x = f x
+let ( ! ) r = !r
+let ( := ) r v = r := v
+
+let not = fun b -> if b then false else true
+let fst = fun pair -> match pair with (first, _) -> first
+let snd = fun pair -> match pair with (_, second) -> second
+let failwith = fun message -> raise (Failure message)
+let invalid_arg = fun message -> raise (Invalid_argument message)
+";
+
+// picocamlで書いた標準ライブラリ(List、Option、Int、Bool と型 option)
+const PRELUDE: &str = include_str!("prelude.picocaml");
+
+// 組み込みの定義に標準ライブラリを加えた環境。REPLやplaygroundはこれで始める
+pub fn environments() -> Result<(Environment, TypeEnvironment)> {
+    load(bare_environments()?, PRELUDE)
+}
+
+// 標準ライブラリを読み込まない、組み込みの定義だけの環境
+pub fn bare_environments() -> Result<(Environment, TypeEnvironment)> {
+    let (environment, type_environment) = load(
+        (Environment::default(), TypeEnvironment::default()),
+        DEFINITIONS,
+    )?;
+    let environment = RAISED_EXCEPTIONS
+        .iter()
+        .try_fold(environment, |environment, name| {
+            let name = name.to_string();
+            let Some(tag) = environment.get_constructor(&name) else {
+                bail!(BuiltinError::MissingException(name));
+            };
+            Ok(environment.bind_builtin_exception(name, tag))
+        })?;
+    define_primitives((environment, type_environment))
+}
+
+// Rustの関数 function を、型式 type_expression の型を持つ値 name として両方の環境に加える
+// 型式の中の 'a などについて多相になり、型の矢印の数だけ引数が揃ったところで function を呼び出す
+// 引数をとらない型なら、その場で function を呼び出した値を束縛する
+pub fn define_primitive(
+    (environment, type_environment): (Environment, TypeEnvironment),
+    name: &str,
+    type_expression: &str,
+    function: impl Fn(Vec<Value>) -> Result<Value> + Send + Sync + 'static,
+) -> Result<(Environment, TypeEnvironment)> {
+    let value_type = parse_type_expression(tokenize(type_expression.to_string())?)?;
+    let (type_environment, value_type) =
+        declare_value(type_environment, name.to_string(), value_type)?;
+
+    let value = match arity(&value_type) {
+        0 => function(Vec::new())?,
+        arity => Value::Primitive {
+            name: name.to_string(),
+            arity,
+            arguments: Vec::new(),
+            function: PrimitiveFunction::new(function),
+        },
+    };
+    let environment = environment.bind(name.to_string(), value)?;
+
+    Ok((environment, type_environment))
+}
+
+fn arity(t: &Type) -> usize {
+    match t {
+        Type::Function { range, .. } => 1 + arity(range),
+        _ => 0,
+    }
+}
+
+// Rustで実装する組み込みの関数
+fn define_primitives(
+    environments: (Environment, TypeEnvironment),
+) -> Result<(Environment, TypeEnvironment)> {
+    let environments = define_primitive(
+        environments,
+        "string_of_int",
+        "int -> string",
+        |arguments| match arguments.as_slice() {
+            [Value::Integer(n)] => Ok(Value::String(n.to_string())),
+            _ => bail!(BuiltinError::InvalidArguments("string_of_int".to_string())),
+        },
+    )?;
+    let failure = environments
+        .0
+        .get_builtin_exception("Failure")
+        .ok_or(BuiltinError::MissingException("Failure".to_string()))?;
+    let environments = define_primitive(
+        environments,
+        "int_of_string",
+        "string -> int",
+        move |arguments| match arguments.as_slice() {
+            [Value::String(s)] => s.parse().map(Value::Integer).map_err(|_| {
+                raise(Value::Constructor {
+                    name: "Failure".to_string(),
+                    tag: failure,
+                    argument: Some(Box::new(Value::String("int_of_string".to_string()))),
+                })
+            }),
+            _ => bail!(BuiltinError::InvalidArguments("int_of_string".to_string())),
+        },
+    )?;
+    let environments = define_primitive(
+        environments,
+        "print_string",
+        "string -> unit",
+        |arguments| match arguments.as_slice() {
+            [Value::String(s)] => print(s),
+            _ => bail!(BuiltinError::InvalidArguments("print_string".to_string())),
+        },
+    )?;
+    let environments = define_primitive(
+        environments,
+        "print_endline",
+        "string -> unit",
+        |arguments| match arguments.as_slice() {
+            [Value::String(s)] => print(&format!("{}\n", s)),
+            _ => bail!(BuiltinError::InvalidArguments("print_endline".to_string())),
+        },
+    )?;
+    define_primitive(
+        environments,
+        "print_int",
+        "int -> unit",
+        |arguments| match arguments.as_slice() {
+            [Value::Integer(n)] => print(&n.to_string()),
+            _ => bail!(BuiltinError::InvalidArguments("print_int".to_string())),
+        },
+    )
+}
+
+// REPLの出力と混ざらないように、書くたびに出力を流しておく
+fn print(s: &str) -> Result<Value> {
+    let mut stdout = std::io::stdout();
+    stdout.write_all(s.as_bytes())?;
+    stdout.flush()?;
+    Ok(Value::Unit)
+}
+
+fn load(
+    environments: (Environment, TypeEnvironment),
+    source: &str,
+) -> Result<(Environment, TypeEnvironment)> {
+    let program = parse_program(tokenize(source.to_string())?)?;
+
+    program.top_levels.into_iter().try_fold(
+        environments,
+        |(environment, type_environment), top_level| {
+            let (type_environment, _) = infer_top_level(type_environment, top_level.clone())?;
+            let (environment, _) = eval_top_level(environment, top_level)?;
+
+            Ok((environment, type_environment))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        execution::evaluation::with_evaluation_stack,
+        syntax::{ast::TopLevel, value::Value},
+    };
+
+    fn run(
+        (environment, type_environment): (Environment, TypeEnvironment),
+        source: &str,
+    ) -> Result<Value> {
+        let program = parse_program(tokenize(source.to_string())?)?;
+        let top_level = TopLevel::Expression(match program.top_levels.as_slice() {
+            [TopLevel::Expression(expression)] => expression.clone(),
+            _ => unreachable!(),
+        });
+        infer_top_level(type_environment, top_level.clone())?;
+        let (_, value) = eval_top_level(environment, top_level)?;
+        Ok(value)
+    }
+
+    #[test]
+    fn test_prelude() {
+        let source = "List.fold_left ( + ) 0 (List.map (fun x -> x * 2) (List.rev [1; 2; 3]))";
+
+        let result = run(environments().unwrap(), source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(12));
+    }
+
+    #[test]
+    fn test_prelude_long_list() {
+        let source = "List.length (List.map (fun x -> x + 1) (List.init 1000 (fun i -> i)))";
+
+        let result = with_evaluation_stack(|| run(environments().unwrap(), source));
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(1000));
+    }
+
+    #[test]
+    fn test_bare_environments_without_prelude() {
+        let result = run(bare_environments().unwrap(), "List.length [1]");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_match_failure_is_exception() {
+        let source = "try (match 1 with 2 -> 3) with Match_failure -> 5";
+
+        let result = run(bare_environments().unwrap(), source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(5));
+    }
+
+    #[test]
+    fn test_builtin_operator_cannot_be_redefined() {
+        let environments = bare_environments().unwrap();
+
+        let result = load(environments.clone(), "let ( + ) a b = a - b");
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The built-in operator ( + ) cannot be redefined"
+        );
+        assert!(run(environments.clone(), "let ( = ) a b = false in 1 = 1").is_err());
+        assert!(run(environments.clone(), "let ( ^ ) a b = a in \"a\" ^ \"b\"").is_err());
+        assert!(run(environments.clone(), "let rec ( |> ) x f = f x in 1").is_err());
+        assert!(run(environments.clone(), "let ( mod ) a b = a in 7 mod 2").is_err());
+
+        // 組み込みでない演算子は定義できる
+        let result = run(environments, "let ( +. ) a b = a - b in 3 +. 1");
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(2));
+    }
+
+    #[test]
+    fn test_define_primitive() {
+        let environments = define_primitive(
+            bare_environments().unwrap(),
+            "add3",
+            "int -> int -> int -> int",
+            |arguments| match arguments.as_slice() {
+                [Value::Integer(a), Value::Integer(b), Value::Integer(c)] => {
+                    Ok(Value::Integer(a + b + c))
+                }
+                _ => bail!(BuiltinError::InvalidArguments("add3".to_string())),
+            },
+        );
+        assert!(environments.is_ok());
+
+        let result = run(environments.unwrap(), "let f = add3 1 2 in f 3 + f 4");
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(13));
+    }
+
+    #[test]
+    fn test_define_polymorphic_primitive() {
+        let environments = define_primitive(
+            bare_environments().unwrap(),
+            "twice",
+            "'a -> 'a * 'a",
+            |arguments| {
+                Ok(Value::Tuple(vec![
+                    arguments[0].clone(),
+                    arguments[0].clone(),
+                ]))
+            },
+        );
+        assert!(environments.is_ok());
+
+        let environments = environments.unwrap();
+        assert!(run(environments.clone(), "(twice 1, twice true)").is_ok());
+        assert!(run(environments, "fst (twice 1) + snd (twice true)").is_err());
+    }
+}
@@ -1,2 +1,3 @@
 pub mod environment;
 pub mod evaluation;
+pub mod store;
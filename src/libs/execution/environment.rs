@@ -1,22 +1,177 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::{Ok, Result};
 
-use crate::{adapter::Symbol, syntax::value::Value};
+use crate::{
+    adapter::{Symbol, split_path},
+    syntax::{
+        ast::{ModuleExpression, TopLevel, TypeKind},
+        value::Value,
+    },
+};
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
+// クロージャが環境を複製しても値そのものは共有されるように、値はArcで持つ
 pub struct Environment {
-    variables: HashMap<Symbol, Value>,
+    variables: HashMap<Symbol, Arc<Value>>,
+    // モジュールの中身は、そのモジュールで宣言した名前だけを持つ環境として入れ子にする
+    modules: HashMap<Symbol, Environment>,
+    functors: HashMap<Symbol, Functor>,
+    // 構築子の番号。型の構築子は宣言した順に番号を持ち、値と照合するときは名前と番号の両方を比べる
+    // 例外は宣言するたびに新しい番号を持つので、同じ名前でも別の宣言の例外とは照合しない
+    constructors: HashMap<Symbol, usize>,
+    // レコードのラベルが型の宣言の中で何番目か。レコードの値はフィールドを宣言の順に並べて持つ
+    labels: HashMap<Symbol, usize>,
+    // 評価器が送出する組み込みの例外の番号。同じ名前の例外を宣言し直しても変わらない
+    builtin_exceptions: HashMap<Symbol, usize>,
+}
+
+// ファンクタは適用するたびに、引数のモジュールを束縛した定義時の環境で本体を評価する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Functor {
+    pub parameter: Symbol,
+    pub body: ModuleExpression,
+    pub environment: Environment,
 }
 
 impl Environment {
+    // M.N.x のような経路をたどり、x を探すべき環境と x を返す
+    fn namespace<'a, 'b>(&'a self, name: &'b str) -> Option<(&'a Environment, &'b str)> {
+        match split_path(name) {
+            Some((module, rest)) => self.modules.get(module)?.namespace(rest),
+            None => Some((self, name)),
+        }
+    }
+
     pub fn bind(self, variable: Symbol, value: Value) -> Result<Self> {
         let mut new = self.clone();
-        new.variables.insert(variable, value);
+        new.variables.insert(variable, Arc::new(value));
         Ok(new)
     }
 
     pub fn get(&self, variable: &Symbol) -> Option<Value> {
-        self.variables.get(variable).cloned()
+        let (namespace, variable) = self.namespace(variable)?;
+        namespace
+            .variables
+            .get(variable)
+            .map(|value| value.as_ref().clone())
+    }
+
+    pub fn bind_module(mut self, name: Symbol, namespace: Environment) -> Self {
+        self.modules.insert(name, namespace);
+        self
+    }
+
+    pub fn get_module(&self, path: &Symbol) -> Option<&Environment> {
+        let (namespace, name) = self.namespace(path)?;
+        namespace.modules.get(name)
+    }
+
+    pub fn bind_functor(mut self, name: Symbol, functor: Functor) -> Self {
+        self.functors.insert(name, functor);
+        self
+    }
+
+    pub fn get_functor(&self, path: &Symbol) -> Option<&Functor> {
+        let (namespace, name) = self.namespace(path)?;
+        namespace.functors.get(name)
+    }
+
+    pub fn bind_constructor(mut self, name: Symbol, tag: usize) -> Self {
+        self.constructors.insert(name, tag);
+        self
+    }
+
+    pub fn get_constructor(&self, path: &Symbol) -> Option<usize> {
+        let (namespace, name) = self.namespace(path)?;
+        namespace.constructors.get(name).copied()
+    }
+
+    pub fn bind_label(mut self, label: Symbol, index: usize) -> Self {
+        self.labels.insert(label, index);
+        self
+    }
+
+    pub fn get_label(&self, path: &Symbol) -> Option<usize> {
+        let (namespace, label) = self.namespace(path)?;
+        namespace.labels.get(label).copied()
+    }
+
+    pub fn bind_builtin_exception(mut self, name: Symbol, tag: usize) -> Self {
+        self.builtin_exceptions.insert(name, tag);
+        self
+    }
+
+    pub fn get_builtin_exception(&self, name: &str) -> Option<usize> {
+        self.builtin_exceptions.get(name).copied()
+    }
+
+    // open M で、モジュールの中の名前を経路なしで使えるようにする
+    pub fn open(mut self, namespace: &Environment) -> Self {
+        self.variables.extend(namespace.variables.clone());
+        self.modules.extend(namespace.modules.clone());
+        self.functors.extend(namespace.functors.clone());
+        self.constructors.extend(namespace.constructors.clone());
+        self.labels.extend(namespace.labels.clone());
+        self
+    }
+
+    // モジュールの中で宣言した名前だけを取り出した環境を作る
+    pub fn export(&self, declarations: &[TopLevel]) -> Environment {
+        let mut namespace = Environment::default();
+        for declaration in declarations {
+            let variables = match declaration {
+                TopLevel::Let { variable, .. } => vec![variable.clone()],
+                TopLevel::LetPattern { pattern, .. } => pattern.variables(),
+                TopLevel::LetRec { bindings } => bindings
+                    .iter()
+                    .map(|(variable, _)| variable.clone())
+                    .collect(),
+                TopLevel::Module { name, .. } => {
+                    if let Some(module) = self.modules.get(name) {
+                        namespace.modules.insert(name.clone(), module.clone());
+                    }
+                    if let Some(functor) = self.functors.get(name) {
+                        namespace.functors.insert(name.clone(), functor.clone());
+                    }
+                    vec![]
+                }
+                TopLevel::TypeDefinition(definitions) => {
+                    for definition in definitions {
+                        match &definition.kind {
+                            TypeKind::Variant(constructors) => {
+                                for (constructor, _) in constructors {
+                                    if let Some(tag) = self.constructors.get(constructor) {
+                                        namespace.constructors.insert(constructor.clone(), *tag);
+                                    }
+                                }
+                            }
+                            TypeKind::Record(fields) => {
+                                for field in fields {
+                                    if let Some(index) = self.labels.get(&field.label) {
+                                        namespace.labels.insert(field.label.clone(), *index);
+                                    }
+                                }
+                            }
+                            TypeKind::Abstract | TypeKind::Alias(_) => {}
+                        }
+                    }
+                    vec![]
+                }
+                TopLevel::Exception { name, .. } => {
+                    if let Some(tag) = self.constructors.get(name) {
+                        namespace.constructors.insert(name.clone(), *tag);
+                    }
+                    vec![]
+                }
+                _ => vec![],
+            };
+            for variable in variables {
+                if let Some(value) = self.variables.get(&variable) {
+                    namespace.variables.insert(variable, value.clone());
+                }
+            }
+        }
+        namespace
     }
 }
@@ -1,13 +1,25 @@
+use std::{
+    cmp::Ordering,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
 use anyhow::{Ok, Result, anyhow, bail};
 use thiserror::Error;
 
 use crate::{
     adapter::{
-        RArithmeticOperation, RBool, RComparisonOperation, RInteger, Symbol, r_lt, r_minus, r_plus,
-        r_times,
+        RArithmeticOperation, RBool, RChar, RComparisonOperation, RInteger, RString, Symbol,
+        base_name, r_concat, r_divide, r_eq, r_ge, r_gt, r_le, r_lt, r_minus, r_modulo, r_ne,
+        r_negate, r_plus, r_times,
+    },
+    execution::{
+        environment::{Environment, Functor},
+        store::Location,
+    },
+    syntax::{
+        ast::{Expression, ModuleExpression, Pattern, TopLevel, TypeDefinition, TypeKind},
+        value::{RecursiveFunction, Value},
     },
-    execution::environment::Environment,
-    syntax::{ast::Expression, value::Value},
 };
 
 type EvalResult = Result<(Environment, Value)>;
@@ -18,12 +30,45 @@ enum EvalError {
     InvalidExpression,
     #[error("Undefined variable: {0}")]
     UndefinedVariable(Symbol),
+    #[error("Unbound record field {0}")]
+    UndefinedField(Symbol),
+    #[error("Exception: {0}")]
+    Exception(Box<Value>),
+    #[error("Unbound module {0}")]
+    UndefinedModule(Symbol),
+    #[error("Unbound built-in exception {0}")]
+    UndefinedBuiltinException(Symbol),
+}
+
+// 評価や型推論は、式の入れ子や関数呼び出しの深さだけRustのスタックを使う
+// 長いリストを再帰で処理しても溢れないように、REPLやplaygroundは大きなスタックを持つスレッドで実行する
+pub const EVALUATION_STACK_SIZE: usize = 1 << 30;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn with_evaluation_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(EVALUATION_STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn the evaluation thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+// WebAssemblyではスレッドを作れないので、スタックの大きさはリンク時に指定する(.cargo/config.toml)
+#[cfg(target_arch = "wasm32")]
+pub fn with_evaluation_stack<T>(f: impl FnOnce() -> T) -> T {
+    f()
 }
 
 pub fn eval(environment: Environment, expression: Expression) -> EvalResult {
     match expression {
         Expression::Integer(n) => eval_integer(environment, n),
         Expression::Bool(b) => eval_bool(environment, b),
+        Expression::String(s) => eval_string(environment, s),
+        Expression::Char(c) => eval_char(environment, c),
+        Expression::Unit => Ok((environment, Value::Unit)),
         Expression::Variable(variable) => eval_variable(environment, variable),
         Expression::Plus {
             expression1,
@@ -37,10 +82,55 @@ pub fn eval(environment: Environment, expression: Expression) -> EvalResult {
             expression1,
             expression2,
         } => eval_arithmetic_operation(environment, *expression1, *expression2, r_times),
+        Expression::Divide {
+            expression1,
+            expression2,
+        } => eval_arithmetic_operation(environment, *expression1, *expression2, r_divide),
+        Expression::Modulo {
+            expression1,
+            expression2,
+        } => eval_arithmetic_operation(environment, *expression1, *expression2, r_modulo),
+        Expression::Negate(expression) => eval_negation(environment, *expression),
         Expression::LessThan {
             expression1,
             expression2,
         } => eval_comparison_operation(environment, *expression1, *expression2, r_lt),
+        Expression::Equal {
+            expression1,
+            expression2,
+        } => eval_comparison_operation(environment, *expression1, *expression2, r_eq),
+        Expression::NotEqual {
+            expression1,
+            expression2,
+        } => eval_comparison_operation(environment, *expression1, *expression2, r_ne),
+        Expression::LessEqual {
+            expression1,
+            expression2,
+        } => eval_comparison_operation(environment, *expression1, *expression2, r_le),
+        Expression::GreaterThan {
+            expression1,
+            expression2,
+        } => eval_comparison_operation(environment, *expression1, *expression2, r_gt),
+        Expression::GreaterEqual {
+            expression1,
+            expression2,
+        } => eval_comparison_operation(environment, *expression1, *expression2, r_ge),
+        Expression::And {
+            expression1,
+            expression2,
+        } => eval_and(environment, *expression1, *expression2),
+        Expression::Or {
+            expression1,
+            expression2,
+        } => eval_or(environment, *expression1, *expression2),
+        Expression::Concat {
+            expression1,
+            expression2,
+        } => eval_concat(environment, *expression1, *expression2),
+        Expression::Append {
+            expression1,
+            expression2,
+        } => eval_append(environment, *expression1, *expression2),
         Expression::If {
             predicate,
             consequent,
@@ -51,23 +141,178 @@ pub fn eval(environment: Environment, expression: Expression) -> EvalResult {
             bound,
             body,
         } => eval_let(environment, variable, *bound, *body),
+        Expression::Sequence {
+            expression1,
+            expression2,
+        } => eval_sequence(environment, *expression1, *expression2),
         Expression::Fun { parameter, body } => eval_fun(environment, parameter, *body),
         Expression::App { function, argument } => eval_app(environment, *function, *argument),
-        Expression::LetRec {
-            variable,
-            bound_function,
-            body,
-        } => eval_let_rec(environment, variable, *bound_function, *body),
+        Expression::LetRec { bindings, body } => eval_let_rec(environment, bindings, *body),
         Expression::Nil => eval_nil(environment),
         Expression::Cons { car, cdr } => eval_cons(environment, *car, *cdr),
-        Expression::Match {
-            scrutinee,
-            nil_case,
-            cons_pattern: (car, cdr, cons_case),
-        } => eval_match(environment, *scrutinee, *nil_case, (car, cdr, *cons_case)),
+        Expression::Match { scrutinee, arms } => eval_match(environment, *scrutinee, arms),
+        Expression::Tuple(expressions) => eval_tuple(environment, expressions),
+        Expression::Constructor { name, argument } => {
+            eval_constructor(environment, name, argument.map(|argument| *argument))
+        }
+        Expression::TypeDefinition { definitions, body } => {
+            eval_type_definition(environment, &definitions, *body)
+        }
+        Expression::Record(fields) => eval_record(environment, fields),
+        Expression::Field { record, label } => eval_field(environment, *record, label),
+        Expression::RecordUpdate { record, fields } => {
+            eval_record_update(environment, *record, fields)
+        }
+        Expression::FieldAssignment {
+            record,
+            label,
+            value,
+        } => eval_field_assignment(environment, *record, label, *value),
+        Expression::Ref(expression) => eval_ref(environment, *expression),
+        Expression::Deref(expression) => eval_deref(environment, *expression),
+        Expression::Assign { reference, value } => eval_assign(environment, *reference, *value),
+        Expression::Raise(expression) => eval_raise(environment, *expression),
+        Expression::Try { body, arms } => eval_try(environment, *body, arms),
+        Expression::Assert(expression) => eval_assert(environment, *expression),
+        Expression::Annotation { expression, .. } => eval(environment, *expression),
+        Expression::Open { module, body } => eval_open(environment, module, *body),
+    }
+}
+
+// トップレベルの宣言を評価し、宣言した名前を環境に加える
+// 返す値は、式ならその値、let宣言なら束縛した値(let rec ... and ... なら関数の組)、型宣言なら()
+// let p = e ならパターンの変数の値の組(変数が1つならその値、なければ e の値)
+pub fn eval_top_level(environment: Environment, top_level: TopLevel) -> EvalResult {
+    match top_level {
+        TopLevel::Expression(expression) => {
+            let (_, value) = eval(environment.clone(), expression)?;
+            Ok((environment, value))
+        }
+        TopLevel::Let { variable, bound } => {
+            let (_, value) = eval(environment.clone(), bound)?;
+            let environment = environment.bind(variable, value.clone())?;
+            Ok((environment, value))
+        }
+        TopLevel::LetPattern { pattern, bound } => {
+            let (_, value) = eval(environment.clone(), bound)?;
+            let Some(bindings) = match_pattern(&environment, &pattern, &value) else {
+                return Err(builtin_exception(&environment, "Match_failure", None));
+            };
+            let mut values = bindings
+                .iter()
+                .map(|(_, value)| value.clone())
+                .collect::<Vec<_>>();
+            let environment = bindings
+                .into_iter()
+                .try_fold(environment, |environment, (variable, value)| {
+                    environment.bind(variable, value)
+                })?;
+            let value = match values.len() {
+                0 => value,
+                1 => values.remove(0),
+                _ => Value::Tuple(values),
+            };
+            Ok((environment, value))
+        }
+        TopLevel::LetRec { bindings } => {
+            let variables = bindings
+                .iter()
+                .map(|(variable, _)| variable.clone())
+                .collect::<Vec<_>>();
+            let environment = bind_recursive_functions(environment, bindings)?;
+            let mut values = variables
+                .iter()
+                .map(|variable| environment.get(variable))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(EvalError::InvalidExpression)?;
+            let value = match values.len() {
+                1 => values.remove(0),
+                _ => Value::Tuple(values),
+            };
+            Ok((environment, value))
+        }
+        TopLevel::Exception { name, .. } => {
+            let environment = environment.bind_constructor(name, exception_tag());
+            Ok((environment, Value::Unit))
+        }
+        TopLevel::TypeDefinition(definitions) => Ok((
+            bind_type_definitions(environment, &definitions),
+            Value::Unit,
+        )),
+        TopLevel::ModuleType { .. } => Ok((environment, Value::Unit)),
+        TopLevel::Module { name, module } => {
+            let environment = match eval_module(&environment, module)? {
+                ModuleValue::Structure(namespace) => environment.bind_module(name, namespace),
+                ModuleValue::Functor(functor) => environment.bind_functor(name, functor),
+            };
+            Ok((environment, Value::Unit))
+        }
+        TopLevel::Open(path) => {
+            let namespace = get_module(&environment, &path)?.clone();
+            Ok((environment.open(&namespace), Value::Unit))
+        }
+    }
+}
+
+// モジュール式の値。構造はモジュールの中の名前を持つ環境になる
+enum ModuleValue {
+    Structure(Environment),
+    Functor(Functor),
+}
+
+// シグネチャで隠した名前は型検査で使えなくなっているので、評価では区別しない
+fn eval_module(environment: &Environment, module: ModuleExpression) -> Result<ModuleValue> {
+    match module {
+        ModuleExpression::Structure(top_levels) => {
+            let inner =
+                top_levels
+                    .iter()
+                    .try_fold(environment.clone(), |environment, top_level| {
+                        eval_top_level(environment, top_level.clone())
+                            .map(|(environment, _)| environment)
+                    })?;
+            Ok(ModuleValue::Structure(inner.export(&top_levels)))
+        }
+        ModuleExpression::Path(path) => match environment.get_functor(&path) {
+            Some(functor) => Ok(ModuleValue::Functor(functor.clone())),
+            None => Ok(ModuleValue::Structure(
+                get_module(environment, &path)?.clone(),
+            )),
+        },
+        ModuleExpression::Ascription { module, .. } => eval_module(environment, *module),
+        ModuleExpression::Functor {
+            parameter, body, ..
+        } => Ok(ModuleValue::Functor(Functor {
+            parameter,
+            body: *body,
+            environment: environment.clone(),
+        })),
+        ModuleExpression::Application { functor, argument } => {
+            let (ModuleValue::Functor(functor), ModuleValue::Structure(argument)) = (
+                eval_module(environment, *functor)?,
+                eval_module(environment, *argument)?,
+            ) else {
+                bail!(EvalError::InvalidExpression);
+            };
+            let environment = functor.environment.bind_module(functor.parameter, argument);
+            eval_module(&environment, functor.body)
+        }
     }
 }
 
+fn get_module<'a>(environment: &'a Environment, path: &Symbol) -> Result<&'a Environment> {
+    environment
+        .get_module(path)
+        .ok_or_else(|| anyhow!(EvalError::UndefinedModule(path.clone())))
+}
+
+fn eval_open(environment: Environment, module: Symbol, body: Expression) -> EvalResult {
+    let namespace = get_module(&environment, &module)?.clone();
+    let (_, value) = eval(environment.clone().open(&namespace), body)?;
+
+    Ok((environment, value))
+}
+
 fn eval_integer(environment: Environment, n: RInteger) -> EvalResult {
     Ok((environment, Value::Integer(n)))
 }
@@ -76,10 +321,18 @@ fn eval_bool(environment: Environment, b: RBool) -> EvalResult {
     Ok((environment, Value::Bool(b)))
 }
 
+fn eval_string(environment: Environment, s: RString) -> EvalResult {
+    Ok((environment, Value::String(s)))
+}
+
+fn eval_char(environment: Environment, c: RChar) -> EvalResult {
+    Ok((environment, Value::Char(c)))
+}
+
 fn eval_variable(environment: Environment, variable: Symbol) -> EvalResult {
     let value = environment
         .get(&variable)
-        .ok_or(anyhow!(EvalError::UndefinedVariable(variable.clone())))?;
+        .ok_or_else(|| anyhow!(EvalError::UndefinedVariable(variable.clone())))?;
 
     Ok((environment, value))
 }
@@ -94,10 +347,21 @@ fn eval_arithmetic_operation(
     let (_, expression2) = eval(environment.clone(), expression2)?;
 
     match (expression1, expression2) {
-        (Value::Integer(expression1_value), Value::Integer(expression2_value)) => Ok((
-            environment,
-            Value::Integer(operation(expression1_value, expression2_value)),
-        )),
+        (Value::Integer(expression1_value), Value::Integer(expression2_value)) => {
+            match operation(expression1_value, expression2_value) {
+                Some(n) => Ok((environment, Value::Integer(n))),
+                None => Err(builtin_exception(&environment, "Division_by_zero", None)),
+            }
+        }
+        _ => bail!(EvalError::InvalidExpression),
+    }
+}
+
+fn eval_negation(environment: Environment, expression: Expression) -> EvalResult {
+    let (_, operand) = eval(environment.clone(), expression)?;
+
+    match operand {
+        Value::Integer(n) => Ok((environment, Value::Integer(r_negate(n)))),
         _ => bail!(EvalError::InvalidExpression),
     }
 }
@@ -111,15 +375,180 @@ fn eval_comparison_operation(
     let (_, expression1) = eval(environment.clone(), expression1)?;
     let (_, expression2) = eval(environment.clone(), expression2)?;
 
+    let ordering = compare_values(&environment, &expression1, &expression2)?;
+
+    Ok((environment, Value::Bool(operation(ordering))))
+}
+
+// OCamlのcompareと同じく値を構造的に比較する。関数値の比較は例外になる
+fn compare_values(environment: &Environment, value1: &Value, value2: &Value) -> Result<Ordering> {
+    let ordering = match (value1, value2) {
+        (Value::Integer(n1), Value::Integer(n2)) => n1.cmp(n2),
+        (Value::Bool(b1), Value::Bool(b2)) => b1.cmp(b2),
+        (Value::String(s1), Value::String(s2)) => s1.cmp(s2),
+        (Value::Char(c1), Value::Char(c2)) => c1.cmp(c2),
+        (Value::Unit, Value::Unit) | (Value::Nil, Value::Nil) => Ordering::Equal,
+        (Value::Nil, Value::Cons { .. }) => Ordering::Less,
+        (Value::Cons { .. }, Value::Nil) => Ordering::Greater,
+        (
+            Value::Cons {
+                car: car1,
+                cdr: cdr1,
+            },
+            Value::Cons {
+                car: car2,
+                cdr: cdr2,
+            },
+        ) => match compare_values(environment, car1, car2)? {
+            Ordering::Equal => compare_values(environment, cdr1, cdr2)?,
+            ordering => ordering,
+        },
+        (Value::Tuple(values1), Value::Tuple(values2)) => {
+            compare_all(environment, values1, values2)?
+        }
+        // 引数のない構成子は引数をとる構成子より小さく、それ以外は型で宣言した順に並ぶ
+        (
+            Value::Constructor {
+                name: name1,
+                tag: tag1,
+                argument: argument1,
+            },
+            Value::Constructor {
+                name: name2,
+                tag: tag2,
+                argument: argument2,
+            },
+        ) => match (argument1, argument2) {
+            (None, Some(_)) => Ordering::Less,
+            (Some(_), None) => Ordering::Greater,
+            _ if (tag1, name1) != (tag2, name2) => tag1.cmp(tag2).then_with(|| name1.cmp(name2)),
+            (Some(argument1), Some(argument2)) => {
+                compare_values(environment, argument1, argument2)?
+            }
+            _ => Ordering::Equal,
+        },
+        // 同じ場所を指すレコードや参照は中身を見ずに等しいとし、自分自身を指す値でも比較が止まるようにする
+        (Value::Record(fields1), Value::Record(fields2)) if fields1 == fields2 => Ordering::Equal,
+        (Value::Ref(location1), Value::Ref(location2)) if location1 == location2 => Ordering::Equal,
+        // レコードのフィールドは型で宣言した順に並んでいるので、その順に比較する
+        (Value::Record(fields1), Value::Record(fields2)) => {
+            let values = |fields: &Vec<(Symbol, Location)>| {
+                fields
+                    .iter()
+                    .map(|(_, location)| location.get())
+                    .collect::<Vec<_>>()
+            };
+            compare_all(environment, &values(fields1), &values(fields2))?
+        }
+        (Value::Ref(location1), Value::Ref(location2)) => {
+            compare_values(environment, &location1.get(), &location2.get())?
+        }
+        (
+            Value::Closure { .. } | Value::RecClosure { .. } | Value::Primitive { .. },
+            Value::Closure { .. } | Value::RecClosure { .. } | Value::Primitive { .. },
+        ) => {
+            return Err(builtin_exception(
+                environment,
+                "Invalid_argument",
+                Some(Value::String("compare: functional value".to_string())),
+            ));
+        }
+        _ => bail!(EvalError::InvalidExpression),
+    };
+
+    Ok(ordering)
+}
+
+fn compare_all(
+    environment: &Environment,
+    values1: &[Value],
+    values2: &[Value],
+) -> Result<Ordering> {
+    for (value1, value2) in values1.iter().zip(values2) {
+        match compare_values(environment, value1, value2)? {
+            Ordering::Equal => continue,
+            ordering => return Ok(ordering),
+        }
+    }
+
+    Ok(values1.len().cmp(&values2.len()))
+}
+
+// && と || は左辺だけで結果が決まるときは右辺を評価しない
+fn eval_and(
+    environment: Environment,
+    expression1: Expression,
+    expression2: Expression,
+) -> EvalResult {
+    let (_, expression1) = eval(environment.clone(), expression1)?;
+
+    match expression1 {
+        Value::Bool(true) => eval(environment, expression2),
+        Value::Bool(false) => Ok((environment, Value::Bool(false))),
+        _ => bail!(EvalError::InvalidExpression),
+    }
+}
+
+fn eval_or(
+    environment: Environment,
+    expression1: Expression,
+    expression2: Expression,
+) -> EvalResult {
+    let (_, expression1) = eval(environment.clone(), expression1)?;
+
+    match expression1 {
+        Value::Bool(true) => Ok((environment, Value::Bool(true))),
+        Value::Bool(false) => eval(environment, expression2),
+        _ => bail!(EvalError::InvalidExpression),
+    }
+}
+
+fn eval_concat(
+    environment: Environment,
+    expression1: Expression,
+    expression2: Expression,
+) -> EvalResult {
+    let (_, expression1) = eval(environment.clone(), expression1)?;
+    let (_, expression2) = eval(environment.clone(), expression2)?;
+
     match (expression1, expression2) {
-        (Value::Integer(expression1_value), Value::Integer(expression2_value)) => Ok((
+        (Value::String(expression1_value), Value::String(expression2_value)) => Ok((
             environment,
-            Value::Bool(operation(expression1_value, expression2_value)),
+            Value::String(r_concat(expression1_value, &expression2_value)),
         )),
         _ => bail!(EvalError::InvalidExpression),
     }
 }
 
+fn eval_append(
+    environment: Environment,
+    expression1: Expression,
+    expression2: Expression,
+) -> EvalResult {
+    let (_, expression1) = eval(environment.clone(), expression1)?;
+    let (_, expression2) = eval(environment.clone(), expression2)?;
+
+    let mut elements = Vec::new();
+    let mut rest = expression1;
+    while let Value::Cons { car, cdr } = rest {
+        elements.push(car.as_ref().clone());
+        rest = cdr.as_ref().clone();
+    }
+    if rest != Value::Nil {
+        bail!(EvalError::InvalidExpression);
+    }
+
+    let appended = elements
+        .into_iter()
+        .rev()
+        .fold(expression2, |cdr, car| Value::Cons {
+            car: car.into(),
+            cdr: cdr.into(),
+        });
+
+    Ok((environment, appended))
+}
+
 fn eval_if(
     environment: Environment,
     predicate: Expression,
@@ -142,9 +571,19 @@ fn eval_let(
     body: Expression,
 ) -> EvalResult {
     let (_, bound) = eval(environment.clone(), bound)?;
-    let new_environment = environment.bind(variable, bound)?;
+    let new_environment = environment.clone().bind(variable, bound)?;
+    let (_, value) = eval(new_environment, body)?;
 
-    eval(new_environment, body)
+    Ok((environment, value))
+}
+
+fn eval_sequence(
+    environment: Environment,
+    expression1: Expression,
+    expression2: Expression,
+) -> EvalResult {
+    eval(environment.clone(), expression1)?;
+    eval(environment, expression2)
 }
 
 fn eval_fun(environment: Environment, parameter: Symbol, body: Expression) -> EvalResult {
@@ -177,51 +616,129 @@ fn eval_app(environment: Environment, function: Expression, argument: Expression
         Value::RecClosure {
             environment,
             call_name,
-            parameter,
-            body,
+            functions,
         } => {
-            let rec_closure = Value::RecClosure {
-                environment: environment.clone(),
-                call_name: call_name.clone(),
-                parameter: parameter.clone(),
-                body: body.clone(),
+            let Some(function) = functions
+                .iter()
+                .find(|function| function.name == call_name)
+                .cloned()
+            else {
+                bail!(EvalError::UndefinedVariable(call_name));
             };
-            let environment = environment.bind(call_name, rec_closure)?;
-            let captured_environment = environment.bind(parameter, argument)?;
+            let environment = bind_rec_closures(environment, functions)?;
+            let captured_environment = environment.bind(function.parameter, argument)?;
 
-            eval(captured_environment, body)
+            eval(captured_environment, function.body)
+        }
+        Value::Primitive {
+            name,
+            arity,
+            mut arguments,
+            function,
+        } => {
+            arguments.push(argument);
+            if arguments.len() < arity {
+                let partial = Value::Primitive {
+                    name,
+                    arity,
+                    arguments,
+                    function,
+                };
+                return Ok((environment, partial));
+            }
+
+            Ok((environment, function.call(arguments)?))
         }
         _ => bail!(EvalError::InvalidExpression),
     }
 }
 
+// Rustで実装した関数から例外を送出するときに使う
+pub fn raise(exception: Value) -> anyhow::Error {
+    anyhow!(EvalError::Exception(exception.into()))
+}
+
+// 評価器が送出する組み込みの例外。番号は組み込みの定義を読み込んだときに環境に控えたものを使う
+fn builtin_exception(
+    environment: &Environment,
+    name: &str,
+    argument: Option<Value>,
+) -> anyhow::Error {
+    let Some(tag) = environment.get_builtin_exception(name) else {
+        return anyhow!(EvalError::UndefinedBuiltinException(name.to_string()));
+    };
+    raise(Value::Constructor {
+        name: name.to_string(),
+        tag,
+        argument: argument.map(Box::new),
+    })
+}
+
+// 例外の宣言ごとに新しい番号を与える。宣言されていない構築子の番号0とは重ならない
+fn exception_tag() -> usize {
+    static NEXT_EXCEPTION_TAG: AtomicUsize = AtomicUsize::new(1);
+
+    NEXT_EXCEPTION_TAG.fetch_add(1, AtomicOrdering::Relaxed)
+}
+
 fn eval_let_rec(
     environment: Environment,
-    variable: Symbol,
-    bound_function: Expression,
+    bindings: Vec<(Symbol, Expression)>,
     body: Expression,
 ) -> EvalResult {
-    if let Expression::Fun {
-        parameter,
-        body: function_body,
-    } = bound_function
-    {
-        let captured_environment = environment.clone();
-        let call_name = variable.clone();
-        let environment = environment.bind(
-            variable,
-            Value::RecClosure {
-                environment: captured_environment,
-                call_name,
-                parameter,
-                body: *function_body,
+    let new_environment = bind_recursive_functions(environment.clone(), bindings)?;
+    let (_, value) = eval(new_environment, body)?;
+
+    Ok((environment, value))
+}
+
+// 同時に定義した関数の名前をすべて、組全体を持つ再帰クロージャに束縛する
+fn bind_recursive_functions(
+    environment: Environment,
+    bindings: Vec<(Symbol, Expression)>,
+) -> Result<Environment> {
+    let functions = bindings
+        .into_iter()
+        .map(
+            |(name, bound_function)| match strip_annotation(bound_function) {
+                Expression::Fun { parameter, body } => Ok(RecursiveFunction {
+                    name,
+                    parameter,
+                    body: *body,
+                }),
+                _ => bail!(EvalError::InvalidExpression),
             },
-        )?;
+        )
+        .collect::<Result<Vec<_>>>()?;
+
+    bind_rec_closures(environment, functions)
+}
 
-        return eval(environment, body);
+// 型注釈は評価に関係しないので取り除いてから関数を取り出す
+fn strip_annotation(expression: Expression) -> Expression {
+    match expression {
+        Expression::Annotation { expression, .. } => strip_annotation(*expression),
+        expression => expression,
     }
+}
 
-    eval(environment, body)
+// クロージャが捕捉するのは束縛する前の環境で、呼び出すたびに組の名前を束縛し直す
+fn bind_rec_closures(
+    environment: Environment,
+    functions: Vec<RecursiveFunction>,
+) -> Result<Environment> {
+    functions
+        .iter()
+        .try_fold(environment.clone(), |new_environment, function| {
+            new_environment.bind(
+                function.name.clone(),
+                Value::RecClosure {
+                    environment: environment.clone(),
+                    call_name: function.name.clone(),
+                    functions: functions.clone(),
+                },
+            )
+        })
 }
 
 fn eval_nil(environment: Environment) -> EvalResult {
@@ -244,88 +761,634 @@ fn eval_cons(environment: Environment, car: Expression, cdr: Expression) -> Eval
 fn eval_match(
     environment: Environment,
     scrutinee: Expression,
-    nil_case: Expression,
-    cons_pattern: (Symbol, Symbol, Expression),
+    arms: Vec<(Pattern, Expression)>,
 ) -> EvalResult {
-    let (_, pattern) = eval(environment.clone(), scrutinee)?;
+    let (_, scrutinee) = eval(environment.clone(), scrutinee)?;
+
+    match eval_arms(&environment, &scrutinee, arms)? {
+        Some(value) => Ok((environment, value)),
+        None => Err(builtin_exception(&environment, "Match_failure", None)),
+    }
+}
 
-    match pattern {
-        Value::Nil => eval(environment, nil_case),
-        Value::Cons { car, cdr } => {
-            let (car_variable, cdr_variable, cons_case) = cons_pattern;
-            let environment = environment
-                .bind(car_variable, *car)?
-                .bind(cdr_variable, *cdr)?;
+// 最初に照合した腕の本体を評価する。どの腕にも照合しなければNoneを返す
+fn eval_arms(
+    environment: &Environment,
+    scrutinee: &Value,
+    arms: Vec<(Pattern, Expression)>,
+) -> Result<Option<Value>> {
+    for (pattern, body) in arms {
+        let Some(bindings) = match_pattern(environment, &pattern, scrutinee) else {
+            continue;
+        };
+        let arm_environment = bindings
+            .into_iter()
+            .try_fold(environment.clone(), |environment, (variable, value)| {
+                environment.bind(variable, value)
+            })?;
+        let (_, value) = eval(arm_environment, body)?;
 
-            eval(environment, cons_case)
-        }
-        _ => bail!(EvalError::InvalidExpression),
+        return Ok(Some(value));
     }
+
+    Ok(None)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn eval_raise(environment: Environment, expression: Expression) -> EvalResult {
+    let (_, exception) = eval(environment, expression)?;
 
-    #[test]
-    fn test_simple_arithmetic() {
-        // 3 + 5 * 2
-        let expr = Expression::Plus {
-            expression1: Expression::Integer(3).into(),
-            expression2: Expression::Times {
-                expression1: Expression::Integer(5).into(),
-                expression2: Expression::Integer(2).into(),
-            }
-            .into(),
-        };
+    bail!(EvalError::Exception(exception.into()))
+}
 
-        let result = eval(Environment::default(), expr);
+// 例外でないエラーや、どの腕にも照合しなかった例外はそのまま伝える
+fn eval_try(
+    environment: Environment,
+    body: Expression,
+    arms: Vec<(Pattern, Expression)>,
+) -> EvalResult {
+    let error = match eval(environment.clone(), body) {
+        Err(error) => error,
+        result => return result.map(|(_, value)| (environment, value)),
+    };
+    let Some(EvalError::Exception(exception)) = error.downcast_ref::<EvalError>() else {
+        return Err(error);
+    };
 
-        assert!(result.is_ok());
-        let (_, value) = result.unwrap();
-        assert!(matches!(value, Value::Integer(13)));
+    match eval_arms(&environment, exception, arms)? {
+        Some(value) => Ok((environment, value)),
+        None => Err(error),
     }
+}
 
-    #[test]
-    fn test_let_binding() {
-        // let x = 10 in x + 5
-        let expr = Expression::Let {
-            variable: "x".to_string(),
-            bound: Expression::Integer(10).into(),
-            body: Expression::Plus {
-                expression1: Expression::Variable("x".to_string()).into(),
-                expression2: Expression::Integer(5).into(),
-            }
-            .into(),
-        };
-
-        let result = eval(Environment::default(), expr);
+fn eval_assert(environment: Environment, expression: Expression) -> EvalResult {
+    let (_, condition) = eval(environment.clone(), expression)?;
 
-        assert!(result.is_ok());
-        let (_, value) = result.unwrap();
-        assert!(matches!(value, Value::Integer(15)));
+    match condition {
+        Value::Bool(true) => Ok((environment, Value::Unit)),
+        Value::Bool(false) => Err(builtin_exception(&environment, "Assert_failure", None)),
+        _ => bail!(EvalError::InvalidExpression),
     }
+}
 
-    #[test]
-    fn test_if_expression() {
-        // if 5 < 10 then 20 else 30
-        let expr = Expression::If {
-            predicate: Expression::LessThan {
-                expression1: Expression::Integer(5).into(),
-                expression2: Expression::Integer(10).into(),
+// 構築子は名前と番号の両方が同じときだけ照合する
+fn match_pattern(
+    environment: &Environment,
+    pattern: &Pattern,
+    value: &Value,
+) -> Option<Vec<(Symbol, Value)>> {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => Some(vec![]),
+        (Pattern::Variable(variable), value) => Some(vec![(variable.clone(), value.clone())]),
+        (Pattern::Integer(expected), Value::Integer(actual)) if expected == actual => Some(vec![]),
+        (Pattern::Bool(expected), Value::Bool(actual)) if expected == actual => Some(vec![]),
+        (Pattern::String(expected), Value::String(actual)) if expected == actual => Some(vec![]),
+        (Pattern::Char(expected), Value::Char(actual)) if expected == actual => Some(vec![]),
+        (Pattern::Unit, Value::Unit) => Some(vec![]),
+        (Pattern::Nil, Value::Nil) => Some(vec![]),
+        (
+            Pattern::Cons {
+                car: car_pattern,
+                cdr: cdr_pattern,
+            },
+            Value::Cons { car, cdr },
+        ) => Some(
+            [
+                match_pattern(environment, car_pattern, car)?,
+                match_pattern(environment, cdr_pattern, cdr)?,
+            ]
+            .concat(),
+        ),
+        (Pattern::Tuple(patterns), Value::Tuple(values)) if patterns.len() == values.len() => {
+            let bindings = patterns
+                .iter()
+                .zip(values)
+                .map(|(pattern, value)| match_pattern(environment, pattern, value))
+                .collect::<Option<Vec<_>>>()?;
+            Some(bindings.concat())
+        }
+        (
+            Pattern::Constructor {
+                name: expected,
+                argument: argument_pattern,
+            },
+            Value::Constructor {
+                name,
+                tag,
+                argument,
+            },
+        ) if base_name(expected) == name
+            && environment.get_constructor(expected).unwrap_or_default() == *tag =>
+        {
+            match (argument_pattern, argument) {
+                (Some(argument_pattern), Some(argument)) => {
+                    match_pattern(environment, argument_pattern, argument)
+                }
+                (None, None) => Some(vec![]),
+                _ => None,
             }
-            .into(),
-            consequent: Expression::Integer(20).into(),
-            alternative: Expression::Integer(30).into(),
-        };
+        }
+        (Pattern::Record(patterns), Value::Record(fields)) => {
+            let bindings = patterns
+                .iter()
+                .map(|(label, pattern)| {
+                    let (_, location) = fields.iter().find(|(name, _)| name == label)?;
+                    match_pattern(environment, pattern, &location.get())
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(bindings.concat())
+        }
+        _ => None,
+    }
+}
 
-        let result = eval(Environment::default(), expr);
+fn eval_type_definition(
+    environment: Environment,
+    definitions: &[TypeDefinition],
+    body: Expression,
+) -> EvalResult {
+    let (_, value) = eval(
+        bind_type_definitions(environment.clone(), definitions),
+        body,
+    )?;
+
+    Ok((environment, value))
+}
+
+// 型の構築子とレコードのラベルに、型の中で宣言した順の番号をつける
+fn bind_type_definitions(environment: Environment, definitions: &[TypeDefinition]) -> Environment {
+    definitions
+        .iter()
+        .fold(environment, |environment, definition| {
+            match &definition.kind {
+                TypeKind::Variant(constructors) => constructors.iter().enumerate().fold(
+                    environment,
+                    |environment, (tag, (constructor, _))| {
+                        environment.bind_constructor(constructor.clone(), tag)
+                    },
+                ),
+                TypeKind::Record(fields) => {
+                    fields
+                        .iter()
+                        .enumerate()
+                        .fold(environment, |environment, (index, field)| {
+                            environment.bind_label(field.label.clone(), index)
+                        })
+                }
+                TypeKind::Abstract | TypeKind::Alias(_) => environment,
+            }
+        })
+}
+
+fn eval_tuple(environment: Environment, expressions: Vec<Expression>) -> EvalResult {
+    let values = expressions
+        .into_iter()
+        .map(|expression| eval(environment.clone(), expression).map(|(_, value)| value))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((environment, Value::Tuple(values)))
+}
+
+fn eval_constructor(
+    environment: Environment,
+    name: Symbol,
+    argument: Option<Expression>,
+) -> EvalResult {
+    let argument = match argument {
+        Some(argument) => {
+            let (_, argument) = eval(environment.clone(), argument)?;
+            Some(argument.into())
+        }
+        None => None,
+    };
+
+    // M.A と open M をした後の A が同じ値になるように、構築子は経路を除いた名前で持つ
+    let tag = environment.get_constructor(&name).unwrap_or_default();
+    let name = base_name(&name).to_string();
+
+    Ok((
+        environment,
+        Value::Constructor {
+            name,
+            tag,
+            argument,
+        },
+    ))
+}
+
+// フィールドは書いた順に評価し、型で宣言した順に並べる
+fn eval_record(environment: Environment, fields: Vec<(Symbol, Expression)>) -> EvalResult {
+    let mut fields = eval_fields(&environment, fields)?;
+    fields.sort_by_key(|(label, _)| environment.get_label(label).unwrap_or(usize::MAX));
+
+    Ok((environment, Value::Record(fields)))
+}
+
+fn eval_field(environment: Environment, record: Expression, label: Symbol) -> EvalResult {
+    let (_, record) = eval(environment.clone(), record)?;
+    let value = get_field(&record, &label)?.get();
+
+    Ok((environment, value))
+}
+
+// レコードを複製してから指定されたフィールドを置き換える。元のレコードとは場所を共有しない
+fn eval_record_update(
+    environment: Environment,
+    record: Expression,
+    fields: Vec<(Symbol, Expression)>,
+) -> EvalResult {
+    let (_, record) = eval(environment.clone(), record)?;
+    let Value::Record(original_fields) = record else {
+        bail!(EvalError::InvalidExpression);
+    };
+    let updated_fields = eval_fields(&environment, fields)?;
+
+    let fields = original_fields
+        .into_iter()
+        .map(
+            |(label, location)| match updated_fields.iter().find(|(name, _)| *name == label) {
+                Some((_, updated)) => (label, updated.clone()),
+                None => (label, Location::new(location.get())),
+            },
+        )
+        .collect();
+
+    Ok((environment, Value::Record(fields)))
+}
+
+fn eval_field_assignment(
+    environment: Environment,
+    record: Expression,
+    label: Symbol,
+    value: Expression,
+) -> EvalResult {
+    let (_, record) = eval(environment.clone(), record)?;
+    let (_, value) = eval(environment.clone(), value)?;
+    get_field(&record, &label)?.set(value);
+
+    Ok((environment, Value::Unit))
+}
+
+// 参照は新しい場所を確保し、!と:=はその場所を読み書きする
+fn eval_ref(environment: Environment, expression: Expression) -> EvalResult {
+    let (_, value) = eval(environment.clone(), expression)?;
+
+    Ok((environment, Value::Ref(Location::new(value))))
+}
+
+fn eval_deref(environment: Environment, expression: Expression) -> EvalResult {
+    let (_, reference) = eval(environment.clone(), expression)?;
+
+    match reference {
+        Value::Ref(location) => Ok((environment, location.get())),
+        _ => bail!(EvalError::InvalidExpression),
+    }
+}
+
+fn eval_assign(environment: Environment, reference: Expression, value: Expression) -> EvalResult {
+    let (_, reference) = eval(environment.clone(), reference)?;
+    let (_, value) = eval(environment.clone(), value)?;
+
+    match reference {
+        Value::Ref(location) => {
+            location.set(value);
+            Ok((environment, Value::Unit))
+        }
+        _ => bail!(EvalError::InvalidExpression),
+    }
+}
+
+fn eval_fields(
+    environment: &Environment,
+    fields: Vec<(Symbol, Expression)>,
+) -> Result<Vec<(Symbol, Location)>> {
+    fields
+        .into_iter()
+        .map(|(label, expression)| {
+            let (_, value) = eval(environment.clone(), expression)?;
+            Ok((label, Location::new(value)))
+        })
+        .collect()
+}
+
+fn get_field(record: &Value, label: &Symbol) -> Result<Location> {
+    let Value::Record(fields) = record else {
+        bail!(EvalError::InvalidExpression);
+    };
+
+    fields
+        .iter()
+        .find(|(name, _)| name == label)
+        .map(|(_, location)| location.clone())
+        .ok_or_else(|| anyhow!(EvalError::UndefinedField(label.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{
+        parser::{parse, parse_program},
+        tokenizer::tokenize,
+    };
+    use crate::builtin::bare_environments;
+    use crate::syntax::ast::ModuleType;
+    use crate::type_system::types::{BaseType, Type};
+
+    // 評価器が送出する組み込みの例外を宣言した環境
+    fn builtin_environment() -> Environment {
+        let (environment, _) = bare_environments().unwrap();
+        environment
+    }
+
+    fn eval_source(source: &str) -> Result<Value> {
+        let expression = parse(tokenize(source.to_string())?)?;
+        let (_, value) = eval(builtin_environment(), expression)?;
+        Ok(value)
+    }
+
+    fn eval_program(source: &str) -> Result<Value> {
+        let program = parse_program(tokenize(source.to_string())?)?;
+        let (_, value) = program.top_levels.into_iter().try_fold(
+            (builtin_environment(), Value::Unit),
+            |(environment, _), top_level| eval_top_level(environment, top_level),
+        )?;
+        Ok(value)
+    }
+
+    #[test]
+    fn test_simple_arithmetic() {
+        // 3 + 5 * 2
+        let expr = Expression::Plus {
+            expression1: Expression::Integer(3).into(),
+            expression2: Expression::Times {
+                expression1: Expression::Integer(5).into(),
+                expression2: Expression::Integer(2).into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(13)));
+    }
+
+    #[test]
+    fn test_division_and_modulo() {
+        // -7 / 2 + -7 mod 2
+        let expr = Expression::Plus {
+            expression1: Expression::Divide {
+                expression1: Expression::Negate(Expression::Integer(7).into()).into(),
+                expression2: Expression::Integer(2).into(),
+            }
+            .into(),
+            expression2: Expression::Modulo {
+                expression1: Expression::Integer(-7).into(),
+                expression2: Expression::Integer(2).into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(-4));
+    }
+
+    #[test]
+    fn test_division_by_zero() {
+        // 1 / 0
+        let expr = Expression::Divide {
+            expression1: Expression::Integer(1).into(),
+            expression2: Expression::Integer(0).into(),
+        };
+
+        let result = eval(builtin_environment(), expr);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Exception: Division_by_zero"
+        );
+
+        // 同じ名前で宣言し直した例外は、組み込みの例外とは照合しない
+        let result =
+            eval_program("exception Division_by_zero;; try 1 / 0 with Division_by_zero -> 7");
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Exception: Division_by_zero"
+        );
+
+        let result = eval(
+            Environment::default(),
+            Expression::Divide {
+                expression1: Expression::Integer(1).into(),
+                expression2: Expression::Integer(0).into(),
+            },
+        );
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unbound built-in exception Division_by_zero"
+        );
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        // false && 1 / 0 = 0
+        let expr = Expression::And {
+            expression1: Expression::Bool(false).into(),
+            expression2: Expression::Equal {
+                expression1: Expression::Divide {
+                    expression1: Expression::Integer(1).into(),
+                    expression2: Expression::Integer(0).into(),
+                }
+                .into(),
+                expression2: Expression::Integer(0).into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Bool(false));
+    }
+
+    #[test]
+    fn test_structural_comparison() {
+        // (1, 2 :: []) < (1, 2 :: 3 :: [])
+        let list = |ns: Vec<isize>| {
+            ns.into_iter()
+                .rev()
+                .fold(Expression::Nil, |cdr, n| Expression::Cons {
+                    car: Expression::Integer(n).into(),
+                    cdr: cdr.into(),
+                })
+        };
+        let expr = Expression::LessThan {
+            expression1: Expression::Tuple(vec![Expression::Integer(1), list(vec![2])]).into(),
+            expression2: Expression::Tuple(vec![Expression::Integer(1), list(vec![2, 3])]).into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Bool(true));
+    }
+
+    #[test]
+    fn test_compare_in_declaration_order() {
+        let declarations = "
+            type color = Red | Green | Blue;;
+            type p = { b : int; a : int };;
+        ";
+        let cases = [
+            ("Red < Green", "true"),
+            ("Blue > Red", "true"),
+            ("{ b = 1; a = 2 } < { b = 2; a = 1 }", "true"),
+            ("{ a = 2; b = 1 }", "{ b = 1; a = 2 }"),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_program(&format!("{declarations}{source}"));
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_compare_functional_value() {
+        // (fun x -> x) = (fun x -> x)
+        let id = || Expression::Fun {
+            parameter: "x".to_string(),
+            body: Expression::Variable("x".to_string()).into(),
+        };
+        let expr = Expression::Equal {
+            expression1: id().into(),
+            expression2: id().into(),
+        };
+
+        let result = eval(builtin_environment(), expr);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Exception: Invalid_argument \"compare: functional value\""
+        );
+    }
+
+    #[test]
+    fn test_let_binding() {
+        // let x = 10 in x + 5
+        let expr = Expression::Let {
+            variable: "x".to_string(),
+            bound: Expression::Integer(10).into(),
+            body: Expression::Plus {
+                expression1: Expression::Variable("x".to_string()).into(),
+                expression2: Expression::Integer(5).into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(15)));
+    }
+
+    #[test]
+    fn test_if_expression() {
+        // if 5 < 10 then 20 else 30
+        let expr = Expression::If {
+            predicate: Expression::LessThan {
+                expression1: Expression::Integer(5).into(),
+                expression2: Expression::Integer(10).into(),
+            }
+            .into(),
+            consequent: Expression::Integer(20).into(),
+            alternative: Expression::Integer(30).into(),
+        };
+
+        let result = eval(Environment::default(), expr);
 
         assert!(result.is_ok());
         let (_, value) = result.unwrap();
         assert!(matches!(value, Value::Integer(20)));
     }
 
+    #[test]
+    fn test_string_operations() {
+        // if "abc" < "abd" then "foo" ^ "bar" else ""
+        let expr = Expression::If {
+            predicate: Expression::LessThan {
+                expression1: Expression::String("abc".to_string()).into(),
+                expression2: Expression::String("abd".to_string()).into(),
+            }
+            .into(),
+            consequent: Expression::Concat {
+                expression1: Expression::String("foo".to_string()).into(),
+                expression2: Expression::String("bar".to_string()).into(),
+            }
+            .into(),
+            alternative: Expression::String(String::new()).into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::String("foobar".to_string()));
+    }
+
+    #[test]
+    fn test_char_comparison() {
+        // 'b' < 'a'
+        let expr = Expression::LessThan {
+            expression1: Expression::Char('b').into(),
+            expression2: Expression::Char('a').into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Bool(false));
+    }
+
+    #[test]
+    fn test_string_value_is_escaped() {
+        let value = Value::String("say \"hi\"\n".to_string());
+
+        assert_eq!(value.to_string(), "\"say \\\"hi\\\"\\n\"");
+        assert_eq!(Value::Char('\'').to_string(), "'\\''");
+    }
+
+    #[test]
+    fn test_constructor_argument_is_parenthesized() {
+        let cases = [
+            ("type t = A of int | B of t;; A (-1)", "A (-1)"),
+            ("type t = A of int | B of t;; B (A 1)", "B (A 1)"),
+            ("type t = A of int | B of t;; A 1", "A 1"),
+            ("type t = A of (int * int);; A (1, -2)", "A (1, -2)"),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_program(source);
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap().to_string(), expected);
+        }
+    }
+
     #[test]
     fn test_function_application() {
         // (fun x -> x + 1) 5
@@ -353,33 +1416,34 @@ mod tests {
     fn test_recursive_function() {
         // let rec fact = fun n -> if n < 2 then 1 else n * fact (n - 1) in fact 5
         let expr = Expression::LetRec {
-            variable: "fact".to_string(),
-            bound_function: Expression::Fun {
-                parameter: "n".to_string(),
-                body: Expression::If {
-                    predicate: Expression::LessThan {
-                        expression1: Expression::Variable("n".to_string()).into(),
-                        expression2: Expression::Integer(2).into(),
-                    }
-                    .into(),
-                    consequent: Expression::Integer(1).into(),
-                    alternative: Expression::Times {
-                        expression1: Expression::Variable("n".to_string()).into(),
-                        expression2: Expression::App {
-                            function: Expression::Variable("fact".to_string()).into(),
-                            argument: Expression::Minus {
-                                expression1: Expression::Variable("n".to_string()).into(),
-                                expression2: Expression::Integer(1).into(),
+            bindings: vec![(
+                "fact".to_string(),
+                Expression::Fun {
+                    parameter: "n".to_string(),
+                    body: Expression::If {
+                        predicate: Expression::LessThan {
+                            expression1: Expression::Variable("n".to_string()).into(),
+                            expression2: Expression::Integer(2).into(),
+                        }
+                        .into(),
+                        consequent: Expression::Integer(1).into(),
+                        alternative: Expression::Times {
+                            expression1: Expression::Variable("n".to_string()).into(),
+                            expression2: Expression::App {
+                                function: Expression::Variable("fact".to_string()).into(),
+                                argument: Expression::Minus {
+                                    expression1: Expression::Variable("n".to_string()).into(),
+                                    expression2: Expression::Integer(1).into(),
+                                }
+                                .into(),
                             }
                             .into(),
                         }
                         .into(),
                     }
                     .into(),
-                }
-                .into(),
-            }
-            .into(),
+                },
+            )],
             body: Expression::App {
                 function: Expression::Variable("fact".to_string()).into(),
                 argument: Expression::Integer(5).into(),
@@ -395,24 +1459,316 @@ mod tests {
     }
 
     #[test]
-    fn test_list_operations() {
-        // match 1::2::[] with [] -> 0 | hd::tl -> hd
-        let expr = Expression::Match {
-            scrutinee: Expression::Cons {
-                car: Expression::Integer(1).into(),
-                cdr: Expression::Cons {
-                    car: Expression::Integer(2).into(),
-                    cdr: Expression::Nil.into(),
-                }
-                .into(),
+    fn test_annotated_recursive_function() {
+        // let rec count : int -> int = fun n -> if n < 1 then 0 else 1 + count (n - 1) in count 3
+        let expr = Expression::LetRec {
+            bindings: vec![(
+                "count".to_string(),
+                Expression::Annotation {
+                    expression: Expression::Fun {
+                        parameter: "n".to_string(),
+                        body: Expression::If {
+                            predicate: Expression::LessThan {
+                                expression1: Expression::Variable("n".to_string()).into(),
+                                expression2: Expression::Integer(1).into(),
+                            }
+                            .into(),
+                            consequent: Expression::Integer(0).into(),
+                            alternative: Expression::Plus {
+                                expression1: Expression::Integer(1).into(),
+                                expression2: Expression::App {
+                                    function: Expression::Variable("count".to_string()).into(),
+                                    argument: Expression::Minus {
+                                        expression1: Expression::Variable("n".to_string()).into(),
+                                        expression2: Expression::Integer(1).into(),
+                                    }
+                                    .into(),
+                                }
+                                .into(),
+                            }
+                            .into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                    annotation: Type::Function {
+                        domain: Type::Base(BaseType::Integer).into(),
+                        range: Type::Base(BaseType::Integer).into(),
+                    },
+                },
+            )],
+            body: Expression::App {
+                function: Expression::Variable("count".to_string()).into(),
+                argument: Expression::Integer(3).into(),
             }
             .into(),
-            nil_case: Expression::Integer(0).into(),
-            cons_pattern: (
-                "hd".to_string(),
-                "tl".to_string(),
-                Expression::Variable("hd".to_string()).into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_curried_functions() {
+        let cases = [
+            ("(fun x y z -> x - y - z) 10 2 3", 5),
+            ("let f x y = x * y in f 3 4", 12),
+            (
+                "let rec pow x n = if n = 0 then 1 else x * pow x (n - 1) in pow 2 10",
+                1024,
+            ),
+            ("(function 0 -> 1 | n -> n * 2) 5", 10),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_source(source);
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), Value::Integer(expected));
+        }
+    }
+
+    #[test]
+    fn test_pattern_parameters() {
+        let cases = [
+            ("let f () = 1 in f ()", 1),
+            ("(fun (a, b) -> a - b) (5, 3)", 2),
+            (
+                "let rec sum (n, acc) = if n = 0 then acc else sum (n - 1, acc + n) in sum (4, 0)",
+                10,
+            ),
+            ("let head [x] = x in head [7]", 7),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_source(source);
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap(), Value::Integer(expected));
+        }
+    }
+
+    #[test]
+    fn test_application_operators() {
+        let functions = "
+            let rec map f xs = match xs with [] -> [] | x :: rest -> f x :: map f rest in
+            let rec filter p xs =
+              match xs with [] -> [] | x :: rest -> if p x then x :: filter p rest else filter p rest
+            in
+        ";
+        let cases = [
+            (
+                format!(
+                    "{} [1; 2; 3] |> map (fun x -> x * 2) |> filter (fun x -> x > 2)",
+                    functions
+                ),
+                "[4; 6]",
+            ),
+            (
+                "let succ n = n + 1 in let double n = n * 2 in succ @@ double @@ 1 + 2".to_string(),
+                "7",
+            ),
+            ("let minus a b = a - b in 10 |> minus 3".to_string(), "-7"),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_source(&source);
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_let_operators() {
+        let operators = "
+            type 'a option = None | Some of 'a
+            let ( let* ) o f = match o with None -> None | Some x -> f x
+            let ( and* ) a b = match (a, b) with (Some x, Some y) -> Some (x, y) | _ -> None
+        ";
+        let cases = [
+            (
+                "let r = let* x = Some 1 and* y = Some true and* z = Some \"a\" in Some (x, y, z)",
+                "Some (1, true, \"a\")",
+            ),
+            (
+                "let r = let* x = Some 1 and* y = None and* z = Some 3 in Some (x + y + z)",
+                "None",
+            ),
+            (
+                "let r = let* x = Some 1 in let* y = Some 2 in Some (x + y)",
+                "Some 3",
             ),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_program(&format!("{} {}", operators, source));
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap().to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_list_operations() {
+        // match 1::2::[] with [] -> 0 | hd::tl -> hd
+        let expr = Expression::Match {
+            scrutinee: Expression::Cons {
+                car: Expression::Integer(1).into(),
+                cdr: Expression::Cons {
+                    car: Expression::Integer(2).into(),
+                    cdr: Expression::Nil.into(),
+                }
+                .into(),
+            }
+            .into(),
+            arms: vec![
+                (Pattern::Nil, Expression::Integer(0)),
+                (
+                    Pattern::Cons {
+                        car: Pattern::Variable("hd".to_string()).into(),
+                        cdr: Pattern::Variable("tl".to_string()).into(),
+                    },
+                    Expression::Variable("hd".to_string()),
+                ),
+            ],
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_append() {
+        // (1 :: 2 :: []) @ (3 :: [])
+        let list = |ns: Vec<isize>| {
+            ns.into_iter()
+                .rev()
+                .fold(Expression::Nil, |cdr, n| Expression::Cons {
+                    car: Expression::Integer(n).into(),
+                    cdr: cdr.into(),
+                })
+        };
+        let expr = Expression::Append {
+            expression1: list(vec![1, 2]).into(),
+            expression2: list(vec![3]).into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value.to_string(), "[1; 2; 3]");
+    }
+
+    #[test]
+    fn test_user_defined_operator() {
+        // let ( +++ ) = fun a -> fun b -> a + b * 2 in 1 +++ 2
+        let variable = |name: &str| Expression::Variable(name.to_string());
+        let expr = Expression::Let {
+            variable: "+++".to_string(),
+            bound: Expression::Fun {
+                parameter: "a".to_string(),
+                body: Expression::Fun {
+                    parameter: "b".to_string(),
+                    body: Expression::Plus {
+                        expression1: variable("a").into(),
+                        expression2: Expression::Times {
+                            expression1: variable("b").into(),
+                            expression2: Expression::Integer(2).into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+            body: Expression::App {
+                function: Expression::App {
+                    function: variable("+++").into(),
+                    argument: Expression::Integer(1).into(),
+                }
+                .into(),
+                argument: Expression::Integer(2).into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(5));
+    }
+
+    #[test]
+    fn test_tuple_operations() {
+        // match (1 + 2, true) with (n, b) -> if b then n else 0
+        let expr = Expression::Match {
+            scrutinee: Expression::Tuple(vec![
+                Expression::Plus {
+                    expression1: Expression::Integer(1).into(),
+                    expression2: Expression::Integer(2).into(),
+                },
+                Expression::Bool(true),
+            ])
+            .into(),
+            arms: vec![(
+                Pattern::Tuple(vec![
+                    Pattern::Variable("n".to_string()),
+                    Pattern::Variable("b".to_string()),
+                ]),
+                Expression::If {
+                    predicate: Expression::Variable("b".to_string()).into(),
+                    consequent: Expression::Variable("n".to_string()).into(),
+                    alternative: Expression::Integer(0).into(),
+                },
+            )],
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_match_first_arm_wins() {
+        // match 1 :: [] with _ :: [] -> 1 | x :: _ -> x + 10 | _ -> 0
+        let expr = Expression::Match {
+            scrutinee: Expression::Cons {
+                car: Expression::Integer(1).into(),
+                cdr: Expression::Nil.into(),
+            }
+            .into(),
+            arms: vec![
+                (
+                    Pattern::Cons {
+                        car: Pattern::Wildcard.into(),
+                        cdr: Pattern::Nil.into(),
+                    },
+                    Expression::Integer(1),
+                ),
+                (
+                    Pattern::Cons {
+                        car: Pattern::Variable("x".to_string()).into(),
+                        cdr: Pattern::Wildcard.into(),
+                    },
+                    Expression::Plus {
+                        expression1: Expression::Variable("x".to_string()).into(),
+                        expression2: Expression::Integer(10).into(),
+                    },
+                ),
+                (Pattern::Wildcard, Expression::Integer(0)),
+            ],
         };
 
         let result = eval(Environment::default(), expr);
@@ -421,4 +1777,523 @@ mod tests {
         let (_, value) = result.unwrap();
         assert!(matches!(value, Value::Integer(1)));
     }
+
+    #[test]
+    fn test_match_failure() {
+        // match 3 with 1 -> true | 2 -> false
+        let expr = Expression::Match {
+            scrutinee: Expression::Integer(3).into(),
+            arms: vec![
+                (Pattern::Integer(1), Expression::Bool(true)),
+                (Pattern::Integer(2), Expression::Bool(false)),
+            ],
+        };
+
+        let result = eval(builtin_environment(), expr);
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Exception: Match_failure");
+
+        let result = eval_source("try (match 1 with 2 -> 3) with _ -> 5");
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(5));
+    }
+
+    #[test]
+    fn test_try_catches_exception() {
+        // try raise (Error 3) with Not_found -> 0 | Error n -> n
+        let expr = Expression::Try {
+            body: Expression::Raise(
+                Expression::Constructor {
+                    name: "Error".to_string(),
+                    argument: Some(Expression::Integer(3).into()),
+                }
+                .into(),
+            )
+            .into(),
+            arms: vec![
+                (
+                    Pattern::Constructor {
+                        name: "Not_found".to_string(),
+                        argument: None,
+                    },
+                    Expression::Integer(0),
+                ),
+                (
+                    Pattern::Constructor {
+                        name: "Error".to_string(),
+                        argument: Some(Pattern::Variable("n".to_string()).into()),
+                    },
+                    Expression::Variable("n".to_string()),
+                ),
+            ],
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(3));
+    }
+
+    #[test]
+    fn test_uncaught_exception() {
+        // try raise Not_found with Error _ -> 0
+        let expr = Expression::Try {
+            body: Expression::Raise(
+                Expression::Constructor {
+                    name: "Not_found".to_string(),
+                    argument: None,
+                }
+                .into(),
+            )
+            .into(),
+            arms: vec![(
+                Pattern::Constructor {
+                    name: "Error".to_string(),
+                    argument: Some(Pattern::Wildcard.into()),
+                },
+                Expression::Integer(0),
+            )],
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Exception: Not_found");
+    }
+
+    #[test]
+    fn test_exceptions_are_generative() {
+        let source = "
+            exception E;;
+            let f () = raise E;;
+            exception E;;
+            try f () with E -> 1
+        ";
+
+        let result = eval_program(source);
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Exception: E");
+
+        let source = "
+            module A = struct exception E of int end;;
+            module B = struct exception E of int end;;
+            try raise (A.E 1) with B.E b -> b | A.E a -> a + 1
+        ";
+
+        let result = eval_program(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Integer(2));
+    }
+
+    #[test]
+    fn test_assert_failure() {
+        // assert (2 < 1)
+        let expr = Expression::Assert(
+            Expression::LessThan {
+                expression1: Expression::Integer(2).into(),
+                expression2: Expression::Integer(1).into(),
+            }
+            .into(),
+        );
+
+        let result = eval(builtin_environment(), expr);
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Exception: Assert_failure");
+    }
+
+    #[test]
+    fn test_match_constructor() {
+        // match Circle 3 with Dot -> 0 | Circle r -> r
+        let expr = Expression::Match {
+            scrutinee: Expression::Constructor {
+                name: "Circle".to_string(),
+                argument: Some(Expression::Integer(3).into()),
+            }
+            .into(),
+            arms: vec![
+                (
+                    Pattern::Constructor {
+                        name: "Dot".to_string(),
+                        argument: None,
+                    },
+                    Expression::Integer(0),
+                ),
+                (
+                    Pattern::Constructor {
+                        name: "Circle".to_string(),
+                        argument: Some(Pattern::Variable("r".to_string()).into()),
+                    },
+                    Expression::Variable("r".to_string()),
+                ),
+            ],
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_mutable_field_is_shared() {
+        // let p = { name = 1; age = 30 } in
+        // let q = { p with name = 2 } in
+        // let r = p in
+        // match r.age <- 31 with _ -> (p.age, q.age)
+        let field = |record: &str, label: &str| Expression::Field {
+            record: Expression::Variable(record.to_string()).into(),
+            label: label.to_string(),
+        };
+        let expr = Expression::Let {
+            variable: "p".to_string(),
+            bound: Expression::Record(vec![
+                ("name".to_string(), Expression::Integer(1)),
+                ("age".to_string(), Expression::Integer(30)),
+            ])
+            .into(),
+            body: Expression::Let {
+                variable: "q".to_string(),
+                bound: Expression::RecordUpdate {
+                    record: Expression::Variable("p".to_string()).into(),
+                    fields: vec![("name".to_string(), Expression::Integer(2))],
+                }
+                .into(),
+                body: Expression::Let {
+                    variable: "r".to_string(),
+                    bound: Expression::Variable("p".to_string()).into(),
+                    body: Expression::Match {
+                        scrutinee: Expression::FieldAssignment {
+                            record: Expression::Variable("r".to_string()).into(),
+                            label: "age".to_string(),
+                            value: Expression::Integer(31).into(),
+                        }
+                        .into(),
+                        arms: vec![(
+                            Pattern::Wildcard,
+                            Expression::Tuple(vec![field("p", "age"), field("q", "age")]),
+                        )],
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value.to_string(), "(31, 30)");
+    }
+
+    #[test]
+    fn test_sequence() {
+        // let p = { age = 30 } in p.age <- 31; p.age
+        let expr = Expression::Let {
+            variable: "p".to_string(),
+            bound: Expression::Record(vec![("age".to_string(), Expression::Integer(30))]).into(),
+            body: Expression::Sequence {
+                expression1: Expression::FieldAssignment {
+                    record: Expression::Variable("p".to_string()).into(),
+                    label: "age".to_string(),
+                    value: Expression::Integer(31).into(),
+                }
+                .into(),
+                expression2: Expression::Field {
+                    record: Expression::Variable("p".to_string()).into(),
+                    label: "age".to_string(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(31));
+    }
+
+    #[test]
+    fn test_cyclic_record() {
+        let source = "
+            type node = { v : int; mutable next : node list };;
+            let a = { v = 1; next = [] };;
+            a.next <- [a];;
+        ";
+
+        let result = eval_program(&format!("{source} a"));
+
+        assert!(result.is_ok());
+        let printed = result.unwrap().to_string();
+        assert!(printed.starts_with("{ v = 1; next = [{ v = 1; next = ["));
+        assert!(printed.contains("..."));
+
+        let result = eval_program(&format!("{source} a = a"));
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Value::Bool(true));
+    }
+
+    #[test]
+    fn test_ref_is_shared() {
+        // let r = ref 1 in let s = r in s := 2; !r
+        let expr = Expression::Let {
+            variable: "r".to_string(),
+            bound: Expression::Ref(Expression::Integer(1).into()).into(),
+            body: Expression::Let {
+                variable: "s".to_string(),
+                bound: Expression::Variable("r".to_string()).into(),
+                body: Expression::Sequence {
+                    expression1: Expression::Assign {
+                        reference: Expression::Variable("s".to_string()).into(),
+                        value: Expression::Integer(2).into(),
+                    }
+                    .into(),
+                    expression2: Expression::Deref(Expression::Variable("r".to_string()).into())
+                        .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(2));
+    }
+
+    #[test]
+    fn test_top_level_declarations() {
+        // let rec fact = fun n -> if n < 1 then 1 else n * fact (n - 1)
+        // fact 5
+        let declaration = TopLevel::LetRec {
+            bindings: vec![(
+                "fact".to_string(),
+                Expression::Fun {
+                    parameter: "n".to_string(),
+                    body: Expression::If {
+                        predicate: Expression::LessThan {
+                            expression1: Expression::Variable("n".to_string()).into(),
+                            expression2: Expression::Integer(1).into(),
+                        }
+                        .into(),
+                        consequent: Expression::Integer(1).into(),
+                        alternative: Expression::Times {
+                            expression1: Expression::Variable("n".to_string()).into(),
+                            expression2: Expression::App {
+                                function: Expression::Variable("fact".to_string()).into(),
+                                argument: Expression::Minus {
+                                    expression1: Expression::Variable("n".to_string()).into(),
+                                    expression2: Expression::Integer(1).into(),
+                                }
+                                .into(),
+                            }
+                            .into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                },
+            )],
+        };
+        let expression = TopLevel::Expression(Expression::App {
+            function: Expression::Variable("fact".to_string()).into(),
+            argument: Expression::Integer(5).into(),
+        });
+
+        let result = eval_top_level(Environment::default(), declaration);
+        assert!(result.is_ok());
+        let (environment, _) = result.unwrap();
+        let result = eval_top_level(environment, expression);
+
+        assert!(result.is_ok());
+        let (environment, value) = result.unwrap();
+        assert!(matches!(value, Value::Integer(120)));
+        assert!(environment.get(&"fact".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_let_with_pattern() {
+        let cases = [
+            ("let (q, r) = (7 / 2, 7 mod 2) in q + r", "4"),
+            ("let (a, b) = (1, 2) let c = a + b", "3"),
+            ("let (a, b) = (1, 2)", "(1, 2)"),
+            ("let _ = 5", "5"),
+        ];
+
+        for (source, expected) in cases {
+            let result = eval_program(source);
+
+            assert!(result.is_ok());
+            assert_eq!(result.unwrap().to_string(), expected);
+        }
+
+        let result = eval_program("let [a] = []");
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Exception: Match_failure");
+    }
+
+    #[test]
+    fn test_top_level_mutually_recursive_functions() {
+        // let rec even n = if n = 0 then true else odd (n - 1)
+        // and odd n = if n = 0 then false else even (n - 1)
+        // odd 7
+        // n = 0 のとき base を返し、そうでなければ other (n - 1) を呼ぶ関数
+        let parity = |base: bool, other: &str| Expression::Fun {
+            parameter: "n".to_string(),
+            body: Expression::If {
+                predicate: Expression::Equal {
+                    expression1: Expression::Variable("n".to_string()).into(),
+                    expression2: Expression::Integer(0).into(),
+                }
+                .into(),
+                consequent: Expression::Bool(base).into(),
+                alternative: Expression::App {
+                    function: Expression::Variable(other.to_string()).into(),
+                    argument: Expression::Minus {
+                        expression1: Expression::Variable("n".to_string()).into(),
+                        expression2: Expression::Integer(1).into(),
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+        let declaration = TopLevel::LetRec {
+            bindings: vec![
+                ("even".to_string(), parity(true, "odd")),
+                ("odd".to_string(), parity(false, "even")),
+            ],
+        };
+        let expression = TopLevel::Expression(Expression::App {
+            function: Expression::Variable("odd".to_string()).into(),
+            argument: Expression::Integer(7).into(),
+        });
+
+        let result = eval_top_level(Environment::default(), declaration);
+        assert!(result.is_ok());
+        let (environment, _) = result.unwrap();
+        let result = eval_top_level(environment, expression);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Bool(true));
+    }
+
+    #[test]
+    fn test_let_does_not_leak_binding() {
+        // let x = 1 in x
+        let expr = Expression::Let {
+            variable: "x".to_string(),
+            bound: Expression::Integer(1).into(),
+            body: Expression::Variable("x".to_string()).into(),
+        };
+
+        let result = eval(Environment::default(), expr);
+
+        assert!(result.is_ok());
+        let (environment, _) = result.unwrap();
+        assert!(environment.get(&"x".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_module_and_open() {
+        // module M = struct let x = 1 let y = x + 1 end
+        // let open M in M.x + y
+        let declaration = TopLevel::Module {
+            name: "M".to_string(),
+            module: ModuleExpression::Structure(vec![
+                TopLevel::Let {
+                    variable: "x".to_string(),
+                    bound: Expression::Integer(1),
+                },
+                TopLevel::Let {
+                    variable: "y".to_string(),
+                    bound: Expression::Plus {
+                        expression1: Expression::Variable("x".to_string()).into(),
+                        expression2: Expression::Integer(1).into(),
+                    },
+                },
+            ]),
+        };
+        let expression = TopLevel::Expression(Expression::Open {
+            module: "M".to_string(),
+            body: Expression::Plus {
+                expression1: Expression::Variable("M.x".to_string()).into(),
+                expression2: Expression::Variable("y".to_string()).into(),
+            }
+            .into(),
+        });
+
+        let result = eval_top_level(Environment::default(), declaration);
+        assert!(result.is_ok());
+        let (environment, _) = result.unwrap();
+        assert!(environment.get(&"x".to_string()).is_none());
+        let result = eval_top_level(environment, expression);
+
+        assert!(result.is_ok());
+        let (environment, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(3));
+        assert!(environment.get(&"y".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_functor_application() {
+        // module F (X : S) = struct let y = X.x * 2 end
+        // module M = F (struct let x = 21 end)
+        // M.y
+        let functor = TopLevel::Module {
+            name: "F".to_string(),
+            module: ModuleExpression::Functor {
+                parameter: "X".to_string(),
+                parameter_type: ModuleType::Signature(vec![]),
+                body: ModuleExpression::Structure(vec![TopLevel::Let {
+                    variable: "y".to_string(),
+                    bound: Expression::Times {
+                        expression1: Expression::Variable("X.x".to_string()).into(),
+                        expression2: Expression::Integer(2).into(),
+                    },
+                }])
+                .into(),
+            },
+        };
+        let declaration = TopLevel::Module {
+            name: "M".to_string(),
+            module: ModuleExpression::Application {
+                functor: ModuleExpression::Path("F".to_string()).into(),
+                argument: ModuleExpression::Structure(vec![TopLevel::Let {
+                    variable: "x".to_string(),
+                    bound: Expression::Integer(21),
+                }])
+                .into(),
+            },
+        };
+        let expression = TopLevel::Expression(Expression::Variable("M.y".to_string()));
+
+        let result = eval_top_level(Environment::default(), functor);
+        assert!(result.is_ok());
+        let (environment, _) = result.unwrap();
+        let result = eval_top_level(environment, declaration);
+        assert!(result.is_ok());
+        let (environment, _) = result.unwrap();
+        let result = eval_top_level(environment, expression);
+
+        assert!(result.is_ok());
+        let (_, value) = result.unwrap();
+        assert_eq!(value, Value::Integer(42));
+    }
 }
@@ -0,0 +1,35 @@
+use std::sync::{Arc, Mutex, PoisonError};
+
+use crate::syntax::value::Value;
+
+// 書き換え可能な値を置く場所
+// 複製しても同じ場所を指すので、一方への書き込みがもう一方からも見える
+// 値は例外としてanyhow::Errorに載るので、Send + Syncにしておく
+#[derive(Debug, Clone)]
+pub struct Location(Arc<Mutex<Value>>);
+
+impl Location {
+    pub fn new(value: Value) -> Self {
+        Self(Arc::new(Mutex::new(value)))
+    }
+
+    pub fn get(&self) -> Value {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn set(&self, value: Value) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+}
+
+// 同じ場所を指すときだけ等しい。中身を比べると、自分自身を指す値で止まらなくなる
+impl PartialEq for Location {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Location {}
@@ -1,5 +1,7 @@
 mod adapter;
 pub mod analysis;
+pub mod builtin;
 pub mod execution;
+pub mod loader;
 pub mod syntax;
 pub mod type_system;
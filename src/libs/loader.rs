@@ -0,0 +1,686 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, anyhow, bail};
+use thiserror::Error;
+
+use crate::{
+    adapter::{Symbol, split_path},
+    analysis::{
+        parser::{parse_interface, parse_program},
+        tokenizer::tokenize,
+    },
+    execution::{environment::Environment, evaluation::eval_top_level},
+    syntax::ast::{
+        Expression, ModuleExpression, ModuleType, Pattern, Program, Specification, TopLevel,
+        TypeDefinition, TypeKind,
+    },
+    type_system::{
+        inference::infer_top_level, type_environment::TypeEnvironment, types::Type,
+        warning::Warning,
+    },
+};
+
+pub const IMPLEMENTATION_EXTENSION: &str = "picocaml";
+pub const INTERFACE_EXTENSION: &str = "picocamli";
+
+#[derive(Debug, Error)]
+enum LoadError {
+    #[error("Invalid source file name: {0}")]
+    InvalidFileName(String),
+    #[error("Module {0} is defined by both {1} and {2}")]
+    DuplicateModule(Symbol, String, String),
+    #[error("Circular dependency between modules: {0}")]
+    CyclicDependency(String),
+    #[error("File \"{0}\": {1}")]
+    InFile(String, String),
+}
+
+// 読み込んだファイルと、そのファイルの型検査で出た警告
+#[derive(Debug, Clone)]
+pub struct LoadedModule {
+    pub name: Symbol,
+    pub path: PathBuf,
+    pub warnings: Vec<Warning>,
+}
+
+struct Loader {
+    environment: Environment,
+    type_environment: TypeEnvironment,
+    loaded: Vec<LoadedModule>,
+    // 読み込み中のモジュール。依存関係の循環を見つけるのに使う
+    loading: Vec<Symbol>,
+}
+
+// foo.picocaml をモジュール Foo として読み込み、環境に加える
+// 同じディレクトリの他のファイルのモジュールを参照していれば、そのファイルを先に読み込む
+// どのファイルも型検査と評価は1度だけで、後のファイルは先に読み込んだファイルのモジュールを含む環境で検査する
+pub fn load_files(
+    environment: Environment,
+    type_environment: TypeEnvironment,
+    paths: &[PathBuf],
+) -> Result<(Environment, TypeEnvironment, Vec<LoadedModule>)> {
+    let mut loader = Loader {
+        environment,
+        type_environment,
+        loaded: Vec::new(),
+        loading: Vec::new(),
+    };
+    for path in paths {
+        loader.load(path)?;
+    }
+
+    Ok((loader.environment, loader.type_environment, loader.loaded))
+}
+
+// ファイル名の先頭を大文字にしたものがモジュール名になる(foo_bar.picocaml は Foo_bar)
+pub fn module_name(path: &Path) -> Result<Symbol> {
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| {
+            let mut chars = stem.chars();
+            chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .ok_or_else(|| LoadError::InvalidFileName(path.display().to_string()))?;
+
+    let mut name = stem.to_string();
+    name[..1].make_ascii_uppercase();
+    Ok(name)
+}
+
+impl Loader {
+    fn load(&mut self, path: &Path) -> Result<()> {
+        let name = module_name(path)?;
+        if let Some(loaded) = self.loaded.iter().find(|loaded| loaded.name == name) {
+            if !same_file(&loaded.path, path) {
+                bail!(LoadError::DuplicateModule(
+                    name,
+                    loaded.path.display().to_string(),
+                    path.display().to_string()
+                ));
+            }
+            return Ok(());
+        }
+        if let Some(start) = self.loading.iter().position(|loading| *loading == name) {
+            let mut cycle = self.loading[start..].to_vec();
+            cycle.push(name);
+            bail!(LoadError::CyclicDependency(cycle.join(" -> ")));
+        }
+
+        let in_file = |path: &Path, e: anyhow::Error| {
+            anyhow!(LoadError::InFile(path.display().to_string(), e.to_string()))
+        };
+        let source = fs::read_to_string(path).map_err(|e| in_file(path, e.into()))?;
+        let tokens = tokenize(source).map_err(|e| in_file(path, e))?;
+        let interface_path = path.with_extension(INTERFACE_EXTENSION);
+        let interface_tokens = if interface_path.is_file() {
+            let source = fs::read_to_string(&interface_path)
+                .map_err(|e| in_file(&interface_path, e.into()))?;
+            Some(tokenize(source).map_err(|e| in_file(&interface_path, e))?)
+        } else {
+            None
+        };
+
+        let program = parse_program(tokens).map_err(|e| in_file(path, e))?;
+        let interface = interface_tokens
+            .map(|tokens| parse_interface(tokens).map_err(|e| in_file(&interface_path, e)))
+            .transpose()?;
+
+        self.loading.push(name.clone());
+        for dependency in dependencies(path, &name, &program, interface.as_deref()) {
+            self.load(&dependency)?;
+        }
+        self.loading.pop();
+
+        let mut module = ModuleExpression::Structure(program.top_levels);
+        if let Some(specifications) = interface {
+            module = ModuleExpression::Ascription {
+                module: Box::new(module),
+                module_type: ModuleType::Signature(specifications),
+            };
+        }
+        let top_level = TopLevel::Module {
+            name: name.clone(),
+            module,
+        };
+
+        let (type_environment, _) =
+            infer_top_level(self.type_environment.clone(), top_level.clone())
+                .map_err(|e| in_file(path, e))?;
+        let (environment, _) =
+            eval_top_level(self.environment.clone(), top_level).map_err(|e| in_file(path, e))?;
+        self.loaded.push(LoadedModule {
+            name,
+            path: path.to_path_buf(),
+            warnings: type_environment.warnings().to_vec(),
+        });
+        self.type_environment = type_environment;
+        self.environment = environment;
+
+        Ok(())
+    }
+}
+
+fn same_file(path1: &Path, path2: &Path) -> bool {
+    match (fs::canonicalize(path1), fs::canonicalize(path2)) {
+        (Ok(path1), Ok(path2)) => path1 == path2,
+        _ => path1 == path2,
+    }
+}
+
+// ファイルが経路の先頭に書いたモジュールのうち、同じディレクトリのファイルになっているもの
+fn dependencies(
+    path: &Path,
+    name: &str,
+    program: &Program,
+    interface: Option<&[Specification]>,
+) -> Vec<PathBuf> {
+    let mut references = References::default();
+    references.top_levels(&program.top_levels);
+    interface
+        .into_iter()
+        .flatten()
+        .for_each(|specification| references.specification(specification));
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+    references
+        .modules
+        .into_iter()
+        .filter(|module| module != name)
+        .map(|module| {
+            let mut file_name = module;
+            file_name[..1].make_ascii_lowercase();
+            directory
+                .join(file_name)
+                .with_extension(IMPLEMENTATION_EXTENSION)
+        })
+        .filter(|dependency| dependency.is_file())
+        .collect()
+}
+
+// 構文木の中の M.x、M.C、M.t、open M、モジュール式の M から、参照するモジュールの名前を集める
+// ファイルの中で宣言したモジュールやファンクタの引数は、その名前が見える範囲では参照とみなさない
+#[derive(Default)]
+struct References {
+    bound: Vec<Symbol>,
+    modules: Vec<Symbol>,
+}
+
+impl References {
+    fn module_path(&mut self, path: &str) {
+        let module = split_path(path).map_or(path, |(module, _)| module);
+        if !self.bound.iter().any(|bound| bound == module)
+            && !self.modules.iter().any(|name| name == module)
+        {
+            self.modules.push(module.to_string());
+        }
+    }
+
+    fn qualified_name(&mut self, name: &str) {
+        if let Some((module, _)) = split_path(name) {
+            self.module_path(module);
+        }
+    }
+
+    // 構造の中で宣言したモジュールは、構造の終わりまで見える
+    fn top_levels(&mut self, top_levels: &[TopLevel]) {
+        let scope = self.bound.len();
+        for top_level in top_levels {
+            match top_level {
+                TopLevel::Expression(expression)
+                | TopLevel::Let {
+                    bound: expression, ..
+                }
+                | TopLevel::LetPattern {
+                    bound: expression, ..
+                } => self.expression(expression),
+                TopLevel::LetRec { bindings } => bindings
+                    .iter()
+                    .for_each(|(_, bound)| self.expression(bound)),
+                TopLevel::TypeDefinition(definitions) => self.definitions(definitions),
+                TopLevel::Exception { argument, .. } => {
+                    argument.iter().for_each(|t| self.type_expression(t))
+                }
+                TopLevel::Module { name, module } => {
+                    self.module_expression(module);
+                    self.bound.push(name.clone());
+                }
+                TopLevel::ModuleType { module_type, .. } => self.module_type(module_type),
+                TopLevel::Open(path) => self.module_path(path),
+            }
+        }
+        self.bound.truncate(scope);
+    }
+
+    fn module_expression(&mut self, module: &ModuleExpression) {
+        match module {
+            ModuleExpression::Structure(top_levels) => self.top_levels(top_levels),
+            ModuleExpression::Path(path) => self.module_path(path),
+            ModuleExpression::Ascription {
+                module,
+                module_type,
+            } => {
+                self.module_expression(module);
+                self.module_type(module_type);
+            }
+            ModuleExpression::Functor {
+                parameter,
+                parameter_type,
+                body,
+            } => {
+                self.module_type(parameter_type);
+                self.bound.push(parameter.clone());
+                self.module_expression(body);
+                self.bound.pop();
+            }
+            ModuleExpression::Application { functor, argument } => {
+                self.module_expression(functor);
+                self.module_expression(argument);
+            }
+        }
+    }
+
+    fn module_type(&mut self, module_type: &ModuleType) {
+        match module_type {
+            ModuleType::Signature(specifications) => specifications
+                .iter()
+                .for_each(|specification| self.specification(specification)),
+            ModuleType::Path(path) => self.module_path(path),
+            ModuleType::With {
+                module_type,
+                constraints,
+            } => {
+                self.module_type(module_type);
+                self.definitions(constraints);
+            }
+        }
+    }
+
+    fn specification(&mut self, specification: &Specification) {
+        match specification {
+            Specification::Value { value_type, .. } => self.type_expression(value_type),
+            Specification::Type(definitions) => self.definitions(definitions),
+            Specification::Exception { argument, .. } => {
+                argument.iter().for_each(|t| self.type_expression(t))
+            }
+        }
+    }
+
+    fn definitions(&mut self, definitions: &[TypeDefinition]) {
+        for definition in definitions {
+            match &definition.kind {
+                TypeKind::Variant(constructors) => constructors
+                    .iter()
+                    .flat_map(|(_, argument)| argument)
+                    .for_each(|t| self.type_expression(t)),
+                TypeKind::Record(fields) => fields
+                    .iter()
+                    .for_each(|field| self.type_expression(&field.field_type)),
+                TypeKind::Abstract => {}
+                TypeKind::Alias(t) => self.type_expression(t),
+            }
+        }
+    }
+
+    fn type_expression(&mut self, t: &Type) {
+        match t {
+            Type::Base(_) | Type::Variable { .. } => {}
+            Type::List(t) | Type::Ref(t) => self.type_expression(t),
+            Type::Tuple(types) => types.iter().for_each(|t| self.type_expression(t)),
+            Type::Function { domain, range } => {
+                self.type_expression(domain);
+                self.type_expression(range);
+            }
+            Type::Constructor { name, args } => {
+                self.qualified_name(name);
+                args.iter().for_each(|t| self.type_expression(t));
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Cons { car, cdr } => {
+                self.pattern(car);
+                self.pattern(cdr);
+            }
+            Pattern::Tuple(patterns) => patterns.iter().for_each(|p| self.pattern(p)),
+            Pattern::Constructor { name, argument } => {
+                self.qualified_name(name);
+                argument.iter().for_each(|p| self.pattern(p));
+            }
+            Pattern::Record(fields) => fields.iter().for_each(|(_, p)| self.pattern(p)),
+            _ => {}
+        }
+    }
+
+    fn arms(&mut self, arms: &[(Pattern, Expression)]) {
+        for (pattern, body) in arms {
+            self.pattern(pattern);
+            self.expression(body);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Integer(_)
+            | Expression::Bool(_)
+            | Expression::String(_)
+            | Expression::Char(_)
+            | Expression::Unit
+            | Expression::Nil => {}
+            Expression::Variable(name) => self.qualified_name(name),
+            Expression::Plus {
+                expression1,
+                expression2,
+            }
+            | Expression::Minus {
+                expression1,
+                expression2,
+            }
+            | Expression::Times {
+                expression1,
+                expression2,
+            }
+            | Expression::Divide {
+                expression1,
+                expression2,
+            }
+            | Expression::Modulo {
+                expression1,
+                expression2,
+            }
+            | Expression::LessThan {
+                expression1,
+                expression2,
+            }
+            | Expression::Equal {
+                expression1,
+                expression2,
+            }
+            | Expression::NotEqual {
+                expression1,
+                expression2,
+            }
+            | Expression::LessEqual {
+                expression1,
+                expression2,
+            }
+            | Expression::GreaterThan {
+                expression1,
+                expression2,
+            }
+            | Expression::GreaterEqual {
+                expression1,
+                expression2,
+            }
+            | Expression::And {
+                expression1,
+                expression2,
+            }
+            | Expression::Or {
+                expression1,
+                expression2,
+            }
+            | Expression::Concat {
+                expression1,
+                expression2,
+            }
+            | Expression::Append {
+                expression1,
+                expression2,
+            }
+            | Expression::Sequence {
+                expression1,
+                expression2,
+            }
+            | Expression::Let {
+                bound: expression1,
+                body: expression2,
+                ..
+            }
+            | Expression::App {
+                function: expression1,
+                argument: expression2,
+            }
+            | Expression::Cons {
+                car: expression1,
+                cdr: expression2,
+            }
+            | Expression::FieldAssignment {
+                record: expression1,
+                value: expression2,
+                ..
+            }
+            | Expression::Assign {
+                reference: expression1,
+                value: expression2,
+            } => {
+                self.expression(expression1);
+                self.expression(expression2);
+            }
+            Expression::Negate(expression)
+            | Expression::Fun {
+                body: expression, ..
+            }
+            | Expression::Field {
+                record: expression, ..
+            }
+            | Expression::Ref(expression)
+            | Expression::Deref(expression)
+            | Expression::Raise(expression)
+            | Expression::Assert(expression) => self.expression(expression),
+            Expression::If {
+                predicate,
+                consequent,
+                alternative,
+            } => {
+                self.expression(predicate);
+                self.expression(consequent);
+                self.expression(alternative);
+            }
+            Expression::LetRec { bindings, body } => {
+                bindings
+                    .iter()
+                    .for_each(|(_, bound)| self.expression(bound));
+                self.expression(body);
+            }
+            Expression::Match { scrutinee, arms } => {
+                self.expression(scrutinee);
+                self.arms(arms);
+            }
+            Expression::Try { body, arms } => {
+                self.expression(body);
+                self.arms(arms);
+            }
+            Expression::Tuple(expressions) => expressions.iter().for_each(|e| self.expression(e)),
+            Expression::Constructor { name, argument } => {
+                self.qualified_name(name);
+                argument.iter().for_each(|e| self.expression(e));
+            }
+            Expression::TypeDefinition { definitions, body } => {
+                self.definitions(definitions);
+                self.expression(body);
+            }
+            Expression::Record(fields) => fields.iter().for_each(|(_, e)| self.expression(e)),
+            Expression::RecordUpdate { record, fields } => {
+                self.expression(record);
+                fields.iter().for_each(|(_, e)| self.expression(e));
+            }
+            Expression::Annotation {
+                expression,
+                annotation,
+            } => {
+                self.expression(expression);
+                self.type_expression(annotation);
+            }
+            Expression::Open { module, body } => {
+                self.module_path(module);
+                self.expression(body);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::{ast::Expression, value::Value};
+
+    // テストごとに別の一時ディレクトリにファイルを書き出し、テストが終わったら消す
+    struct Project(PathBuf);
+
+    impl Project {
+        fn join(&self, file_name: &str) -> PathBuf {
+            self.0.join(file_name)
+        }
+    }
+
+    impl Drop for Project {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn project(name: &str, files: &[(&str, &str)]) -> Project {
+        let directory =
+            std::env::temp_dir().join(format!("picocaml-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for (file_name, source) in files {
+            fs::write(directory.join(file_name), source).unwrap();
+        }
+        Project(directory)
+    }
+
+    fn load(
+        project: &Project,
+        file_name: &str,
+    ) -> Result<(Environment, TypeEnvironment, Vec<LoadedModule>)> {
+        load_files(
+            Environment::default(),
+            TypeEnvironment::default(),
+            &[project.join(file_name)],
+        )
+    }
+
+    #[test]
+    fn test_load_dependencies() {
+        let directory = project(
+            "dependencies",
+            &[
+                ("main.picocaml", "let x = Util.double Util.base"),
+                ("util.picocaml", "let base = 21\nlet double n = n * 2"),
+            ],
+        );
+
+        let result = load(&directory, "main.picocaml");
+
+        assert!(result.is_ok());
+        let (environment, type_environment, modules) = result.unwrap();
+        let names = modules.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Util", "Main"]);
+
+        let expression = TopLevel::Expression(Expression::Variable("Main.x".to_string()));
+        let (_, value) = eval_top_level(environment, expression.clone()).unwrap();
+        assert_eq!(value, Value::Integer(42));
+        assert!(infer_top_level(type_environment, expression).is_ok());
+    }
+
+    #[test]
+    fn test_load_interface() {
+        let directory = project(
+            "interface",
+            &[
+                (
+                    "counter.picocaml",
+                    "type t = int\nlet zero = 0\nlet hidden = 1",
+                ),
+                ("counter.picocamli", "type t\nval zero : t"),
+            ],
+        );
+
+        let result = load(&directory, "counter.picocaml");
+
+        assert!(result.is_ok());
+        let (_, type_environment, _) = result.unwrap();
+        let hidden = TopLevel::Expression(Expression::Variable("Counter.hidden".to_string()));
+        assert!(infer_top_level(type_environment, hidden).is_err());
+    }
+
+    #[test]
+    fn test_load_interface_mismatch() {
+        let directory = project(
+            "mismatch",
+            &[
+                ("counter.picocaml", "let zero = true"),
+                ("counter.picocamli", "val zero : int"),
+            ],
+        );
+
+        let result = load(&directory, "counter.picocaml");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_load_cyclic_dependency() {
+        let directory = project(
+            "cycle",
+            &[("a.picocaml", "let x = B.y"), ("b.picocaml", "let y = A.x")],
+        );
+
+        let result = load(&directory, "a.picocaml");
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Circular dependency between modules: A -> B -> A"
+        );
+    }
+
+    #[test]
+    fn test_load_constructor_named_like_module() {
+        // a の構築子 B は b.picocaml への参照ではない
+        let directory = project(
+            "constructor",
+            &[
+                ("a.picocaml", "type t = B | C\nlet v = B"),
+                ("b.picocaml", "let w = match A.v with A.B -> 1 | A.C -> 2"),
+            ],
+        );
+
+        let result = load(&directory, "b.picocaml");
+
+        assert!(result.is_ok());
+        let (_, _, modules) = result.unwrap();
+        let names = modules.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["A", "B"]);
+    }
+
+    #[test]
+    fn test_load_local_module_named_like_file() {
+        // main の中で宣言した Util は util.picocaml を読み込まない
+        let directory = project(
+            "local",
+            &[
+                (
+                    "main.picocaml",
+                    "module Util = struct let x = 1 end\nlet y = Util.x",
+                ),
+                ("util.picocaml", "let x = Main.y"),
+            ],
+        );
+
+        let result = load(&directory, "main.picocaml");
+
+        assert!(result.is_ok());
+        let (_, _, modules) = result.unwrap();
+        let names = modules.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Main"]);
+    }
+}
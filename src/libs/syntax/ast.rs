@@ -1,11 +1,222 @@
 use std::fmt::Display;
 
-use crate::adapter::{RBool, RInteger, Symbol};
+use crate::{
+    adapter::{RBool, RChar, RInteger, RString, Symbol, escape_char, escape_string},
+    type_system::types::Type,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub top_levels: Vec<TopLevel>,
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let top_levels = self
+            .top_levels
+            .iter()
+            .map(TopLevel::to_string)
+            .collect::<Vec<_>>();
+        write!(f, "{}", top_levels.join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopLevel {
+    Expression(Expression),
+    Let {
+        variable: Symbol,
+        bound: Expression,
+    },
+    // let (a, b) = e や let () = e のように、変数でないパターンを束縛する宣言
+    LetPattern {
+        pattern: Pattern,
+        bound: Expression,
+    },
+    LetRec {
+        bindings: Vec<(Symbol, Expression)>,
+    },
+    TypeDefinition(Vec<TypeDefinition>),
+    Exception {
+        name: Symbol,
+        argument: Option<Type>,
+    },
+    Module {
+        name: Symbol,
+        module: ModuleExpression,
+    },
+    ModuleType {
+        name: Symbol,
+        module_type: ModuleType,
+    },
+    Open(Symbol),
+}
+
+impl Display for TopLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TopLevel::Expression(expression) => write!(f, "{}", expression),
+            TopLevel::Let { variable, bound } => write!(f, "(define {} {})", variable, bound),
+            TopLevel::LetPattern { pattern, bound } => write!(f, "(define {} {})", pattern, bound),
+            TopLevel::LetRec { bindings } => {
+                write!(f, "(define-rec")?;
+                for (variable, bound_function) in bindings {
+                    write!(f, " ({} {})", variable, bound_function)?;
+                }
+                write!(f, ")")
+            }
+            TopLevel::TypeDefinition(definitions) => {
+                write!(f, "(type")?;
+                for definition in definitions {
+                    write!(f, " {}", definition)?;
+                }
+                write!(f, ")")
+            }
+            TopLevel::Exception {
+                name,
+                argument: Some(argument),
+            } => write!(f, "(exception {} {})", name, argument),
+            TopLevel::Exception {
+                name,
+                argument: None,
+            } => write!(f, "(exception {})", name),
+            TopLevel::Module { name, module } => write!(f, "(module {} {})", name, module),
+            TopLevel::ModuleType { name, module_type } => {
+                write!(f, "(module-type {} {})", name, module_type)
+            }
+            TopLevel::Open(path) => write!(f, "(open {})", path),
+        }
+    }
+}
+
+// モジュールの名前は M.N のように . でつないだ経路で参照する
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleExpression {
+    Structure(Vec<TopLevel>),
+    Path(Symbol),
+    Ascription {
+        module: Box<ModuleExpression>,
+        module_type: ModuleType,
+    },
+    // functor (X : S) -> body。2つ以上の引数は入れ子にする
+    Functor {
+        parameter: Symbol,
+        parameter_type: ModuleType,
+        body: Box<ModuleExpression>,
+    },
+    Application {
+        functor: Box<ModuleExpression>,
+        argument: Box<ModuleExpression>,
+    },
+}
+
+impl Display for ModuleExpression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleExpression::Structure(top_levels) => {
+                write!(f, "(struct")?;
+                for top_level in top_levels {
+                    write!(f, " {}", top_level)?;
+                }
+                write!(f, ")")
+            }
+            ModuleExpression::Path(path) => write!(f, "{}", path),
+            ModuleExpression::Ascription {
+                module,
+                module_type,
+            } => write!(f, "(: {} {})", module, module_type),
+            ModuleExpression::Functor {
+                parameter,
+                parameter_type,
+                body,
+            } => write!(f, "(functor ({} {}) {})", parameter, parameter_type, body),
+            ModuleExpression::Application { functor, argument } => {
+                write!(f, "({} {})", functor, argument)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleType {
+    Signature(Vec<Specification>),
+    Path(Symbol),
+    // S with type t = int and ... 。制約は型の別名として持つ
+    With {
+        module_type: Box<ModuleType>,
+        constraints: Vec<TypeDefinition>,
+    },
+}
+
+impl Display for ModuleType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleType::Signature(specifications) => {
+                write!(f, "(sig")?;
+                for specification in specifications {
+                    write!(f, " {}", specification)?;
+                }
+                write!(f, ")")
+            }
+            ModuleType::Path(path) => write!(f, "{}", path),
+            ModuleType::With {
+                module_type,
+                constraints,
+            } => {
+                write!(f, "(with {}", module_type)?;
+                for constraint in constraints {
+                    write!(f, " {}", constraint)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+// シグネチャに並べる宣言。型は定義を省略すると抽象型になる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Specification {
+    Value {
+        name: Symbol,
+        value_type: Type,
+    },
+    Type(Vec<TypeDefinition>),
+    Exception {
+        name: Symbol,
+        argument: Option<Type>,
+    },
+}
+
+impl Display for Specification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Specification::Value { name, value_type } => write!(f, "(val {} {})", name, value_type),
+            Specification::Type(definitions) => {
+                write!(f, "(type")?;
+                for definition in definitions {
+                    write!(f, " {}", definition)?;
+                }
+                write!(f, ")")
+            }
+            Specification::Exception {
+                name,
+                argument: Some(argument),
+            } => write!(f, "(exception {} {})", name, argument),
+            Specification::Exception {
+                name,
+                argument: None,
+            } => write!(f, "(exception {})", name),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Expression {
     Integer(RInteger),
     Bool(RBool),
+    String(RString),
+    Char(RChar),
+    Unit,
     Variable(Symbol),
     Plus {
         expression1: Box<Expression>,
@@ -19,10 +230,55 @@ pub enum Expression {
         expression1: Box<Expression>,
         expression2: Box<Expression>,
     },
+    Divide {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    Modulo {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    Negate(Box<Expression>),
     LessThan {
         expression1: Box<Expression>,
         expression2: Box<Expression>,
     },
+    Equal {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    NotEqual {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    LessEqual {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    GreaterThan {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    GreaterEqual {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    And {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    Or {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    Concat {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
+    Append {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
     If {
         predicate: Box<Expression>,
         consequent: Box<Expression>,
@@ -33,6 +289,10 @@ pub enum Expression {
         bound: Box<Expression>,
         body: Box<Expression>,
     },
+    Sequence {
+        expression1: Box<Expression>,
+        expression2: Box<Expression>,
+    },
     Fun {
         parameter: Symbol,
         body: Box<Expression>,
@@ -42,8 +302,7 @@ pub enum Expression {
         argument: Box<Expression>,
     },
     LetRec {
-        variable: Symbol,
-        bound_function: Box<Expression>, // Expressionを評価した結果が再帰関数であることを暗黙的に前提とする
+        bindings: Vec<(Symbol, Expression)>, // 束縛する式はすべてfunであることを暗黙的に前提とする
         body: Box<Expression>,
     },
     Nil,
@@ -53,8 +312,50 @@ pub enum Expression {
     },
     Match {
         scrutinee: Box<Expression>,
-        nil_case: Box<Expression>,
-        cons_pattern: (Symbol, Symbol, Box<Expression>),
+        arms: Vec<(Pattern, Expression)>,
+    },
+    Tuple(Vec<Expression>),
+    Constructor {
+        name: Symbol,
+        argument: Option<Box<Expression>>,
+    },
+    TypeDefinition {
+        definitions: Vec<TypeDefinition>,
+        body: Box<Expression>,
+    },
+    Record(Vec<(Symbol, Expression)>),
+    Field {
+        record: Box<Expression>,
+        label: Symbol,
+    },
+    RecordUpdate {
+        record: Box<Expression>,
+        fields: Vec<(Symbol, Expression)>,
+    },
+    FieldAssignment {
+        record: Box<Expression>,
+        label: Symbol,
+        value: Box<Expression>,
+    },
+    Ref(Box<Expression>),
+    Deref(Box<Expression>),
+    Assign {
+        reference: Box<Expression>,
+        value: Box<Expression>,
+    },
+    Raise(Box<Expression>),
+    Try {
+        body: Box<Expression>,
+        arms: Vec<(Pattern, Expression)>,
+    },
+    Assert(Box<Expression>),
+    Annotation {
+        expression: Box<Expression>,
+        annotation: Type,
+    },
+    Open {
+        module: Symbol,
+        body: Box<Expression>,
     },
 }
 
@@ -63,6 +364,9 @@ impl Display for Expression {
         match self {
             Expression::Integer(i) => write!(f, "{}", i),
             Expression::Bool(b) => write!(f, "{}", b),
+            Expression::String(s) => write!(f, "\"{}\"", escape_string(s)),
+            Expression::Char(c) => write!(f, "'{}'", escape_char(*c)),
+            Expression::Unit => write!(f, "()"),
             Expression::Variable(sym) => write!(f, "{}", sym),
             Expression::Plus {
                 expression1,
@@ -76,10 +380,55 @@ impl Display for Expression {
                 expression1,
                 expression2,
             } => write!(f, "(* {} {})", expression1, expression2),
+            Expression::Divide {
+                expression1,
+                expression2,
+            } => write!(f, "(/ {} {})", expression1, expression2),
+            Expression::Modulo {
+                expression1,
+                expression2,
+            } => write!(f, "(mod {} {})", expression1, expression2),
+            Expression::Negate(expression) => write!(f, "(neg {})", expression),
             Expression::LessThan {
                 expression1,
                 expression2,
             } => write!(f, "(< {} {})", expression1, expression2),
+            Expression::Equal {
+                expression1,
+                expression2,
+            } => write!(f, "(= {} {})", expression1, expression2),
+            Expression::NotEqual {
+                expression1,
+                expression2,
+            } => write!(f, "(<> {} {})", expression1, expression2),
+            Expression::LessEqual {
+                expression1,
+                expression2,
+            } => write!(f, "(<= {} {})", expression1, expression2),
+            Expression::GreaterThan {
+                expression1,
+                expression2,
+            } => write!(f, "(> {} {})", expression1, expression2),
+            Expression::GreaterEqual {
+                expression1,
+                expression2,
+            } => write!(f, "(>= {} {})", expression1, expression2),
+            Expression::And {
+                expression1,
+                expression2,
+            } => write!(f, "(&& {} {})", expression1, expression2),
+            Expression::Or {
+                expression1,
+                expression2,
+            } => write!(f, "(|| {} {})", expression1, expression2),
+            Expression::Concat {
+                expression1,
+                expression2,
+            } => write!(f, "(^ {} {})", expression1, expression2),
+            Expression::Append {
+                expression1,
+                expression2,
+            } => write!(f, "(@ {} {})", expression1, expression2),
             Expression::If {
                 predicate,
                 consequent,
@@ -90,26 +439,224 @@ impl Display for Expression {
                 bound,
                 body,
             } => write!(f, "(let ({} {}) {})", variable, bound, body),
+            Expression::Sequence {
+                expression1,
+                expression2,
+            } => write!(f, "(seq {} {})", expression1, expression2),
             Expression::Fun { parameter, body } => write!(f, "(fun {} {})", parameter, body),
             Expression::App { function, argument } => write!(f, "(app {} {})", function, argument),
-            Expression::LetRec {
-                variable,
-                bound_function,
-                body,
-            } => write!(f, "(letrec ({} {}) {})", variable, bound_function, body),
+            Expression::LetRec { bindings, body } => {
+                write!(f, "(letrec")?;
+                for (variable, bound_function) in bindings {
+                    write!(f, " ({} {})", variable, bound_function)?;
+                }
+                write!(f, " {})", body)
+            }
             Expression::Nil => write!(f, "nil"),
             Expression::Cons { car, cdr } => write!(f, "(cons {} {})", car, cdr),
-            Expression::Match {
-                scrutinee,
-                nil_case,
-                cons_pattern,
-            } => {
-                let (car, cdr, cons_body) = cons_pattern;
-                write!(
-                    f,
-                    "(match {} (nil {}) (cons ({} {}) {}))",
-                    scrutinee, nil_case, car, cdr, cons_body
-                )
+            Expression::Match { scrutinee, arms } => {
+                write!(f, "(match {}", scrutinee)?;
+                for (pattern, body) in arms {
+                    write!(f, " ({} {})", pattern, body)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Tuple(expressions) => {
+                write!(f, "(tuple")?;
+                for expression in expressions {
+                    write!(f, " {}", expression)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Constructor {
+                name,
+                argument: Some(argument),
+            } => write!(f, "({} {})", name, argument),
+            Expression::Constructor {
+                name,
+                argument: None,
+            } => write!(f, "{}", name),
+            Expression::TypeDefinition { definitions, body } => {
+                write!(f, "(type")?;
+                for definition in definitions {
+                    write!(f, " {}", definition)?;
+                }
+                write!(f, " {})", body)
+            }
+            Expression::Record(fields) => {
+                write!(f, "(record")?;
+                for (label, expression) in fields {
+                    write!(f, " ({} {})", label, expression)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Field { record, label } => write!(f, "(. {} {})", record, label),
+            Expression::RecordUpdate { record, fields } => {
+                write!(f, "(with {}", record)?;
+                for (label, expression) in fields {
+                    write!(f, " ({} {})", label, expression)?;
+                }
+                write!(f, ")")
+            }
+            Expression::FieldAssignment {
+                record,
+                label,
+                value,
+            } => write!(f, "(<- (. {} {}) {})", record, label, value),
+            Expression::Ref(expression) => write!(f, "(ref {})", expression),
+            Expression::Deref(expression) => write!(f, "(! {})", expression),
+            Expression::Assign { reference, value } => write!(f, "(:= {} {})", reference, value),
+            Expression::Raise(expression) => write!(f, "(raise {})", expression),
+            Expression::Try { body, arms } => {
+                write!(f, "(try {}", body)?;
+                for (pattern, body) in arms {
+                    write!(f, " ({} {})", pattern, body)?;
+                }
+                write!(f, ")")
+            }
+            Expression::Assert(expression) => write!(f, "(assert {})", expression),
+            Expression::Annotation {
+                expression,
+                annotation,
+            } => write!(f, "(: {} {})", expression, annotation),
+            Expression::Open { module, body } => write!(f, "(open {} {})", module, body),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeDefinition {
+    pub name: Symbol,
+    pub parameters: Vec<Symbol>,
+    pub kind: TypeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeKind {
+    Variant(Vec<(Symbol, Option<Type>)>),
+    Record(Vec<RecordField>),
+    Abstract,
+    // type t = int のような型の別名
+    Alias(Type),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordField {
+    pub label: Symbol,
+    pub mutable: bool,
+    pub field_type: Type,
+}
+
+impl Display for TypeDefinition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(")?;
+        for parameter in &self.parameters {
+            write!(f, "{} ", parameter)?;
+        }
+        write!(f, "{}", self.name)?;
+        match &self.kind {
+            TypeKind::Variant(constructors) => {
+                for (constructor, argument_type) in constructors {
+                    match argument_type {
+                        Some(argument_type) => write!(f, " ({} {})", constructor, argument_type)?,
+                        None => write!(f, " {}", constructor)?,
+                    }
+                }
+            }
+            TypeKind::Record(fields) => {
+                for field in fields {
+                    let mutable = if field.mutable { "mutable " } else { "" };
+                    write!(f, " ({}{} {})", mutable, field.label, field.field_type)?;
+                }
+            }
+            TypeKind::Abstract => {}
+            TypeKind::Alias(t) => write!(f, " = {}", t)?,
+        }
+        write!(f, ")")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Wildcard,
+    Variable(Symbol),
+    Integer(RInteger),
+    Bool(RBool),
+    String(RString),
+    Char(RChar),
+    Unit,
+    Nil,
+    Cons {
+        car: Box<Pattern>,
+        cdr: Box<Pattern>,
+    },
+    Tuple(Vec<Pattern>),
+    Constructor {
+        name: Symbol,
+        argument: Option<Box<Pattern>>,
+    },
+    Record(Vec<(Symbol, Pattern)>),
+}
+
+impl Pattern {
+    pub fn variables(&self) -> Vec<Symbol> {
+        match self {
+            Pattern::Variable(variable) => vec![variable.clone()],
+            Pattern::Cons { car, cdr } => [car.variables(), cdr.variables()].concat(),
+            Pattern::Tuple(patterns) => patterns.iter().flat_map(Pattern::variables).collect(),
+            Pattern::Constructor {
+                argument: Some(argument),
+                ..
+            } => argument.variables(),
+            Pattern::Constructor { argument: None, .. } => vec![],
+            Pattern::Record(fields) => fields
+                .iter()
+                .flat_map(|(_, pattern)| pattern.variables())
+                .collect(),
+            Pattern::Wildcard
+            | Pattern::Integer(_)
+            | Pattern::Bool(_)
+            | Pattern::String(_)
+            | Pattern::Char(_)
+            | Pattern::Unit
+            | Pattern::Nil => vec![],
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Variable(sym) => write!(f, "{}", sym),
+            Pattern::Integer(i) => write!(f, "{}", i),
+            Pattern::Bool(b) => write!(f, "{}", b),
+            Pattern::String(s) => write!(f, "\"{}\"", escape_string(s)),
+            Pattern::Char(c) => write!(f, "'{}'", escape_char(*c)),
+            Pattern::Unit => write!(f, "()"),
+            Pattern::Nil => write!(f, "nil"),
+            Pattern::Cons { car, cdr } => write!(f, "(cons {} {})", car, cdr),
+            Pattern::Tuple(patterns) => {
+                write!(f, "(tuple")?;
+                for pattern in patterns {
+                    write!(f, " {}", pattern)?;
+                }
+                write!(f, ")")
+            }
+            Pattern::Constructor {
+                name,
+                argument: Some(argument),
+            } => write!(f, "({} {})", name, argument),
+            Pattern::Constructor {
+                name,
+                argument: None,
+            } => write!(f, "{}", name),
+            Pattern::Record(fields) => {
+                write!(f, "(record")?;
+                for (label, pattern) in fields {
+                    write!(f, " ({} {})", label, pattern)?;
+                }
+                write!(f, ")")
             }
         }
     }
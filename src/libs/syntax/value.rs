@@ -1,8 +1,10 @@
-use std::fmt::Display;
+use std::{fmt::Display, sync::Arc};
+
+use anyhow::Result;
 
 use crate::{
-    adapter::{RBool, RInteger, Symbol},
-    execution::environment::Environment,
+    adapter::{RBool, RChar, RInteger, RString, Symbol, escape_char, escape_string},
+    execution::{environment::Environment, store::Location},
     syntax::ast::Expression,
 };
 
@@ -10,40 +12,171 @@ use crate::{
 pub enum Value {
     Integer(RInteger),
     Bool(RBool),
+    String(RString),
+    Char(RChar),
+    Unit,
     Closure {
         environment: Environment,
         parameter: Symbol,
         body: Expression,
     },
+    // let rec ... and ... で同時に定義した関数の組全体を持ち、そのうちcall_nameの関数を表す
     RecClosure {
         environment: Environment,
         call_name: Symbol,
-        parameter: Symbol,
-        body: Expression,
+        functions: Vec<RecursiveFunction>,
     },
     Nil,
+    // 束縛や照合で値を複製しても、リストの残りは複製せずに共有する
     Cons {
-        car: Box<Value>,
-        cdr: Box<Value>,
+        car: Arc<Value>,
+        cdr: Arc<Value>,
+    },
+    Tuple(Vec<Value>),
+    // tag は型の中で構築子を宣言した順の番号。例外なら宣言ごとに違う番号になる
+    Constructor {
+        name: Symbol,
+        tag: usize,
+        argument: Option<Box<Value>>,
+    },
+    Record(Vec<(Symbol, Location)>),
+    Ref(Location),
+    // Rustで実装した関数。arity個の引数が揃うまではargumentsに溜めておく
+    Primitive {
+        name: Symbol,
+        arity: usize,
+        arguments: Vec<Value>,
+        function: PrimitiveFunction,
     },
 }
 
+// 値は例外としてanyhow::Errorに載るので、関数もSend + Syncにしておく
+#[derive(Clone)]
+pub struct PrimitiveFunction(Arc<dyn Fn(Vec<Value>) -> Result<Value> + Send + Sync>);
+
+impl PrimitiveFunction {
+    pub fn new(function: impl Fn(Vec<Value>) -> Result<Value> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(function))
+    }
+
+    pub fn call(&self, arguments: Vec<Value>) -> Result<Value> {
+        (self.0)(arguments)
+    }
+}
+
+impl std::fmt::Debug for PrimitiveFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PrimitiveFunction")
+    }
+}
+
+// 同じ関数を指すときだけ等しい
+impl PartialEq for PrimitiveFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PrimitiveFunction {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecursiveFunction {
+    pub name: Symbol,
+    pub parameter: Symbol,
+    pub body: Expression,
+}
+
+// 書き換え可能なフィールドや参照で自分自身を指す値もあるので、入れ子が深すぎる部分は ... と表示する
+const MAX_DISPLAY_DEPTH: usize = 100;
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        write!(f, "{}", Nested(self, 0))
+    }
+}
+
+// 入れ子の深さを数えながら値を表示する
+struct Nested<'a>(&'a Value, usize);
+
+impl Nested<'_> {
+    fn child<'b>(&self, value: &'b Value) -> Nested<'b> {
+        Nested(value, self.1 + 1)
+    }
+}
+
+impl Display for Nested<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Nested(value, depth) = *self;
+        if depth > MAX_DISPLAY_DEPTH {
+            return write!(f, "...");
+        }
+        match value {
             Value::Integer(i) => write!(f, "{}", i),
             Value::Bool(b) => write!(f, "{}", b),
+            Value::String(s) => write!(f, "\"{}\"", escape_string(s)),
+            Value::Char(c) => write!(f, "'{}'", escape_char(*c)),
+            Value::Unit => write!(f, "()"),
             Value::Closure {
                 parameter, body, ..
             } => write!(f, "<fun {} -> {}>", parameter, body),
             Value::RecClosure {
                 call_name,
-                parameter,
-                body,
+                functions,
+                ..
+            } => match functions
+                .iter()
+                .find(|function| &function.name == call_name)
+            {
+                Some(function) => write!(
+                    f,
+                    "<recfun {} {} -> {}>",
+                    call_name, function.parameter, function.body
+                ),
+                None => write!(f, "<recfun {}>", call_name),
+            },
+            Value::Nil => write!(f, "[]"),
+            Value::Cons { car, cdr } => {
+                let mut elements = vec![self.child(car).to_string()];
+                let mut rest = &**cdr;
+                while let Value::Cons { car, cdr } = rest {
+                    elements.push(self.child(car).to_string());
+                    rest = cdr;
+                }
+                write!(f, "[{}]", elements.join("; "))
+            }
+            Value::Tuple(values) => {
+                let components = values
+                    .iter()
+                    .map(|value| self.child(value).to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "({})", components.join(", "))
+            }
+            Value::Constructor {
+                name,
+                argument: Some(argument),
+                ..
+            } => match **argument {
+                // 読み直して同じ値になるように、引数をとる構築子と負の整数は括弧で囲む
+                Value::Constructor {
+                    argument: Some(_), ..
+                } => write!(f, "{} ({})", name, self.child(argument)),
+                Value::Integer(n) if n < 0 => write!(f, "{} ({})", name, n),
+                _ => write!(f, "{} {}", name, self.child(argument)),
+            },
+            Value::Constructor {
+                name,
+                argument: None,
                 ..
-            } => write!(f, "<recfun {} {} -> {}>", call_name, parameter, body),
-            Value::Nil => write!(f, "nil"),
-            Value::Cons { car, cdr } => write!(f, "(cons {} {})", car, cdr),
+            } => write!(f, "{}", name),
+            Value::Record(fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(label, location)| format!("{} = {}", label, self.child(&location.get())))
+                    .collect::<Vec<_>>();
+                write!(f, "{{ {} }}", fields.join("; "))
+            }
+            Value::Ref(location) => write!(f, "{{ contents = {} }}", self.child(&location.get())),
+            Value::Primitive { name, .. } => write!(f, "<primitive {}>", name),
         }
     }
 }
@@ -1,5 +1,7 @@
+pub mod exhaustiveness;
 pub mod inference;
 pub mod type_environment;
 pub mod type_scheme;
 pub mod types;
 pub mod unification;
+pub mod warning;
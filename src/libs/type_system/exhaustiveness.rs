@@ -0,0 +1,600 @@
+use anyhow::{Ok, Result};
+
+use crate::{
+    adapter::{
+        RBool, RChar, RInteger, RString, Symbol, TypeTraverseHistory, base_name, unique_symbol,
+    },
+    syntax::ast::{Pattern, TypeKind},
+    type_system::{
+        type_environment::{EXCEPTION_TYPE, TypeEnvironment},
+        types::{BaseType, Type},
+        warning::Warning,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Constructor {
+    Integer(RInteger),
+    Bool(RBool),
+    String(RString),
+    Char(RChar),
+    Unit,
+    Nil,
+    Cons,
+    Tuple(usize),
+    Variant { name: Symbol, arity: usize },
+    Record(Vec<Symbol>),
+}
+
+impl Constructor {
+    fn arity(&self) -> usize {
+        match self {
+            Constructor::Cons => 2,
+            Constructor::Tuple(arity) | Constructor::Variant { arity, .. } => *arity,
+            Constructor::Record(labels) => labels.len(),
+            Constructor::Integer(_)
+            | Constructor::Bool(_)
+            | Constructor::String(_)
+            | Constructor::Char(_)
+            | Constructor::Unit
+            | Constructor::Nil => 0,
+        }
+    }
+
+    fn argument_types(&self, type_environment: &TypeEnvironment, t: &Type) -> Vec<Type> {
+        match (self, t) {
+            (Constructor::Cons, Type::List(element_type)) => vec![*element_type.clone(), t.clone()],
+            (Constructor::Tuple(_), Type::Tuple(types)) => types.clone(),
+            (Constructor::Variant { name, .. }, _) => {
+                match type_environment.get_constructor(name) {
+                    Some((constructed_type, Some(argument_type))) => {
+                        vec![instantiate_as(&constructed_type, t, argument_type)]
+                    }
+                    _ => vec![],
+                }
+            }
+            (Constructor::Record(labels), _) => {
+                let Some((record_type, fields)) = type_environment.get_record(&labels[0]) else {
+                    return vec![];
+                };
+                fields
+                    .into_iter()
+                    .map(|field| instantiate_as(&record_type, t, field.field_type))
+                    .collect()
+            }
+            _ => (0..self.arity())
+                .map(|_| Type::Variable {
+                    name: unique_symbol(),
+                })
+                .collect(),
+        }
+    }
+
+    fn to_pattern(&self, mut arguments: Vec<Pattern>) -> Pattern {
+        match self {
+            Constructor::Integer(i) => Pattern::Integer(*i),
+            Constructor::Bool(b) => Pattern::Bool(*b),
+            Constructor::String(s) => Pattern::String(s.clone()),
+            Constructor::Char(c) => Pattern::Char(*c),
+            Constructor::Unit => Pattern::Unit,
+            Constructor::Nil => Pattern::Nil,
+            Constructor::Cons => {
+                let cdr = arguments.pop().unwrap_or(Pattern::Wildcard);
+                let car = arguments.pop().unwrap_or(Pattern::Wildcard);
+                Pattern::Cons {
+                    car: car.into(),
+                    cdr: cdr.into(),
+                }
+            }
+            Constructor::Tuple(_) => Pattern::Tuple(arguments),
+            Constructor::Variant { name, .. } => Pattern::Constructor {
+                name: name.clone(),
+                argument: arguments.pop().map(Box::new),
+            },
+            Constructor::Record(labels) => {
+                Pattern::Record(labels.iter().cloned().zip(arguments).collect())
+            }
+        }
+    }
+}
+
+// `generic_type` は型パラメータを新しい型変数で具体化した型なので、
+// その型変数を検査対象の型 `t` の引数で置き換えて `component_type` に適用する
+fn instantiate_as(generic_type: &Type, t: &Type, component_type: Type) -> Type {
+    let (
+        Type::Constructor {
+            args: parameters, ..
+        },
+        Type::Constructor { args, .. },
+    ) = (generic_type, t)
+    else {
+        return component_type;
+    };
+
+    parameters
+        .iter()
+        .zip(args)
+        .fold(
+            component_type,
+            |component_type, (parameter, argument)| match parameter {
+                Type::Variable { name } => {
+                    component_type.apply_substitution_for_type(name.clone(), argument.clone())
+                }
+                _ => component_type,
+            },
+        )
+}
+
+// 単一化後の型環境に記録されたmatch式を検査し、網羅性と到達不能な腕についての警告を追加する
+pub fn check_matches(type_environment: TypeEnvironment) -> Result<TypeEnvironment> {
+    let (mut type_environment, matches) = type_environment.take_matches();
+
+    for (scrutinee_type, patterns) in matches {
+        let scrutinee_type =
+            type_environment.normalize_type(TypeTraverseHistory::new(), scrutinee_type)?;
+        for warning in check_match(&type_environment, &scrutinee_type, &patterns) {
+            type_environment = type_environment.add_warning(warning);
+        }
+    }
+
+    Ok(type_environment)
+}
+
+pub fn check_match(
+    type_environment: &TypeEnvironment,
+    scrutinee_type: &Type,
+    patterns: &[Pattern],
+) -> Vec<Warning> {
+    let types = vec![scrutinee_type.clone()];
+    let mut warnings = Vec::new();
+    let mut matrix: Vec<Vec<Pattern>> = Vec::new();
+
+    for pattern in patterns {
+        let row = vec![complete_records(type_environment, pattern)];
+        if useful(type_environment, &matrix, &row, &types).is_none() {
+            warnings.push(Warning::UnusedMatchCase {
+                pattern: pattern.clone(),
+            });
+        }
+        matrix.push(row);
+    }
+
+    if let Some(mut witness) = useful(type_environment, &matrix, &[Pattern::Wildcard], &types) {
+        warnings.push(Warning::NonExhaustiveMatch {
+            counterexample: witness.remove(0),
+        });
+    }
+
+    warnings
+}
+
+// レコードパターンで省略されたフィールドを `_` で補い、型定義の順に並べる
+fn complete_records(type_environment: &TypeEnvironment, pattern: &Pattern) -> Pattern {
+    match pattern {
+        Pattern::Cons { car, cdr } => Pattern::Cons {
+            car: complete_records(type_environment, car).into(),
+            cdr: complete_records(type_environment, cdr).into(),
+        },
+        Pattern::Tuple(patterns) => Pattern::Tuple(
+            patterns
+                .iter()
+                .map(|pattern| complete_records(type_environment, pattern))
+                .collect(),
+        ),
+        Pattern::Constructor { name, argument } => Pattern::Constructor {
+            name: qualified_constructor(type_environment, name),
+            argument: argument
+                .as_ref()
+                .map(|argument| complete_records(type_environment, argument).into()),
+        },
+        Pattern::Record(fields) => {
+            let Some((_, declared_fields)) = type_environment.get_record(&fields[0].0) else {
+                return pattern.clone();
+            };
+            Pattern::Record(
+                declared_fields
+                    .into_iter()
+                    .map(|field| {
+                        let pattern = fields
+                            .iter()
+                            .find(|(label, _)| *label == field.label)
+                            .map(|(_, pattern)| complete_records(type_environment, pattern))
+                            .unwrap_or(Pattern::Wildcard);
+                        (field.label, pattern)
+                    })
+                    .collect(),
+            )
+        }
+        pattern => pattern.clone(),
+    }
+}
+
+// M.A と open M をした後の A は同じ構築子なので、型の経路に構築子の名前を付けた名前にそろえる
+fn qualified_constructor(type_environment: &TypeEnvironment, name: &Symbol) -> Symbol {
+    match type_environment.get_constructor(name) {
+        Some((
+            Type::Constructor {
+                name: type_name, ..
+            },
+            _,
+        )) if type_name != EXCEPTION_TYPE => qualify(&type_name, base_name(name)),
+        _ => name.clone(),
+    }
+}
+
+// 型 M.t の構築子 A の名前 M.A を作る
+fn qualify(type_name: &str, name: &str) -> Symbol {
+    match type_name.rsplit_once('.') {
+        Some((path, _)) => format!("{path}.{name}"),
+        None => name.to_string(),
+    }
+}
+
+// `vector` が `matrix` のどの行にも照合しない値を持つなら、その値を表すパターンの列を返す
+fn useful(
+    type_environment: &TypeEnvironment,
+    matrix: &[Vec<Pattern>],
+    vector: &[Pattern],
+    types: &[Type],
+) -> Option<Vec<Pattern>> {
+    let Some(head) = vector.first() else {
+        return matrix.is_empty().then(Vec::new);
+    };
+
+    if let Some(constructor) = head_constructor(head) {
+        return useful_constructor(type_environment, matrix, vector, types, &constructor);
+    }
+
+    let seen = matrix
+        .iter()
+        .filter_map(|row| head_constructor(&row[0]))
+        .fold(Vec::new(), |mut seen, constructor| {
+            if !seen.contains(&constructor) {
+                seen.push(constructor);
+            }
+            seen
+        });
+
+    match signature(type_environment, &types[0], &seen) {
+        Some(constructors) if constructors.iter().all(|c| seen.contains(c)) => {
+            constructors.iter().find_map(|constructor| {
+                useful_constructor(type_environment, matrix, vector, types, constructor)
+            })
+        }
+        signature => {
+            let default_matrix = matrix
+                .iter()
+                .filter(|row| head_constructor(&row[0]).is_none())
+                .map(|row| row[1..].to_vec())
+                .collect::<Vec<_>>();
+            let witness = useful(type_environment, &default_matrix, &vector[1..], &types[1..])?;
+            let missing = missing_pattern(signature, &seen);
+            Some([vec![missing], witness].concat())
+        }
+    }
+}
+
+fn useful_constructor(
+    type_environment: &TypeEnvironment,
+    matrix: &[Vec<Pattern>],
+    vector: &[Pattern],
+    types: &[Type],
+    constructor: &Constructor,
+) -> Option<Vec<Pattern>> {
+    let specialized_matrix = matrix
+        .iter()
+        .filter_map(|row| specialize(row, constructor))
+        .collect::<Vec<_>>();
+    let specialized_vector = specialize(vector, constructor)?;
+    let specialized_types = [
+        constructor.argument_types(type_environment, &types[0]),
+        types[1..].to_vec(),
+    ]
+    .concat();
+
+    let mut witness = useful(
+        type_environment,
+        &specialized_matrix,
+        &specialized_vector,
+        &specialized_types,
+    )?;
+    let rest = witness.split_off(constructor.arity());
+    Some([vec![constructor.to_pattern(witness)], rest].concat())
+}
+
+fn head_constructor(pattern: &Pattern) -> Option<Constructor> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Variable(_) => None,
+        Pattern::Integer(i) => Some(Constructor::Integer(*i)),
+        Pattern::Bool(b) => Some(Constructor::Bool(*b)),
+        Pattern::String(s) => Some(Constructor::String(s.clone())),
+        Pattern::Char(c) => Some(Constructor::Char(*c)),
+        Pattern::Unit => Some(Constructor::Unit),
+        Pattern::Nil => Some(Constructor::Nil),
+        Pattern::Cons { .. } => Some(Constructor::Cons),
+        Pattern::Tuple(patterns) => Some(Constructor::Tuple(patterns.len())),
+        Pattern::Constructor { name, argument } => Some(Constructor::Variant {
+            name: name.clone(),
+            arity: argument.iter().len(),
+        }),
+        Pattern::Record(fields) => Some(Constructor::Record(
+            fields.iter().map(|(label, _)| label.clone()).collect(),
+        )),
+    }
+}
+
+fn specialize(row: &[Pattern], constructor: &Constructor) -> Option<Vec<Pattern>> {
+    let arguments = match &row[0] {
+        Pattern::Wildcard | Pattern::Variable(_) => vec![Pattern::Wildcard; constructor.arity()],
+        Pattern::Cons { car, cdr } if *constructor == Constructor::Cons => {
+            vec![*car.clone(), *cdr.clone()]
+        }
+        Pattern::Tuple(patterns) if *constructor == Constructor::Tuple(patterns.len()) => {
+            patterns.clone()
+        }
+        Pattern::Constructor {
+            argument: Some(argument),
+            ..
+        } if head_constructor(&row[0]).as_ref() == Some(constructor) => vec![*argument.clone()],
+        Pattern::Record(fields) if head_constructor(&row[0]).as_ref() == Some(constructor) => {
+            fields.iter().map(|(_, pattern)| pattern.clone()).collect()
+        }
+        pattern if head_constructor(pattern).as_ref() == Some(constructor) => vec![],
+        _ => return None,
+    };
+
+    Some([arguments, row[1..].to_vec()].concat())
+}
+
+// 型の構築子が有限個なら、そのすべてを返す
+fn signature(
+    type_environment: &TypeEnvironment,
+    t: &Type,
+    seen: &[Constructor],
+) -> Option<Vec<Constructor>> {
+    match (t, seen.first()) {
+        (Type::Constructor { name, .. }, _) => variants(type_environment, name),
+        (_, Some(Constructor::Record(labels))) => Some(vec![Constructor::Record(labels.clone())]),
+        (_, Some(Constructor::Variant { name, .. })) => {
+            let (Type::Constructor { name, .. }, _) = type_environment.get_constructor(name)?
+            else {
+                return None;
+            };
+            variants(type_environment, &name)
+        }
+        (Type::Base(BaseType::Bool), _) | (_, Some(Constructor::Bool(_))) => {
+            Some(vec![Constructor::Bool(true), Constructor::Bool(false)])
+        }
+        (Type::Base(BaseType::Unit), _) | (_, Some(Constructor::Unit)) => {
+            Some(vec![Constructor::Unit])
+        }
+        (Type::List(_), _) | (_, Some(Constructor::Nil | Constructor::Cons)) => {
+            Some(vec![Constructor::Nil, Constructor::Cons])
+        }
+        (Type::Tuple(types), _) => Some(vec![Constructor::Tuple(types.len())]),
+        (_, Some(Constructor::Tuple(arity))) => Some(vec![Constructor::Tuple(*arity)]),
+        _ => None,
+    }
+}
+
+fn variants(type_environment: &TypeEnvironment, type_name: &Symbol) -> Option<Vec<Constructor>> {
+    // 例外は後から宣言を増やせるので、構築子を列挙できない
+    if type_name == EXCEPTION_TYPE {
+        return None;
+    }
+    let definition = type_environment.get_type_definition(type_name)?;
+    match &definition.kind {
+        TypeKind::Variant(constructors) => Some(
+            constructors
+                .iter()
+                .map(|(name, argument_type)| Constructor::Variant {
+                    name: qualify(type_name, name),
+                    arity: argument_type.iter().len(),
+                })
+                .collect(),
+        ),
+        TypeKind::Record(fields) => Some(vec![Constructor::Record(
+            fields.iter().map(|field| field.label.clone()).collect(),
+        )]),
+        // 抽象型の値の形は外からは分からない。別名は型を解決するときに展開してある
+        TypeKind::Abstract | TypeKind::Alias(_) => None,
+    }
+}
+
+fn missing_pattern(signature: Option<Vec<Constructor>>, seen: &[Constructor]) -> Pattern {
+    if seen.is_empty() {
+        return Pattern::Wildcard;
+    }
+
+    match signature {
+        Some(constructors) => constructors
+            .into_iter()
+            .find(|constructor| !seen.contains(constructor))
+            .map(|constructor| {
+                let arguments = vec![Pattern::Wildcard; constructor.arity()];
+                constructor.to_pattern(arguments)
+            })
+            .unwrap_or(Pattern::Wildcard),
+        None => {
+            // 整数・文字列・文字は値が無限にあるので、まだ現れていない値を順に探す
+            let mut candidates: Box<dyn Iterator<Item = Constructor>> = match seen[0] {
+                Constructor::Integer(_) => Box::new((0..).map(Constructor::Integer)),
+                Constructor::String(_) => {
+                    Box::new((0..).map(|n| Constructor::String("a".repeat(n))))
+                }
+                Constructor::Char(_) => Box::new(('a'..=char::MAX).map(Constructor::Char)),
+                _ => return Pattern::Wildcard,
+            };
+            candidates
+                .find(|constructor| !seen.contains(constructor))
+                .map(|constructor| constructor.to_pattern(vec![]))
+                .unwrap_or(Pattern::Wildcard)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::syntax::ast::TypeDefinition;
+
+    fn int_list() -> Type {
+        Type::List(Type::Base(BaseType::Integer).into())
+    }
+
+    fn cons(car: Pattern, cdr: Pattern) -> Pattern {
+        Pattern::Cons {
+            car: car.into(),
+            cdr: cdr.into(),
+        }
+    }
+
+    #[test]
+    fn test_exhaustive_list_match() {
+        let patterns = vec![
+            Pattern::Nil,
+            cons(Pattern::Variable("h".to_string()), Pattern::Wildcard),
+        ];
+
+        let warnings = check_match(&TypeEnvironment::default(), &int_list(), &patterns);
+
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn test_non_exhaustive_list_match() {
+        // [] -> .. | _ :: [] -> ..
+        let patterns = vec![Pattern::Nil, cons(Pattern::Wildcard, Pattern::Nil)];
+
+        let warnings = check_match(&TypeEnvironment::default(), &int_list(), &patterns);
+
+        assert_eq!(
+            warnings,
+            vec![Warning::NonExhaustiveMatch {
+                counterexample: cons(
+                    Pattern::Wildcard,
+                    cons(Pattern::Wildcard, Pattern::Wildcard)
+                ),
+            }]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "this pattern-matching is not exhaustive. Here is an example of a case that is not matched: _ :: _ :: _"
+        );
+    }
+
+    #[test]
+    fn test_non_exhaustive_tuple_match() {
+        // (true, _) -> .. | (_, true) -> ..
+        let patterns = vec![
+            Pattern::Tuple(vec![Pattern::Bool(true), Pattern::Wildcard]),
+            Pattern::Tuple(vec![Pattern::Wildcard, Pattern::Bool(true)]),
+        ];
+        let t = Type::Tuple(vec![Type::Base(BaseType::Bool), Type::Base(BaseType::Bool)]);
+
+        let warnings = check_match(&TypeEnvironment::default(), &t, &patterns);
+
+        assert_eq!(
+            warnings,
+            vec![Warning::NonExhaustiveMatch {
+                counterexample: Pattern::Tuple(vec![Pattern::Bool(false), Pattern::Bool(false)]),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_non_exhaustive_integer_match() {
+        let patterns = vec![Pattern::Integer(0), Pattern::Integer(1)];
+
+        let warnings = check_match(
+            &TypeEnvironment::default(),
+            &Type::Base(BaseType::Integer),
+            &patterns,
+        );
+
+        assert_eq!(
+            warnings,
+            vec![Warning::NonExhaustiveMatch {
+                counterexample: Pattern::Integer(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_non_exhaustive_char_match() {
+        let patterns = vec![Pattern::Char('a'), Pattern::Char('b')];
+
+        let warnings = check_match(
+            &TypeEnvironment::default(),
+            &Type::Base(BaseType::Char),
+            &patterns,
+        );
+
+        assert_eq!(
+            warnings,
+            vec![Warning::NonExhaustiveMatch {
+                counterexample: Pattern::Char('c'),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_unused_match_case() {
+        // _ :: _ -> .. | [] -> .. | x :: [] -> ..
+        let unused = cons(Pattern::Variable("x".to_string()), Pattern::Nil);
+        let patterns = vec![
+            cons(Pattern::Wildcard, Pattern::Wildcard),
+            Pattern::Nil,
+            unused.clone(),
+        ];
+
+        let warnings = check_match(&TypeEnvironment::default(), &int_list(), &patterns);
+
+        assert_eq!(warnings, vec![Warning::UnusedMatchCase { pattern: unused }]);
+    }
+
+    #[test]
+    fn test_non_exhaustive_variant_match() {
+        // type t = A | B of t
+        let type_environment = TypeEnvironment::default().define_type(TypeDefinition {
+            name: "t".to_string(),
+            parameters: vec![],
+            kind: TypeKind::Variant(vec![
+                ("A".to_string(), None),
+                (
+                    "B".to_string(),
+                    Some(Type::Constructor {
+                        name: "t".to_string(),
+                        args: vec![],
+                    }),
+                ),
+            ]),
+        });
+        let constructor = |name: &str, argument: Option<Pattern>| Pattern::Constructor {
+            name: name.to_string(),
+            argument: argument.map(Box::new),
+        };
+        // A -> .. | B A -> ..
+        let patterns = vec![
+            constructor("A", None),
+            constructor("B", Some(constructor("A", None))),
+        ];
+        let t = Type::Constructor {
+            name: "t".to_string(),
+            args: vec![],
+        };
+
+        let warnings = check_match(&type_environment, &t, &patterns);
+
+        assert_eq!(
+            warnings,
+            vec![Warning::NonExhaustiveMatch {
+                counterexample: constructor("B", Some(constructor("B", Some(Pattern::Wildcard)))),
+            }]
+        );
+        assert_eq!(
+            warnings[0].to_string(),
+            "this pattern-matching is not exhaustive. Here is an example of a case that is not matched: B (B _)"
+        );
+    }
+}
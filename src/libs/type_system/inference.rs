@@ -1,18 +1,25 @@
 use crate::{
-    adapter::{Symbol, TypeTraverseHistory, unique_symbol},
-    syntax::ast::Expression,
+    adapter::{Symbol, TypeTraverseHistory, is_builtin_operator, unique_symbol, unique_type_name},
+    syntax::ast::{
+        Expression, ModuleExpression, ModuleType, Pattern, RecordField, Specification, TopLevel,
+        TypeDefinition, TypeKind,
+    },
     type_system::{
-        type_environment::TypeEnvironment,
+        exhaustiveness::check_matches,
+        type_environment::{EXCEPTION_TYPE, Functor, TypeEnvironment, map_definition_types},
         type_scheme::TypeScheme,
         types::{BaseType, Type},
+        warning::Warning,
     },
 };
-use anyhow::{Ok, Result, bail};
+use anyhow::{Ok, Result, anyhow, bail, ensure};
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
 use super::types::free_type_variables;
 
 type InferenceResult = Result<(TypeEnvironment, Type)>;
+type PatternInferenceResult = Result<(TypeEnvironment, Type, Vec<(Symbol, Type)>)>;
 
 #[derive(Debug, Error)]
 enum TypeInferenceError {
@@ -20,23 +27,870 @@ enum TypeInferenceError {
     Impossible(Expression),
     #[error("Invalid type: {0}")]
     InvalidType(Expression),
+    #[error("The built-in operator ( {0} ) cannot be redefined")]
+    BuiltinOperatorRedefinition(Symbol),
     #[error("Undefined variable: {0}")]
     UndefinedVariable(Expression),
+    #[error("Unbound constructor: {0}")]
+    UnboundConstructor(Symbol),
+    #[error("The constructor {0} expects {1} argument(s), but is applied here to {2} argument(s)")]
+    ConstructorArity(Symbol, usize, usize),
+    #[error("Unbound type constructor: {0}")]
+    UnboundTypeConstructor(Symbol),
+    #[error(
+        "The type constructor {0} expects {1} argument(s), but is here applied to {2} argument(s)"
+    )]
+    TypeArity(Symbol, usize, usize),
+    #[error("The expression {0} has type {1} but is annotated with type {2}")]
+    AnnotationMismatch(Expression, Type, Type),
+    #[error("Two constructors are named {0}")]
+    DuplicateConstructor(Symbol),
+    #[error("Multiple definition of the type name {0}")]
+    DuplicateTypeName(Symbol),
+    #[error("The type variable {0} is unbound in this type declaration")]
+    UnboundTypeParameter(Symbol),
+    #[error("Two labels are named {0}")]
+    DuplicateLabel(Symbol),
+    #[error("Unbound record field {0}")]
+    UnboundField(Symbol),
+    #[error("The record field {0} is defined several times")]
+    DuplicateField(Symbol),
+    #[error(
+        "The record field {0} belongs to the type {1} but is mixed here with fields of type {2}"
+    )]
+    MixedFields(Symbol, Symbol, Symbol),
+    #[error("Some record fields are undefined: {0}")]
+    UndefinedFields(String),
+    #[error("The record field {0} is not mutable")]
+    ImmutableField(Symbol),
+    #[error("Unbound module {0}")]
+    UnboundModule(Symbol),
+    #[error("Unbound module type {0}")]
+    UnboundModuleType(Symbol),
+    #[error("The value {0} is required but not provided")]
+    MissingValue(Symbol),
+    #[error("The type {0} is required but not provided")]
+    MissingType(Symbol),
+    #[error("The exception {0} is required but not provided")]
+    MissingException(Symbol),
+    #[error("Values do not match: {0} : {1} is not included in {0} : {2}")]
+    ValueMismatch(Symbol, Type, Type),
+    #[error("Type declarations do not match: type {0}")]
+    TypeMismatch(Symbol),
+    #[error("Exception declarations do not match: exception {0}")]
+    ExceptionMismatch(Symbol),
+    #[error("This module is not a functor: {0}")]
+    NotFunctor(ModuleExpression),
+    #[error("This module is a functor, not a structure: {0}")]
+    NotStructure(ModuleExpression),
+    #[error("The type {0} in this `with` constraint is not an abstract type of the signature")]
+    InvalidConstraint(Symbol),
+    #[error("The type abbreviation {0} is cyclic")]
+    CyclicAbbreviation(Symbol),
+    #[error("The type constructor {0} would escape its scope")]
+    TypeEscape(Symbol),
 }
 
 pub fn infer(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    let type_environment = type_environment
+        .clear_warnings()
+        .clear_annotation_variables();
     let (inferred_environment, inferred_type) = infer_expression(type_environment, expression)?;
     let unified_environment = inferred_environment.unify_equations()?;
     let normalized_type =
         unified_environment.normalize_type(TypeTraverseHistory::new(), inferred_type)?;
+    let checked_environment = check_matches(unified_environment)?;
+    let checked_environment = check_statements(checked_environment)?.apply_equations()?;
+
+    Ok((checked_environment, normalized_type))
+}
+
+// 型が決まらない文は警告しない
+fn check_statements(type_environment: TypeEnvironment) -> Result<TypeEnvironment> {
+    let (mut type_environment, statements) = type_environment.take_statements();
+
+    for statement_type in statements {
+        let statement_type =
+            type_environment.normalize_type(TypeTraverseHistory::new(), statement_type)?;
+        if !matches!(
+            statement_type,
+            Type::Base(BaseType::Unit) | Type::Variable { .. }
+        ) {
+            type_environment =
+                type_environment.add_warning(Warning::NonUnitStatement { statement_type });
+        }
+    }
+
+    Ok(type_environment)
+}
+
+// トップレベルの let p = e で、e の値を照合する前に束縛しておく名前。識別子として書けない名前にする
+const PATTERN_BOUND: &str = "%bound";
+
+// トップレベルの宣言を型検査し、宣言した名前や型を型環境に加える
+// 返す型は、式ならその型、let宣言なら束縛した値の型、型宣言ならunit
+// let p = e ならパターンの変数の型の組(変数が1つならその型、なければ e の型)
+pub fn infer_top_level(type_environment: TypeEnvironment, top_level: TopLevel) -> InferenceResult {
+    match top_level {
+        TopLevel::Expression(expression) => infer(type_environment, expression),
+        TopLevel::Let { variable, bound } => {
+            check_operator_definition(&type_environment, &variable)?;
+            let generalizable = is_nonexpansive(&type_environment, &bound);
+            let (type_environment, t) = infer(type_environment, bound)?;
+            declare_variable(type_environment, variable, t, generalizable)
+        }
+        TopLevel::LetPattern { pattern, bound } => {
+            let generalizable = is_nonexpansive(&type_environment, &bound);
+            let variables = pattern.variables();
+            let body = match variables.as_slice() {
+                [] => Expression::Variable(PATTERN_BOUND.to_string()),
+                [variable] => Expression::Variable(variable.clone()),
+                _ => Expression::Tuple(
+                    variables
+                        .iter()
+                        .cloned()
+                        .map(Expression::Variable)
+                        .collect(),
+                ),
+            };
+            let expression = Expression::Let {
+                variable: PATTERN_BOUND.to_string(),
+                bound: bound.into(),
+                body: Expression::Match {
+                    scrutinee: Expression::Variable(PATTERN_BOUND.to_string()).into(),
+                    arms: vec![(pattern, body.clone())],
+                }
+                .into(),
+            };
+            let (type_environment, t) = infer(type_environment, expression)?;
+
+            let types = match (&t, variables.len()) {
+                (_, 0) => vec![],
+                (_, 1) => vec![t.clone()],
+                (Type::Tuple(types), _) => types.clone(),
+                _ => bail!(TypeInferenceError::InvalidType(body)),
+            };
+            let type_environment = variables.into_iter().zip(types).try_fold(
+                type_environment,
+                |type_environment, (variable, t)| {
+                    declare_variable(type_environment, variable, t, generalizable)
+                        .map(|(type_environment, _)| type_environment)
+                },
+            )?;
+
+            Ok((type_environment, t))
+        }
+        TopLevel::LetRec { bindings } => {
+            let variables = bindings
+                .iter()
+                .map(|(variable, _)| variable.clone())
+                .collect::<Vec<_>>();
+            let body = match variables.as_slice() {
+                [variable] => Expression::Variable(variable.clone()),
+                _ => Expression::Tuple(
+                    variables
+                        .iter()
+                        .cloned()
+                        .map(Expression::Variable)
+                        .collect(),
+                ),
+            };
+            let expression = Expression::LetRec {
+                bindings,
+                body: body.clone().into(),
+            };
+            let (type_environment, t) = infer(type_environment, expression)?;
+
+            let types = match (&t, variables.len()) {
+                (_, 1) => vec![t.clone()],
+                (Type::Tuple(types), _) => types.clone(),
+                _ => bail!(TypeInferenceError::InvalidType(body)),
+            };
+            let type_environment = variables.into_iter().zip(types).try_fold(
+                type_environment,
+                |type_environment, (variable, t)| {
+                    declare_variable(type_environment, variable, t, true)
+                        .map(|(type_environment, _)| type_environment)
+                },
+            )?;
+
+            Ok((type_environment, t))
+        }
+        TopLevel::TypeDefinition(definitions) => {
+            let type_environment = shadow_types(type_environment.clear_warnings(), &definitions);
+            let type_environment = define_types(type_environment, definitions)?;
+            Ok((type_environment, Type::Base(BaseType::Unit)))
+        }
+        TopLevel::Exception { name, argument } => {
+            if let Some(argument) = &argument {
+                check_type(&type_environment, &[], argument)?;
+            }
+            let argument = argument.map(|argument| type_environment.resolve_type(argument));
+            let type_environment = type_environment
+                .clear_warnings()
+                .define_exception(name, argument);
+            Ok((type_environment, exception_type()))
+        }
+        TopLevel::Module { name, module } => {
+            let type_environment = type_environment.clear_warnings().shadow_module(&name);
+            let type_environment = match infer_module(type_environment, &name, module)? {
+                (type_environment, ModuleSignature::Structure(namespace)) => {
+                    type_environment.define_module(name, namespace)
+                }
+                (type_environment, ModuleSignature::Functor(functor)) => {
+                    type_environment.define_functor(name, functor)
+                }
+            };
+            Ok((type_environment, Type::Base(BaseType::Unit)))
+        }
+        TopLevel::ModuleType { name, module_type } => {
+            let specifications = module_type_specifications(&type_environment, module_type)?;
+            let type_environment = type_environment
+                .clear_warnings()
+                .define_module_type(name, specifications);
+            Ok((type_environment, Type::Base(BaseType::Unit)))
+        }
+        TopLevel::Open(path) => {
+            let namespace = get_module(&type_environment, &path)?.clone();
+            let type_environment = type_environment.clear_warnings().open_module(&namespace);
+            Ok((type_environment, Type::Base(BaseType::Unit)))
+        }
+    }
+}
+
+// モジュール式の型。構造はその中の名前の型を持つ型環境、ファンクタは本体と引数のシグネチャを持つ
+enum ModuleSignature {
+    Structure(TypeEnvironment),
+    Functor(Functor),
+}
+
+// シグネチャの型の名前から、その型を表す型への対応。型パラメータを引数の型で置き換えて使う
+type TypeMap = HashMap<Symbol, (Vec<Symbol>, Type)>;
+
+// モジュール式を型検査する
+// module_name はモジュールの中で定義した型に付ける名前 (M.t の M)
+fn infer_module(
+    type_environment: TypeEnvironment,
+    module_name: &Symbol,
+    module: ModuleExpression,
+) -> Result<(TypeEnvironment, ModuleSignature)> {
+    match module {
+        ModuleExpression::Structure(top_levels) => {
+            let (type_environment, namespace) =
+                infer_structure(type_environment, module_name, top_levels)?;
+            Ok((type_environment, ModuleSignature::Structure(namespace)))
+        }
+        ModuleExpression::Path(path) => {
+            if let Some(functor) = type_environment.get_functor(&path) {
+                let functor = functor.clone();
+                return Ok((type_environment, ModuleSignature::Functor(functor)));
+            }
+            let namespace = get_module(&type_environment, &path)?.clone();
+            Ok((type_environment, ModuleSignature::Structure(namespace)))
+        }
+        ModuleExpression::Ascription {
+            module,
+            module_type,
+        } => {
+            let (type_environment, implementation) =
+                infer_structure_expression(type_environment, module_name, *module)?;
+            let specifications = module_type_specifications(&type_environment, module_type)?;
+            let namespace = match_signature(
+                &type_environment,
+                &implementation,
+                specifications,
+                module_name,
+            )?;
+            Ok((type_environment, ModuleSignature::Structure(namespace)))
+        }
+        ModuleExpression::Functor {
+            parameter,
+            parameter_type,
+            body,
+        } => {
+            let parameter_type = module_type_specifications(&type_environment, parameter_type)?;
+            // 引数の型を抽象型のままにして本体を型検査し、どの引数に適用しても型が合うことを確かめる
+            let namespace = signature_namespace(parameter_type.clone(), &parameter, None)?;
+            let (checked_environment, _) = infer_module(
+                type_environment
+                    .clone()
+                    .define_module(parameter.clone(), namespace),
+                module_name,
+                (*body).clone(),
+            )?;
+            let type_environment = checked_environment
+                .warnings()
+                .iter()
+                .cloned()
+                .fold(type_environment.clone(), TypeEnvironment::add_warning);
+
+            let functor = Functor {
+                parameter,
+                parameter_type,
+                body: *body,
+                scope: type_environment.clone().clear_warnings(),
+            };
+            Ok((type_environment, ModuleSignature::Functor(functor)))
+        }
+        ModuleExpression::Application { functor, argument } => {
+            let (type_environment, signature) =
+                infer_module(type_environment, module_name, (*functor).clone())?;
+            let ModuleSignature::Functor(functor) = signature else {
+                bail!(TypeInferenceError::NotFunctor(*functor));
+            };
+            let (type_environment, argument) =
+                infer_structure_expression(type_environment, &functor.parameter, *argument)?;
+
+            // 引数の抽象型を引数の実際の型の別名にしてから当てはめ、本体から引数の型が見えるようにする
+            let parameter_type = strengthen(&argument, functor.parameter_type)?;
+            let parameter = match_signature(
+                &type_environment,
+                &argument,
+                parameter_type,
+                &functor.parameter,
+            )?;
+            // 警告は定義したときに報告しているので、ここでは捨てる
+            let scope = functor.scope.define_module(functor.parameter, parameter);
+            let (_, result) = infer_module(scope, module_name, functor.body)?;
+            Ok((type_environment, result))
+        }
+    }
+}
+
+// 構造でなければならないモジュール式を型検査し、その中の名前の型を持つ型環境を返す
+fn infer_structure_expression(
+    type_environment: TypeEnvironment,
+    module_name: &Symbol,
+    module: ModuleExpression,
+) -> Result<(TypeEnvironment, TypeEnvironment)> {
+    match infer_module(type_environment, module_name, module.clone())? {
+        (type_environment, ModuleSignature::Structure(namespace)) => {
+            Ok((type_environment, namespace))
+        }
+        (_, ModuleSignature::Functor(_)) => bail!(TypeInferenceError::NotStructure(module)),
+    }
+}
+
+// struct ... end の宣言を順に型検査する。各宣言の警告はまとめて報告する
+fn infer_structure(
+    type_environment: TypeEnvironment,
+    module_name: &Symbol,
+    top_levels: Vec<TopLevel>,
+) -> Result<(TypeEnvironment, TypeEnvironment)> {
+    let scope = type_environment.clone();
+    let mut warnings = Vec::new();
+    let type_environment =
+        top_levels
+            .iter()
+            .try_fold(type_environment, |type_environment, top_level| {
+                let (type_environment, _) = infer_top_level(type_environment, top_level.clone())?;
+                warnings.extend(type_environment.warnings().iter().cloned());
+                Ok(type_environment)
+            })?;
+
+    let (type_environment, namespace) =
+        type_environment.leave_module(&scope, module_name, &top_levels);
+    let type_environment = warnings.into_iter().fold(
+        type_environment.clear_warnings(),
+        TypeEnvironment::add_warning,
+    );
+
+    Ok((type_environment, namespace))
+}
+
+fn get_module<'a>(
+    type_environment: &'a TypeEnvironment,
+    path: &Symbol,
+) -> Result<&'a TypeEnvironment> {
+    type_environment
+        .get_module(path)
+        .ok_or_else(|| anyhow!(TypeInferenceError::UnboundModule(path.clone())))
+}
+
+// シグネチャの宣言を検査し、型式中の型の名前を正式な名前にして返す
+// シグネチャの中で宣言した型の名前は、モジュールに当てはめるまでそのままにしておく
+fn module_type_specifications(
+    type_environment: &TypeEnvironment,
+    module_type: ModuleType,
+) -> Result<Vec<Specification>> {
+    let specifications = match module_type {
+        ModuleType::Path(path) => {
+            return type_environment
+                .get_module_type(&path)
+                .cloned()
+                .ok_or_else(|| anyhow!(TypeInferenceError::UnboundModuleType(path.clone())));
+        }
+        ModuleType::With {
+            module_type,
+            constraints,
+        } => {
+            let specifications = module_type_specifications(type_environment, *module_type)?;
+            return constraints.into_iter().try_fold(
+                specifications,
+                |specifications, constraint| {
+                    constrain(type_environment, specifications, constraint)
+                },
+            );
+        }
+        ModuleType::Signature(specifications) => specifications,
+    };
+
+    let mut local_environment = type_environment.clone();
+    let mut checked = Vec::new();
+    for specification in specifications {
+        let specification = match specification {
+            Specification::Value { name, value_type } => {
+                let parameters = Vec::from_iter(free_type_variables(value_type.clone()));
+                check_type(&local_environment, &parameters, &value_type)?;
+                Specification::Value {
+                    name,
+                    value_type: local_environment.resolve_type(value_type),
+                }
+            }
+            Specification::Type(definitions) => {
+                local_environment = define_types(local_environment, definitions.clone())?;
+                Specification::Type(
+                    definitions
+                        .into_iter()
+                        .filter_map(|definition| {
+                            local_environment
+                                .get_type_definition(&definition.name)
+                                .cloned()
+                        })
+                        .collect(),
+                )
+            }
+            Specification::Exception { name, argument } => {
+                if let Some(argument) = &argument {
+                    check_type(&local_environment, &[], argument)?;
+                }
+                Specification::Exception {
+                    name,
+                    argument: argument.map(|argument| local_environment.resolve_type(argument)),
+                }
+            }
+        };
+        checked.push(specification);
+    }
+
+    Ok(checked)
+}
+
+// S with type t = u で、シグネチャの抽象型 t を u の別名にする。u は S の外の型環境で解決する
+fn constrain(
+    type_environment: &TypeEnvironment,
+    mut specifications: Vec<Specification>,
+    constraint: TypeDefinition,
+) -> Result<Vec<Specification>> {
+    let TypeKind::Alias(t) = constraint.kind else {
+        bail!(TypeInferenceError::InvalidConstraint(constraint.name));
+    };
+    check_type(type_environment, &constraint.parameters, &t)?;
+    let t = type_environment.resolve_type(t);
+
+    let definition = specifications
+        .iter_mut()
+        .flat_map(|specification| match specification {
+            Specification::Type(definitions) => definitions.iter_mut().collect(),
+            _ => vec![],
+        })
+        .find(|definition| definition.name == constraint.name);
+    let Some(definition) = definition else {
+        bail!(TypeInferenceError::InvalidConstraint(constraint.name));
+    };
+    ensure!(
+        definition.kind == TypeKind::Abstract
+            && definition.parameters.len() == constraint.parameters.len(),
+        TypeInferenceError::InvalidConstraint(constraint.name)
+    );
+    let arguments = type_variables(&definition.parameters);
+    definition.kind = TypeKind::Alias(t.instantiate_parameters(&constraint.parameters, &arguments));
+
+    Ok(specifications)
+}
+
+fn type_variables(parameters: &[Symbol]) -> Vec<Type> {
+    parameters
+        .iter()
+        .map(|parameter| Type::Variable {
+            name: parameter.clone(),
+        })
+        .collect()
+}
+
+fn substitute_types(t: Type, types: &TypeMap) -> Type {
+    t.substitute_constructors(&|name, args| {
+        let (parameters, t) = types.get(name)?;
+        Some(t.clone().instantiate_parameters(parameters, &args))
+    })
+}
+
+fn type_specifications(specifications: &[Specification]) -> Vec<&TypeDefinition> {
+    specifications
+        .iter()
+        .flat_map(|specification| match specification {
+            Specification::Type(definitions) => definitions.iter().collect(),
+            _ => vec![],
+        })
+        .collect()
+}
+
+// シグネチャで宣言した型を、実装のどの型で表すか
+fn implementation_types(
+    implementation: &TypeEnvironment,
+    specifications: &[Specification],
+) -> Result<TypeMap> {
+    let mut types = TypeMap::new();
+    for definition in type_specifications(specifications) {
+        let Some(implemented) = implementation.get_type_definition(&definition.name) else {
+            bail!(TypeInferenceError::MissingType(definition.name.clone()));
+        };
+        ensure!(
+            implemented.parameters.len() == definition.parameters.len(),
+            TypeInferenceError::TypeMismatch(definition.name.clone())
+        );
+        let arguments = type_variables(&definition.parameters);
+        let t = match &implemented.kind {
+            TypeKind::Alias(t) => t
+                .clone()
+                .instantiate_parameters(&implemented.parameters, &arguments),
+            _ => Type::Constructor {
+                name: implemented.name.clone(),
+                args: arguments,
+            },
+        };
+        types.insert(definition.name.clone(), (definition.parameters.clone(), t));
+    }
+
+    Ok(types)
+}
+
+// シグネチャの抽象型を、実装の型の別名にする
+fn strengthen(
+    implementation: &TypeEnvironment,
+    mut specifications: Vec<Specification>,
+) -> Result<Vec<Specification>> {
+    let types = implementation_types(implementation, &specifications)?;
+    for specification in &mut specifications {
+        let Specification::Type(definitions) = specification else {
+            continue;
+        };
+        for definition in definitions {
+            if definition.kind == TypeKind::Abstract
+                && let Some((_, t)) = types.get(&definition.name)
+            {
+                definition.kind = TypeKind::Alias(t.clone());
+            }
+        }
+    }
+
+    Ok(specifications)
+}
+
+// モジュールの実装がシグネチャを満たすことを確かめ、シグネチャの名前だけを持つ型環境を返す
+fn match_signature(
+    type_environment: &TypeEnvironment,
+    implementation: &TypeEnvironment,
+    specifications: Vec<Specification>,
+    module_name: &Symbol,
+) -> Result<TypeEnvironment> {
+    let types = implementation_types(implementation, &specifications)?;
+
+    for specification in &specifications {
+        match specification {
+            Specification::Value { name, value_type } => {
+                let Result::Ok(implemented_type) = implementation.get_variable_type(name) else {
+                    bail!(TypeInferenceError::MissingValue(name.clone()));
+                };
+                // シグネチャの型変数は任意の型を表すので、どの型とも一致しない型に置き換えて比べる
+                let expected_type = substitute_types(value_type.clone(), &types);
+                let rigid_names = free_type_variables(expected_type.clone())
+                    .into_iter()
+                    .map(|variable| (variable, unique_symbol()))
+                    .collect::<Vec<_>>();
+                let rigid_type =
+                    rigid_names
+                        .iter()
+                        .fold(expected_type.clone(), |t, (variable, rigid_name)| {
+                            t.apply_substitution_for_type(
+                                variable.clone(),
+                                Type::Constructor {
+                                    name: rigid_name.clone(),
+                                    args: vec![],
+                                },
+                            )
+                        });
+                let unified = type_environment
+                    .clone()
+                    .add_equation(implemented_type.clone(), rigid_type)
+                    .unify_equations();
+                // 一般化されていない型変数は1つの型にしかなれないので、シグネチャの型変数には一致しない
+                let matches = unified.is_ok_and(|unified| {
+                    implementation
+                        .get_weak_variables(name)
+                        .into_iter()
+                        .all(|variable| {
+                            unified
+                                .normalize_type(
+                                    TypeTraverseHistory::new(),
+                                    Type::Variable { name: variable },
+                                )
+                                .is_ok_and(|t| {
+                                    rigid_names
+                                        .iter()
+                                        .all(|(_, rigid_name)| !mentions_type(&t, rigid_name))
+                                })
+                        })
+                });
+                ensure!(
+                    matches,
+                    TypeInferenceError::ValueMismatch(
+                        name.clone(),
+                        implemented_type,
+                        expected_type
+                    )
+                );
+            }
+            Specification::Type(definitions) => {
+                for definition in definitions {
+                    ensure!(
+                        matches_type(implementation, &types, definition),
+                        TypeInferenceError::TypeMismatch(definition.name.clone())
+                    );
+                }
+            }
+            Specification::Exception { name, argument } => {
+                let Some((constructed_type, implemented_argument)) =
+                    implementation.get_constructor(name)
+                else {
+                    bail!(TypeInferenceError::MissingException(name.clone()));
+                };
+                ensure!(
+                    constructed_type == exception_type()
+                        && implemented_argument
+                            == argument.clone().map(|t| substitute_types(t, &types)),
+                    TypeInferenceError::ExceptionMismatch(name.clone())
+                );
+            }
+        }
+    }
+
+    signature_namespace(specifications, module_name, Some(implementation))
+}
+
+// 型の宣言が実装の型と合うか。types はシグネチャの型を実装の型で表したもの
+fn matches_type(
+    implementation: &TypeEnvironment,
+    types: &TypeMap,
+    definition: &TypeDefinition,
+) -> bool {
+    let (Some(implemented), Some((_, implemented_type))) = (
+        implementation.get_type_definition(&definition.name),
+        types.get(&definition.name),
+    ) else {
+        return false;
+    };
+
+    match &definition.kind {
+        TypeKind::Abstract => true,
+        TypeKind::Alias(t) => substitute_types(t.clone(), types) == *implemented_type,
+        _ => {
+            // 型パラメータの名前は実装と違ってもよい
+            let arguments = type_variables(&definition.parameters);
+            let implemented = map_definition_types(implemented.clone(), &|t| {
+                t.instantiate_parameters(&implemented.parameters, &arguments)
+            });
+            let expected =
+                map_definition_types(definition.clone(), &|t| substitute_types(t, types));
+            expected.kind == implemented.kind
+        }
+    }
+}
+
+// シグネチャの名前だけを持つ型環境を作る
+// 定義を省略した型は抽象型 M.t になり、構築子やフィールドは外から見えなくなる
+// 実装がないとき(ファンクタの引数)は、定義を書いた型も M.t という名前の型にする
+fn signature_namespace(
+    specifications: Vec<Specification>,
+    module_name: &Symbol,
+    implementation: Option<&TypeEnvironment>,
+) -> Result<TypeEnvironment> {
+    let mut types = TypeMap::new();
+    let mut namespace = TypeEnvironment::default();
+    for specification in specifications {
+        match specification {
+            Specification::Value { name, value_type } => {
+                let value_type = substitute_types(value_type, &types);
+                let type_scheme = TypeScheme::new_polymorphic_type_scheme(
+                    free_type_variables(value_type.clone()).into_iter(),
+                    value_type,
+                );
+                namespace = namespace.substitute_variable(name, type_scheme)?;
+            }
+            Specification::Type(definitions) => {
+                // and で結ばれた型は互いに参照できるので、先に名前を決めておく
+                for definition in &definitions {
+                    let name = match (implementation, &definition.kind) {
+                        (_, TypeKind::Alias(_)) => continue,
+                        (Some(implementation), TypeKind::Variant(_) | TypeKind::Record(_)) => {
+                            implementation
+                                .get_type_definition(&definition.name)
+                                .map(|implemented| implemented.name.clone())
+                                .ok_or_else(|| {
+                                    anyhow!(TypeInferenceError::MissingType(
+                                        definition.name.clone()
+                                    ))
+                                })?
+                        }
+                        _ => format!("{}.{}", module_name, definition.name),
+                    };
+                    let t = Type::Constructor {
+                        name,
+                        args: type_variables(&definition.parameters),
+                    };
+                    types.insert(definition.name.clone(), (definition.parameters.clone(), t));
+                }
+                for definition in definitions {
+                    let definition = match (implementation, &definition.kind) {
+                        (_, TypeKind::Alias(t)) => {
+                            let t = substitute_types(t.clone(), &types);
+                            types.insert(
+                                definition.name.clone(),
+                                (definition.parameters.clone(), t.clone()),
+                            );
+                            TypeDefinition {
+                                name: format!("{}.{}", module_name, definition.name),
+                                kind: TypeKind::Alias(t),
+                                ..definition
+                            }
+                        }
+                        (Some(implementation), TypeKind::Variant(_) | TypeKind::Record(_)) => {
+                            implementation
+                                .get_type_definition(&definition.name)
+                                .cloned()
+                                .ok_or_else(|| {
+                                    anyhow!(TypeInferenceError::MissingType(
+                                        definition.name.clone()
+                                    ))
+                                })?
+                        }
+                        _ => TypeDefinition {
+                            name: format!("{}.{}", module_name, definition.name),
+                            ..map_definition_types(definition, &|t| substitute_types(t, &types))
+                        },
+                    };
+                    namespace = namespace.define_type(definition);
+                }
+            }
+            Specification::Exception { name, argument } => {
+                namespace =
+                    namespace.define_exception(name, argument.map(|t| substitute_types(t, &types)));
+            }
+        }
+    }
+
+    Ok(namespace)
+}
+
+// 型式 value_type の値として variable を宣言する。型式の中の 'a などについて多相になる
+// 式を持たない値(Rustで実装した関数など)の宣言に使い、名前を解決した型を返す
+pub fn declare_value(
+    type_environment: TypeEnvironment,
+    variable: Symbol,
+    value_type: Type,
+) -> InferenceResult {
+    let parameters = Vec::from_iter(free_type_variables(value_type.clone()));
+    check_type(&type_environment, &parameters, &value_type)?;
+    let value_type = type_environment.resolve_type(value_type);
+    let type_scheme = TypeScheme::new_polymorphic_type_scheme(
+        free_type_variables(value_type.clone()).into_iter(),
+        value_type.clone(),
+    );
+    let type_environment = type_environment.substitute_variable(variable, type_scheme)?;
+
+    Ok((type_environment, value_type))
+}
+
+fn declare_variable(
+    type_environment: TypeEnvironment,
+    variable: Symbol,
+    t: Type,
+    generalizable: bool,
+) -> InferenceResult {
+    let type_scheme = generalize(&type_environment, t.clone(), generalizable)?;
+    let type_environment = type_environment.substitute_variable(variable, type_scheme)?;
+
+    Ok((type_environment, t))
+}
+
+// 一般化できない(値でない式の)型は単相のままにする
+fn generalize(
+    type_environment: &TypeEnvironment,
+    t: Type,
+    generalizable: bool,
+) -> Result<TypeScheme> {
+    if !generalizable {
+        return Ok(TypeScheme::new_monomorphic_type_scheme(t));
+    }
+
+    let free_variables =
+        type_environment.get_unbound_variables(free_type_variables(t.clone()).into_iter())?;
+    Ok(TypeScheme::new_polymorphic_type_scheme(
+        free_variables.into_iter(),
+        t,
+    ))
+}
 
-    Ok((unified_environment, normalized_type))
+// 値制限: 評価しても参照を作らない式(値)だけを一般化してよい
+// let r = ref [] の型を一般化すると、r に異なる型のリストを代入できてしまう
+fn is_nonexpansive(type_environment: &TypeEnvironment, expression: &Expression) -> bool {
+    let nonexpansive = |expression| is_nonexpansive(type_environment, expression);
+    match expression {
+        Expression::Integer(_)
+        | Expression::Bool(_)
+        | Expression::String(_)
+        | Expression::Char(_)
+        | Expression::Unit
+        | Expression::Variable(_)
+        | Expression::Fun { .. }
+        | Expression::Nil => true,
+        Expression::Cons { car, cdr } => nonexpansive(car) && nonexpansive(cdr),
+        Expression::Tuple(expressions) => expressions.iter().all(nonexpansive),
+        Expression::Constructor { argument, .. } => argument.iter().all(|e| nonexpansive(e)),
+        // 書き換え可能なフィールドを持つレコードは参照と同じく扱う
+        Expression::Record(fields) => {
+            fields
+                .iter()
+                .all(|(_, expression)| nonexpansive(expression))
+                && fields.first().is_none_or(|(label, _)| {
+                    type_environment
+                        .get_record(label)
+                        .is_some_and(|(_, fields)| fields.iter().all(|field| !field.mutable))
+                })
+        }
+        Expression::Field { record, .. } => nonexpansive(record),
+        Expression::Annotation { expression, .. } => nonexpansive(expression),
+        Expression::Open { body, .. } => nonexpansive(body),
+        Expression::Let { bound, body, .. } => nonexpansive(bound) && nonexpansive(body),
+        Expression::LetRec { body, .. } | Expression::TypeDefinition { body, .. } => {
+            nonexpansive(body)
+        }
+        Expression::If {
+            predicate,
+            consequent,
+            alternative,
+        } => nonexpansive(predicate) && nonexpansive(consequent) && nonexpansive(alternative),
+        _ => false,
+    }
 }
 
 fn infer_expression(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
     match expression {
         Expression::Integer(_) => infer_integer(type_environment, expression),
         Expression::Bool(_) => infer_bool(type_environment, expression),
+        Expression::String(_) => infer_string(type_environment, expression),
+        Expression::Char(_) => infer_char(type_environment, expression),
+        Expression::Unit => Ok((type_environment, Type::Base(BaseType::Unit))),
         Expression::Variable(_) => infer_variable(type_environment, expression),
         Expression::Plus {
             expression1,
@@ -50,10 +904,55 @@ fn infer_expression(type_environment: TypeEnvironment, expression: Expression) -
             expression1,
             expression2,
         } => infer_binary_operation(type_environment, *expression1, *expression2),
+        Expression::Divide {
+            expression1,
+            expression2,
+        } => infer_binary_operation(type_environment, *expression1, *expression2),
+        Expression::Modulo {
+            expression1,
+            expression2,
+        } => infer_binary_operation(type_environment, *expression1, *expression2),
+        Expression::Negate(expression) => infer_negation(type_environment, *expression),
         Expression::LessThan {
             expression1,
             expression2,
         } => infer_binary_predicate(type_environment, *expression1, *expression2),
+        Expression::Equal {
+            expression1,
+            expression2,
+        } => infer_binary_predicate(type_environment, *expression1, *expression2),
+        Expression::NotEqual {
+            expression1,
+            expression2,
+        } => infer_binary_predicate(type_environment, *expression1, *expression2),
+        Expression::LessEqual {
+            expression1,
+            expression2,
+        } => infer_binary_predicate(type_environment, *expression1, *expression2),
+        Expression::GreaterThan {
+            expression1,
+            expression2,
+        } => infer_binary_predicate(type_environment, *expression1, *expression2),
+        Expression::GreaterEqual {
+            expression1,
+            expression2,
+        } => infer_binary_predicate(type_environment, *expression1, *expression2),
+        Expression::And {
+            expression1,
+            expression2,
+        } => infer_logical_operation(type_environment, *expression1, *expression2),
+        Expression::Or {
+            expression1,
+            expression2,
+        } => infer_logical_operation(type_environment, *expression1, *expression2),
+        Expression::Concat {
+            expression1,
+            expression2,
+        } => infer_concat(type_environment, *expression1, *expression2),
+        Expression::Append {
+            expression1,
+            expression2,
+        } => infer_append(type_environment, *expression1, *expression2),
         Expression::If {
             predicate,
             consequent,
@@ -63,26 +962,55 @@ fn infer_expression(type_environment: TypeEnvironment, expression: Expression) -
             variable,
             bound,
             body,
-        } => infer_let(type_environment, variable, *bound, *body),
+        } => {
+            check_operator_definition(&type_environment, &variable)?;
+            infer_let(type_environment, variable, *bound, *body)
+        }
+        Expression::Sequence {
+            expression1,
+            expression2,
+        } => infer_sequence(type_environment, *expression1, *expression2),
         Expression::Fun { parameter, body } => infer_fun(type_environment, parameter, *body),
         Expression::App { function, argument } => infer_app(type_environment, *function, *argument),
-        Expression::LetRec {
-            variable,
-            bound_function,
-            body,
-        } => infer_let_rec(type_environment, variable, *bound_function, *body),
+        Expression::LetRec { bindings, body } => {
+            for (variable, _) in &bindings {
+                check_operator_definition(&type_environment, variable)?;
+            }
+            infer_let_rec(type_environment, bindings, *body)
+        }
         Expression::Nil => infer_nil(type_environment),
         Expression::Cons { car, cdr } => infer_cons(type_environment, *car, *cdr),
-        Expression::Match {
-            scrutinee,
-            nil_case,
-            cons_pattern: (car, cdr, cons_case),
-        } => infer_match(
-            type_environment,
-            *scrutinee,
-            *nil_case,
-            (car, cdr, *cons_case),
-        ),
+        Expression::Match { scrutinee, arms } => infer_match(type_environment, *scrutinee, arms),
+        Expression::Tuple(expressions) => infer_tuple(type_environment, expressions),
+        Expression::Constructor { name, argument } => {
+            infer_constructor(type_environment, name, argument.map(|argument| *argument))
+        }
+        Expression::TypeDefinition { definitions, body } => {
+            infer_type_definition(type_environment, definitions, *body)
+        }
+        Expression::Record(fields) => infer_record(type_environment, fields),
+        Expression::Field { record, label } => infer_field(type_environment, *record, label),
+        Expression::RecordUpdate { record, fields } => {
+            infer_record_update(type_environment, *record, fields)
+        }
+        Expression::FieldAssignment {
+            record,
+            label,
+            value,
+        } => infer_field_assignment(type_environment, *record, label, *value),
+        Expression::Ref(expression) => infer_ref(type_environment, *expression),
+        Expression::Deref(expression) => infer_deref(type_environment, *expression),
+        Expression::Assign { reference, value } => {
+            infer_assign(type_environment, *reference, *value)
+        }
+        Expression::Raise(expression) => infer_raise(type_environment, *expression),
+        Expression::Try { body, arms } => infer_try(type_environment, *body, arms),
+        Expression::Assert(expression) => infer_assert(type_environment, *expression),
+        Expression::Annotation {
+            expression,
+            annotation,
+        } => infer_annotation(type_environment, *expression, annotation),
+        Expression::Open { module, body } => infer_open(type_environment, module, *body),
     }
 }
 
@@ -100,10 +1028,26 @@ fn infer_bool(type_environment: TypeEnvironment, expression: Expression) -> Infe
     }
 }
 
+fn infer_string(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    match expression {
+        Expression::String(_) => Ok((type_environment, Type::Base(BaseType::String))),
+        _ => bail!(TypeInferenceError::Impossible(expression)),
+    }
+}
+
+fn infer_char(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    match expression {
+        Expression::Char(_) => Ok((type_environment, Type::Base(BaseType::Char))),
+        _ => bail!(TypeInferenceError::Impossible(expression)),
+    }
+}
+
 fn infer_variable(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
     match &expression {
         Expression::Variable(name) => {
-            let variable_type = type_environment.get_variable_type(name)?;
+            let variable_type = type_environment
+                .get_variable_type(name)
+                .map_err(|_| TypeInferenceError::UndefinedVariable(expression.clone()))?;
 
             Ok((type_environment, variable_type))
         }
@@ -111,6 +1055,7 @@ fn infer_variable(type_environment: TypeEnvironment, expression: Expression) ->
     }
 }
 
+// 算術演算は int -> int -> int
 fn infer_binary_operation(
     type_environment: TypeEnvironment,
     expression1: Expression,
@@ -119,12 +1064,22 @@ fn infer_binary_operation(
     let (type_environment, expression1_type) = infer_expression(type_environment, expression1)?;
     let (type_environment, expression2_type) = infer_expression(type_environment, expression2)?;
 
-    let type_environment =
-        type_environment.add_equation(expression1_type.clone(), expression2_type.clone());
+    let type_environment = type_environment
+        .add_equation(expression1_type, Type::Base(BaseType::Integer))
+        .add_equation(expression2_type, Type::Base(BaseType::Integer));
 
-    Ok((type_environment, expression1_type))
+    Ok((type_environment, Type::Base(BaseType::Integer)))
 }
 
+fn infer_negation(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    let (type_environment, t) = infer_expression(type_environment, expression)?;
+    let type_environment = type_environment.add_equation(t, Type::Base(BaseType::Integer));
+
+    Ok((type_environment, Type::Base(BaseType::Integer)))
+}
+
+// 比較は 'a -> 'a -> bool
+
 fn infer_binary_predicate(
     type_environment: TypeEnvironment,
     expression1: Expression,
@@ -139,6 +1094,59 @@ fn infer_binary_predicate(
     Ok((type_environment, Type::Base(BaseType::Bool)))
 }
 
+// && と || は bool -> bool -> bool
+fn infer_logical_operation(
+    type_environment: TypeEnvironment,
+    expression1: Expression,
+    expression2: Expression,
+) -> InferenceResult {
+    let (type_environment, expression1_type) = infer_expression(type_environment, expression1)?;
+    let (type_environment, expression2_type) = infer_expression(type_environment, expression2)?;
+
+    let type_environment = type_environment
+        .add_equation(expression1_type, Type::Base(BaseType::Bool))
+        .add_equation(expression2_type, Type::Base(BaseType::Bool));
+
+    Ok((type_environment, Type::Base(BaseType::Bool)))
+}
+
+fn infer_concat(
+    type_environment: TypeEnvironment,
+    expression1: Expression,
+    expression2: Expression,
+) -> InferenceResult {
+    let (type_environment, expression1_type) = infer_expression(type_environment, expression1)?;
+    let (type_environment, expression2_type) = infer_expression(type_environment, expression2)?;
+
+    let type_environment = type_environment
+        .add_equation(expression1_type, Type::Base(BaseType::String))
+        .add_equation(expression2_type, Type::Base(BaseType::String));
+
+    Ok((type_environment, Type::Base(BaseType::String)))
+}
+
+// @ は 'a list -> 'a list -> 'a list
+fn infer_append(
+    type_environment: TypeEnvironment,
+    expression1: Expression,
+    expression2: Expression,
+) -> InferenceResult {
+    let (type_environment, expression1_type) = infer_expression(type_environment, expression1)?;
+    let (type_environment, expression2_type) = infer_expression(type_environment, expression2)?;
+
+    let list_type = Type::List(
+        Type::Variable {
+            name: unique_symbol(),
+        }
+        .into(),
+    );
+    let type_environment = type_environment
+        .add_equation(expression1_type, list_type.clone())
+        .add_equation(expression2_type, list_type.clone());
+
+    Ok((type_environment, list_type))
+}
+
 fn infer_if(
     type_environment: TypeEnvironment,
     predicate: Expression,
@@ -160,23 +1168,56 @@ fn infer_if(
     Ok((type_environment, consequent_type))
 }
 
+// 組み込みの演算子を使う式は束縛を参照しないので、組み込みの定義で束縛した後は定義し直せない
+fn check_operator_definition(type_environment: &TypeEnvironment, variable: &Symbol) -> Result<()> {
+    ensure!(
+        !(is_builtin_operator(variable) && type_environment.get_variable_type(variable).is_ok()),
+        TypeInferenceError::BuiltinOperatorRedefinition(variable.clone()).to_string()
+    );
+    Ok(())
+}
+
 fn infer_let(
     type_environment: TypeEnvironment,
     variable: Symbol,
     bound: Expression,
     body: Expression,
 ) -> InferenceResult {
+    let scope = type_environment.clone();
+    let generalizable = is_nonexpansive(&type_environment, &bound);
     let (type_environment, bound_type) = infer_expression(type_environment, bound)?;
 
-    let free_variables =
-        type_environment.get_unbound_variables(free_type_variables(bound_type.clone()).into_iter());
-
-    let type_environment = type_environment.substitute_variable(
-        variable.clone(),
-        TypeScheme::new_polymorphic_type_scheme(free_variables.into_iter(), bound_type),
-    )?;
+    let type_environment = type_environment.unify_equations()?;
+    let bound_type = type_environment.normalize_type(TypeTraverseHistory::new(), bound_type)?;
+    let type_scheme = generalize(&type_environment, bound_type, generalizable)?;
+    let type_environment = type_environment.substitute_variable(variable.clone(), type_scheme)?;
 
-    infer_expression(type_environment, body)
+    let (type_environment, body_type) = infer_expression(type_environment, body)?;
+    Ok((type_environment.restore_scope(&scope), body_type))
+}
+
+fn infer_open(
+    type_environment: TypeEnvironment,
+    module: Symbol,
+    body: Expression,
+) -> InferenceResult {
+    let scope = type_environment.clone();
+    let namespace = get_module(&type_environment, &module)?.clone();
+    let type_environment = type_environment.open_module(&namespace);
+
+    let (type_environment, body_type) = infer_expression(type_environment, body)?;
+    Ok((type_environment.restore_scope(&scope), body_type))
+}
+
+fn infer_sequence(
+    type_environment: TypeEnvironment,
+    expression1: Expression,
+    expression2: Expression,
+) -> InferenceResult {
+    let (type_environment, expression1_type) = infer_expression(type_environment, expression1)?;
+    let type_environment = type_environment.record_statement(expression1_type);
+
+    infer_expression(type_environment, expression2)
 }
 
 fn infer_fun(
@@ -190,12 +1231,13 @@ fn infer_fun(
         name: unique_parameter.clone(),
     };
 
-    let type_environment = type_environment.substitute_variable(
+    let body_environment = type_environment.clone().substitute_variable(
         parameter.clone(),
         TypeScheme::new_monomorphic_type_scheme(parameter_type.clone()),
     )?;
 
-    let (type_environment, body_type) = infer_expression(type_environment, body)?;
+    let (body_environment, body_type) = infer_expression(body_environment, body)?;
+    let type_environment = body_environment.restore_scope(&type_environment);
     let substitued_body_type = body_type.apply_substitution(parameter, unique_parameter);
 
     Ok((
@@ -213,68 +1255,113 @@ fn infer_app(
     argument: Expression,
 ) -> InferenceResult {
     let (type_environment, function_type) = infer_expression(type_environment, function.clone())?;
-    let Type::Function { domain, range } = function_type else {
-        bail!(TypeInferenceError::InvalidType(function));
+    let (type_environment, domain, range) = match function_type {
+        Type::Function { domain, range } => (type_environment, *domain, *range),
+        variable @ Type::Variable { .. } => {
+            let domain = Type::Variable {
+                name: unique_symbol(),
+            };
+            let range = Type::Variable {
+                name: unique_symbol(),
+            };
+            let type_environment = type_environment.add_equation(
+                variable,
+                Type::Function {
+                    domain: domain.clone().into(),
+                    range: range.clone().into(),
+                },
+            );
+            (type_environment, domain, range)
+        }
+        _ => bail!(TypeInferenceError::InvalidType(function)),
     };
 
     let (type_environment, argument_type) = infer_expression(type_environment, argument.clone())?;
-    let type_environment = type_environment.add_equation(*domain, argument_type);
+    let type_environment = type_environment.add_equation(domain, argument_type);
 
-    Ok((type_environment, *range))
+    Ok((type_environment, range))
 }
 
+// let rec f = ... and g = ... の関数はすべて、どの関数の本体からも参照できる
+// 組の中では単相的に扱い、すべての本体を推論してからまとめて一般化する
 fn infer_let_rec(
     type_environment: TypeEnvironment,
-    variable: Symbol,
-    bound_function: Expression,
+    bindings: Vec<(Symbol, Expression)>,
     body: Expression,
 ) -> InferenceResult {
-    // 1. 仮の関数型を作成
-    let recursive_function_argument_type = Type::Variable {
-        name: unique_symbol(),
-    };
-    let recursive_function_return_type = Type::Variable {
-        name: unique_symbol(),
-    };
-    let recursice_function_type = Type::Function {
-        domain: recursive_function_argument_type.clone().into(),
-        range: recursive_function_return_type.clone().into(),
-    };
-
-    // 2. 単相的な型として関数を型環境に追加（関数本体の型推論用）
-    let temporal_environment = type_environment.substitute_variable(
-        variable.clone(),
-        TypeScheme::new_monomorphic_type_scheme(recursice_function_type.clone()),
+    // 1. 関数ごとに仮の関数型を作成
+    let recursive_function_types = bindings
+        .iter()
+        .map(|_| Type::Function {
+            domain: Type::Variable {
+                name: unique_symbol(),
+            }
+            .into(),
+            range: Type::Variable {
+                name: unique_symbol(),
+            }
+            .into(),
+        })
+        .collect::<Vec<_>>();
+
+    // 2. 単相的な型としてすべての関数を型環境に追加（関数本体の型推論用）
+    let temporal_environment = bindings.iter().zip(&recursive_function_types).try_fold(
+        type_environment.clone(),
+        |temporal_environment, ((variable, _), recursive_function_type)| {
+            temporal_environment.substitute_variable(
+                variable.clone(),
+                TypeScheme::new_monomorphic_type_scheme(recursive_function_type.clone()),
+            )
+        },
     )?;
 
-    // 3. 関数本体の型推論
-    let (bound_function_environment, bound_function_type) =
-        infer_expression(temporal_environment, bound_function.clone())?;
-    let Type::Function { domain, range } = bound_function_type else {
-        bail!(TypeInferenceError::InvalidType(bound_function));
-    };
-
-    // 4. 関数型の制約を追加
-    let type_environment = bound_function_environment
-        .add_equation(recursive_function_argument_type.clone(), *domain)
-        .add_equation(recursive_function_return_type.clone(), *range);
-
-    // 5. 単一化して最終的な関数型を得る
-    let unified_environment = type_environment.clone().unify_equations()?;
-    let actual_function_type = unified_environment
-        .normalize_type(TypeTraverseHistory::new(), recursice_function_type.clone())?;
+    // 3. 関数本体を推論し、仮の関数型との制約を追加
+    let mut bound_function_environment = temporal_environment;
+    for ((_, bound_function), recursive_function_type) in
+        bindings.iter().zip(&recursive_function_types)
+    {
+        let (inferred_environment, bound_function_type) =
+            infer_expression(bound_function_environment, bound_function.clone())?;
+        ensure!(
+            matches!(bound_function_type, Type::Function { .. }),
+            TypeInferenceError::InvalidType(bound_function.clone())
+        );
+        bound_function_environment =
+            inferred_environment.add_equation(recursive_function_type.clone(), bound_function_type);
+    }
 
-    // 6. 自由型変数を抽出し、多相型化
-    let free_variables = type_environment
-        .get_unbound_variables(free_type_variables(actual_function_type.clone()).into_iter());
+    // 4. 単一化して最終的な関数型を得る
+    let unified_environment = bound_function_environment.unify_equations()?;
+    let actual_function_types = recursive_function_types
+        .into_iter()
+        .map(|t| unified_environment.normalize_type(TypeTraverseHistory::new(), t))
+        .collect::<Result<Vec<_>>>()?;
+
+    // 5. 組全体の自由型変数を抽出し、まとめて多相型化（関数自身の単相的な束縛は外側のスコープに含めない）
+    let type_environment = unified_environment.restore_scope(&type_environment);
+    let free_variables = type_environment.get_unbound_variables(
+        actual_function_types
+            .iter()
+            .flat_map(|t| free_type_variables(t.clone())),
+    )?;
 
-    // 7. 多相型として関数を型環境に追加し、本体の型推論
-    let type_environment = type_environment.substitute_variable(
-        variable.clone(),
-        TypeScheme::new_polymorphic_type_scheme(free_variables.into_iter(), actual_function_type),
+    // 6. 多相型として関数を型環境に追加し、本体の型推論
+    let scope = type_environment.clone();
+    let type_environment = bindings.into_iter().zip(actual_function_types).try_fold(
+        type_environment,
+        |type_environment, ((variable, _), actual_function_type)| {
+            type_environment.substitute_variable(
+                variable,
+                TypeScheme::new_polymorphic_type_scheme(
+                    free_variables.iter().cloned(),
+                    actual_function_type,
+                ),
+            )
+        },
     )?;
 
-    infer_expression(type_environment, body)
+    let (type_environment, body_type) = infer_expression(type_environment, body)?;
+    Ok((type_environment.restore_scope(&scope), body_type))
 }
 
 fn infer_nil(type_environment: TypeEnvironment) -> InferenceResult {
@@ -297,56 +1384,727 @@ fn infer_cons(
     let (type_environment, car_type) = infer_expression(type_environment, car)?;
 
     let (type_environment, cdr_type) = infer_expression(type_environment, cdr.clone())?;
-    let Type::List(element_type) = cdr_type.clone() else {
-        bail!(TypeInferenceError::InvalidType(cdr));
+    let type_environment = match cdr_type.clone() {
+        Type::List(element_type) => type_environment.add_equation(car_type, *element_type),
+        variable @ Type::Variable { .. } => {
+            type_environment.add_equation(variable, Type::List(car_type.into()))
+        }
+        _ => bail!(TypeInferenceError::InvalidType(cdr)),
     };
 
-    let type_environment = type_environment.add_equation(car_type, *element_type);
-
     Ok((type_environment, cdr_type))
 }
 
 fn infer_match(
     type_environment: TypeEnvironment,
     scrutinee: Expression,
-    nil_case: Expression,
-    (car, cdr, cons_case): (Symbol, Symbol, Expression),
+    arms: Vec<(Pattern, Expression)>,
 ) -> InferenceResult {
-    let (type_environment, scrutinee_type) = infer_expression(type_environment, scrutinee.clone())?;
-    let (type_environment, element_type) = match scrutinee_type {
-        Type::List(element_type) => (type_environment, *element_type),
-        variable @ Type::Variable { .. } => {
-            let element_type = Type::Variable {
+    let (type_environment, scrutinee_type) = infer_expression(type_environment, scrutinee)?;
+    let patterns = arms.iter().map(|(pattern, _)| pattern.clone()).collect();
+    let type_environment = type_environment.record_match(scrutinee_type.clone(), patterns);
+
+    infer_arms(type_environment, scrutinee_type, arms)
+}
+
+// 各腕のパターンをscrutinee_typeの値に照合し、腕の本体の型をそろえる
+fn infer_arms(
+    type_environment: TypeEnvironment,
+    scrutinee_type: Type,
+    arms: Vec<(Pattern, Expression)>,
+) -> InferenceResult {
+    let result_type = Type::Variable {
+        name: unique_symbol(),
+    };
+    let type_environment =
+        arms.into_iter()
+            .try_fold(type_environment, |type_environment, (pattern, body)| {
+                let (arm_environment, pattern_type, bindings) =
+                    infer_pattern(type_environment.clone(), pattern)?;
+                let arm_environment = bindings.into_iter().try_fold(
+                    arm_environment.add_equation(scrutinee_type.clone(), pattern_type),
+                    |arm_environment, (variable, variable_type)| {
+                        arm_environment.substitute_variable(
+                            variable,
+                            TypeScheme::new_monomorphic_type_scheme(variable_type),
+                        )
+                    },
+                )?;
+
+                let (arm_environment, body_type) = infer_expression(arm_environment, body)?;
+                Ok(arm_environment
+                    .add_equation(result_type.clone(), body_type)
+                    .restore_scope(&type_environment))
+            })?;
+
+    Ok((type_environment, result_type))
+}
+
+fn infer_pattern(type_environment: TypeEnvironment, pattern: Pattern) -> PatternInferenceResult {
+    match pattern {
+        Pattern::Wildcard => Ok((
+            type_environment,
+            Type::Variable {
+                name: unique_symbol(),
+            },
+            vec![],
+        )),
+        Pattern::Variable(variable) => {
+            let variable_type = Type::Variable {
                 name: unique_symbol(),
             };
+            Ok((
+                type_environment,
+                variable_type.clone(),
+                vec![(variable, variable_type)],
+            ))
+        }
+        Pattern::Integer(_) => Ok((type_environment, Type::Base(BaseType::Integer), vec![])),
+        Pattern::String(_) => Ok((type_environment, Type::Base(BaseType::String), vec![])),
+        Pattern::Char(_) => Ok((type_environment, Type::Base(BaseType::Char), vec![])),
+        Pattern::Unit => Ok((type_environment, Type::Base(BaseType::Unit), vec![])),
+        Pattern::Bool(_) => Ok((type_environment, Type::Base(BaseType::Bool), vec![])),
+        Pattern::Nil => {
+            let (type_environment, nil_type) = infer_nil(type_environment)?;
+            Ok((type_environment, nil_type, vec![]))
+        }
+        Pattern::Cons { car, cdr } => {
+            let (type_environment, car_type, car_bindings) = infer_pattern(type_environment, *car)?;
+            let (type_environment, cdr_type, cdr_bindings) = infer_pattern(type_environment, *cdr)?;
             let type_environment =
-                type_environment.add_equation(variable, Type::List(element_type.clone().into()));
-            (type_environment, element_type)
+                type_environment.add_equation(cdr_type.clone(), Type::List(car_type.into()));
+            Ok((
+                type_environment,
+                cdr_type,
+                [car_bindings, cdr_bindings].concat(),
+            ))
+        }
+        Pattern::Tuple(patterns) => {
+            let (type_environment, types, bindings) = patterns.into_iter().try_fold(
+                (type_environment, Vec::new(), Vec::new()),
+                |(type_environment, mut types, mut bindings), pattern| {
+                    let (type_environment, t, pattern_bindings) =
+                        infer_pattern(type_environment, pattern)?;
+                    types.push(t);
+                    bindings.extend(pattern_bindings);
+                    Ok((type_environment, types, bindings))
+                },
+            )?;
+            Ok((type_environment, Type::Tuple(types), bindings))
+        }
+        Pattern::Constructor { name, argument } => {
+            let (constructor_type, argument_type) =
+                get_constructor(&type_environment, &name, argument.is_some())?;
+            let Some(argument) = argument else {
+                return Ok((type_environment, constructor_type, vec![]));
+            };
+
+            let (type_environment, t, bindings) = infer_pattern(type_environment, *argument)?;
+            let type_environment = type_environment.add_equation(argument_type.unwrap(), t);
+            Ok((type_environment, constructor_type, bindings))
         }
-        _ => bail!(TypeInferenceError::InvalidType(scrutinee.clone())),
+        Pattern::Record(fields) => {
+            let labels = fields.iter().map(|(label, _)| label).collect::<Vec<_>>();
+            let (record_type, declared_fields) = get_record(&type_environment, &labels)?;
+
+            let mut type_environment = type_environment;
+            let mut bindings = Vec::new();
+            for (label, pattern) in fields {
+                let (new_environment, t, new_bindings) = infer_pattern(type_environment, pattern)?;
+                type_environment =
+                    new_environment.add_equation(field_type(&declared_fields, &label)?, t);
+                bindings.extend(new_bindings);
+            }
+
+            Ok((type_environment, record_type, bindings))
+        }
+    }
+}
+
+fn infer_tuple(type_environment: TypeEnvironment, expressions: Vec<Expression>) -> InferenceResult {
+    let (type_environment, types) = expressions.into_iter().try_fold(
+        (type_environment, Vec::new()),
+        |(type_environment, mut types), expression| {
+            let (type_environment, t) = infer_expression(type_environment, expression)?;
+            types.push(t);
+            Ok((type_environment, types))
+        },
+    )?;
+
+    Ok((type_environment, Type::Tuple(types)))
+}
+
+fn infer_constructor(
+    type_environment: TypeEnvironment,
+    name: Symbol,
+    argument: Option<Expression>,
+) -> InferenceResult {
+    let (constructor_type, argument_type) =
+        get_constructor(&type_environment, &name, argument.is_some())?;
+    let Some(argument) = argument else {
+        return Ok((type_environment, constructor_type));
     };
 
-    let (type_environment, nil_case_type) = infer_expression(type_environment, nil_case)?;
+    let (type_environment, t) = infer_expression(type_environment, argument)?;
+    let type_environment = type_environment.add_equation(argument_type.unwrap(), t);
+
+    Ok((type_environment, constructor_type))
+}
+
+fn get_constructor(
+    type_environment: &TypeEnvironment,
+    name: &Symbol,
+    applied: bool,
+) -> Result<(Type, Option<Type>)> {
+    let Some((constructor_type, argument_type)) = type_environment.get_constructor(name) else {
+        bail!(TypeInferenceError::UnboundConstructor(name.clone()));
+    };
+    ensure!(
+        argument_type.is_some() == applied,
+        TypeInferenceError::ConstructorArity(
+            name.clone(),
+            argument_type.is_some().into(),
+            applied.into()
+        )
+    );
+
+    Ok((constructor_type, argument_type))
+}
 
+fn infer_type_definition(
+    type_environment: TypeEnvironment,
+    definitions: Vec<TypeDefinition>,
+    body: Expression,
+) -> InferenceResult {
+    let scope = type_environment.clone();
+    let type_names = definitions
+        .iter()
+        .map(|definition| definition.name.clone())
+        .collect::<Vec<_>>();
+    let type_environment = shadow_types(type_environment, &definitions);
+    let type_environment = define_types(type_environment, definitions)?;
+    let (mut type_environment, mut body_type) = infer_expression(type_environment, body)?;
+
+    // 本体を抜けたら局所的な型は見えなくなるので、外の同じ名前の型と区別できる名前に付け替える
+    let mut local_types = HashMap::new();
+    for type_name in type_names {
+        let local_name = unique_type_name(&type_name);
+        type_environment = type_environment.rename_type(&type_name, local_name.clone());
+        body_type =
+            body_type.rename_constructors(&|name| (*name == type_name).then(|| local_name.clone()));
+        local_types.insert(local_name, type_name);
+    }
     let type_environment = type_environment
-        .substitute_variable(
-            car.clone(),
-            TypeScheme::new_monomorphic_type_scheme(element_type.clone()),
-        )?
-        .substitute_variable(
-            cdr.clone(),
-            TypeScheme::new_monomorphic_type_scheme(Type::List(element_type.into())),
-        )?;
-    let (type_environment, cons_case_type) = infer_expression(type_environment, cons_case)?;
+        .reveal_types(&scope)
+        .restore_types(&scope)
+        .unify_equations()?;
+
+    // 本体の型に局所的な型が現れるなら、その型は宣言の外に出られない
+    let normalized_type =
+        type_environment.normalize_type(TypeTraverseHistory::new(), body_type.clone())?;
+    if let Some(type_name) = local_types.iter().find_map(|(local_name, type_name)| {
+        mentions_type(&normalized_type, local_name).then_some(type_name)
+    }) {
+        bail!(TypeInferenceError::TypeEscape(type_name.clone()));
+    }
+
+    Ok((type_environment, body_type))
+}
+
+// 型の中に name という名前の型構築子が現れるか
+fn mentions_type(t: &Type, name: &str) -> bool {
+    match t {
+        Type::Base(_) | Type::Variable { .. } => false,
+        Type::List(t) | Type::Ref(t) => mentions_type(t, name),
+        Type::Tuple(types) => types.iter().any(|t| mentions_type(t, name)),
+        Type::Constructor {
+            name: constructor,
+            args,
+        } => constructor == name || args.iter().any(|t| mentions_type(t, name)),
+        Type::Function { domain, range } => {
+            mentions_type(domain, name) || mentions_type(range, name)
+        }
+    }
+}
+
+// 宣言し直す型と同じ名前の型は、それまでの値の型から別の型として区別できるようにしておく
+fn shadow_types(
+    type_environment: TypeEnvironment,
+    definitions: &[TypeDefinition],
+) -> TypeEnvironment {
+    definitions
+        .iter()
+        .fold(type_environment, |type_environment, definition| {
+            type_environment.shadow_type(&definition.name)
+        })
+}
+
+fn define_types(
+    type_environment: TypeEnvironment,
+    definitions: Vec<TypeDefinition>,
+) -> Result<TypeEnvironment> {
+    let mut type_names = HashSet::new();
+    let mut constructor_names = HashSet::new();
+    let mut labels = HashSet::new();
+    for definition in &definitions {
+        ensure!(
+            type_names.insert(&definition.name),
+            TypeInferenceError::DuplicateTypeName(definition.name.clone())
+        );
+        match &definition.kind {
+            TypeKind::Variant(constructors) => {
+                for (constructor, _) in constructors {
+                    ensure!(
+                        constructor_names.insert(constructor),
+                        TypeInferenceError::DuplicateConstructor(constructor.clone())
+                    );
+                }
+            }
+            TypeKind::Record(fields) => {
+                for field in fields {
+                    ensure!(
+                        labels.insert(&field.label),
+                        TypeInferenceError::DuplicateLabel(field.label.clone())
+                    );
+                }
+            }
+            TypeKind::Abstract | TypeKind::Alias(_) => {}
+        }
+    }
+
+    // and で結ばれた型は互いに参照できるので、すべて定義してから検査する
+    let type_environment = definitions
+        .iter()
+        .cloned()
+        .fold(type_environment, TypeEnvironment::define_type);
+    for definition in &definitions {
+        let component_types = match &definition.kind {
+            TypeKind::Variant(constructors) => constructors
+                .iter()
+                .filter_map(|(_, argument_type)| argument_type.as_ref())
+                .collect::<Vec<_>>(),
+            TypeKind::Record(fields) => fields.iter().map(|field| &field.field_type).collect(),
+            TypeKind::Abstract => vec![],
+            TypeKind::Alias(t) => vec![t],
+        };
+        for t in component_types {
+            check_type(&type_environment, &definition.parameters, t)?;
+        }
+    }
+
+    // 構成要素の型に書かれた名前を正式な名前にし、別名を展開してから定義し直す
+    // 別名の右辺は、同じ組の中で参照している別名を先に解決しておく
+    let mut type_environment = type_environment;
+    for definition in sort_abbreviations(&definitions)? {
+        let resolved = map_definition_types(definition, &|t| type_environment.resolve_type(t));
+        type_environment = type_environment.define_type(resolved);
+    }
+    let resolved = definitions
+        .into_iter()
+        .filter(|definition| !matches!(definition.kind, TypeKind::Alias(_)))
+        .map(|definition| map_definition_types(definition, &|t| type_environment.resolve_type(t)))
+        .collect::<Vec<_>>();
+
+    Ok(resolved
+        .into_iter()
+        .fold(type_environment, TypeEnvironment::define_type))
+}
+
+// 別名の定義を、右辺で参照している同じ組の別名が先に来るように並べる
+// type t = t list のように別名が自分自身に展開されるならエラー
+fn sort_abbreviations(definitions: &[TypeDefinition]) -> Result<Vec<TypeDefinition>> {
+    let abbreviations = definitions
+        .iter()
+        .filter_map(|definition| match &definition.kind {
+            TypeKind::Alias(t) => Some((&definition.name, t)),
+            _ => None,
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut sorted = Vec::new();
+    for definition in definitions {
+        visit_abbreviation(&definition.name, &abbreviations, &mut vec![], &mut sorted)?;
+    }
+
+    Ok(sorted
+        .into_iter()
+        .filter_map(|name| {
+            definitions
+                .iter()
+                .find(|definition| definition.name == *name)
+                .cloned()
+        })
+        .collect())
+}
+
+fn visit_abbreviation<'a>(
+    name: &'a Symbol,
+    abbreviations: &HashMap<&'a Symbol, &'a Type>,
+    visiting: &mut Vec<&'a Symbol>,
+    sorted: &mut Vec<&'a Symbol>,
+) -> Result<()> {
+    let Some(t) = abbreviations.get(name) else {
+        return Ok(());
+    };
+    if sorted.contains(&name) {
+        return Ok(());
+    }
+    ensure!(
+        !visiting.contains(&name),
+        TypeInferenceError::CyclicAbbreviation(name.clone())
+    );
+
+    visiting.push(name);
+    for referenced in referenced_types(t) {
+        visit_abbreviation(referenced, abbreviations, visiting, sorted)?;
+    }
+    visiting.pop();
+    sorted.push(name);
+
+    Ok(())
+}
+
+// 型式に現れる型構築子の名前
+fn referenced_types(t: &Type) -> Vec<&Symbol> {
+    match t {
+        Type::Constructor { name, args } => std::iter::once(name)
+            .chain(args.iter().flat_map(referenced_types))
+            .collect(),
+        Type::Function { domain, range } => {
+            [referenced_types(domain), referenced_types(range)].concat()
+        }
+        Type::List(t) | Type::Ref(t) => referenced_types(t),
+        Type::Tuple(types) => types.iter().flat_map(referenced_types).collect(),
+        Type::Base(_) | Type::Variable { .. } => vec![],
+    }
+}
+
+fn infer_record(
+    type_environment: TypeEnvironment,
+    fields: Vec<(Symbol, Expression)>,
+) -> InferenceResult {
+    let labels = fields.iter().map(|(label, _)| label).collect::<Vec<_>>();
+    let (record_type, declared_fields) = get_record(&type_environment, &labels)?;
+
+    let undefined_labels = declared_fields
+        .iter()
+        .filter(|field| !labels.contains(&&field.label))
+        .map(|field| field.label.clone())
+        .collect::<Vec<_>>();
+    ensure!(
+        undefined_labels.is_empty(),
+        TypeInferenceError::UndefinedFields(undefined_labels.join(", "))
+    );
+
+    let type_environment = infer_fields(type_environment, &declared_fields, fields)?;
+
+    Ok((type_environment, record_type))
+}
+
+fn infer_field(
+    type_environment: TypeEnvironment,
+    record: Expression,
+    label: Symbol,
+) -> InferenceResult {
+    let (record_type, declared_fields) = get_record(&type_environment, &[&label])?;
+
+    let (type_environment, t) = infer_expression(type_environment, record)?;
+    let type_environment = type_environment.add_equation(record_type, t);
+
+    Ok((type_environment, field_type(&declared_fields, &label)?))
+}
+
+// OCamlと同じく、置き換えるフィールドにしか現れない型パラメータは更新の結果で別の型になってよい
+// 元のレコードと結果をそれぞれ別の型変数で具体化し、置き換えないフィールドの型だけを等しくする
+fn infer_record_update(
+    type_environment: TypeEnvironment,
+    record: Expression,
+    fields: Vec<(Symbol, Expression)>,
+) -> InferenceResult {
+    let labels = fields.iter().map(|(label, _)| label).collect::<Vec<_>>();
+    let (record_type, declared_fields) = get_record(&type_environment, &labels)?;
+    let (result_type, result_fields) = get_record(&type_environment, &labels)?;
+
+    let (type_environment, t) = infer_expression(type_environment, record)?;
+    let type_environment = declared_fields
+        .iter()
+        .zip(&result_fields)
+        .filter(|(field, _)| !labels.contains(&&field.label))
+        .fold(
+            type_environment.add_equation(record_type, t),
+            |type_environment, (field, result_field)| {
+                type_environment
+                    .add_equation(field.field_type.clone(), result_field.field_type.clone())
+            },
+        );
+    let type_environment = infer_fields(type_environment, &result_fields, fields)?;
+
+    Ok((type_environment, result_type))
+}
+
+fn infer_field_assignment(
+    type_environment: TypeEnvironment,
+    record: Expression,
+    label: Symbol,
+    value: Expression,
+) -> InferenceResult {
+    let (record_type, declared_fields) = get_record(&type_environment, &[&label])?;
+    ensure!(
+        declared_fields
+            .iter()
+            .any(|field| field.label == label && field.mutable),
+        TypeInferenceError::ImmutableField(label)
+    );
+
+    let (type_environment, t) = infer_expression(type_environment, record)?;
+    let type_environment = type_environment.add_equation(record_type, t);
+    let (type_environment, t) = infer_expression(type_environment, value)?;
+    let type_environment = type_environment.add_equation(field_type(&declared_fields, &label)?, t);
+
+    Ok((type_environment, Type::Base(BaseType::Unit)))
+}
+
+fn infer_ref(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    let (type_environment, t) = infer_expression(type_environment, expression)?;
+
+    Ok((type_environment, Type::Ref(t.into())))
+}
+
+fn infer_deref(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    let (type_environment, t) = infer_expression(type_environment, expression)?;
+    let content_type = Type::Variable {
+        name: unique_symbol(),
+    };
+    let type_environment = type_environment.add_equation(t, Type::Ref(content_type.clone().into()));
+
+    Ok((type_environment, content_type))
+}
+
+fn infer_assign(
+    type_environment: TypeEnvironment,
+    reference: Expression,
+    value: Expression,
+) -> InferenceResult {
+    let (type_environment, reference_type) = infer_expression(type_environment, reference)?;
+    let (type_environment, value_type) = infer_expression(type_environment, value)?;
     let type_environment =
-        type_environment.add_equation(nil_case_type.clone(), cons_case_type.clone());
+        type_environment.add_equation(reference_type, Type::Ref(value_type.into()));
+
+    Ok((type_environment, Type::Base(BaseType::Unit)))
+}
+
+fn exception_type() -> Type {
+    Type::Constructor {
+        name: EXCEPTION_TYPE.to_string(),
+        args: vec![],
+    }
+}
+
+fn infer_raise(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    let (type_environment, t) = infer_expression(type_environment, expression)?;
+    let type_environment = type_environment.add_equation(t, exception_type());
+
+    Ok((
+        type_environment,
+        Type::Variable {
+            name: unique_symbol(),
+        },
+    ))
+}
+
+// 例外はどの腕にも照合しなければそのまま外に伝わるので、網羅性は検査しない
+fn infer_try(
+    type_environment: TypeEnvironment,
+    body: Expression,
+    arms: Vec<(Pattern, Expression)>,
+) -> InferenceResult {
+    let (type_environment, body_type) = infer_expression(type_environment, body)?;
+    let (type_environment, result_type) = infer_arms(type_environment, exception_type(), arms)?;
+    let type_environment = type_environment.add_equation(body_type, result_type.clone());
+
+    Ok((type_environment, result_type))
+}
+
+// assert false は値を返さないので、raiseと同じくどの型にもなれる
+// 型注釈の型と推論した型が単一化できなければ、注釈を示すエラーにする
+fn infer_annotation(
+    type_environment: TypeEnvironment,
+    expression: Expression,
+    annotation: Type,
+) -> InferenceResult {
+    let parameters = Vec::from_iter(free_type_variables(annotation.clone()));
+    check_type(&type_environment, &parameters, &annotation)?;
+    let annotation = type_environment.resolve_type(annotation);
+    let (type_environment, annotated_type) = type_environment.annotation_type(annotation.clone());
+
+    let (type_environment, t) = infer_expression(type_environment, expression.clone())?;
+    let type_environment = type_environment.unify_equations()?;
+    let t = type_environment.normalize_type(TypeTraverseHistory::new(), t)?;
+
+    let type_environment = type_environment
+        .add_equation(t.clone(), annotated_type.clone())
+        .unify_equations()
+        .map_err(|_| TypeInferenceError::AnnotationMismatch(expression, t, annotation))?;
+
+    Ok((type_environment, annotated_type))
+}
+
+fn infer_assert(type_environment: TypeEnvironment, expression: Expression) -> InferenceResult {
+    if expression == Expression::Bool(false) {
+        return Ok((
+            type_environment,
+            Type::Variable {
+                name: unique_symbol(),
+            },
+        ));
+    }
+
+    let (type_environment, t) = infer_expression(type_environment, expression)?;
+    let type_environment = type_environment.add_equation(t, Type::Base(BaseType::Bool));
+
+    Ok((type_environment, Type::Base(BaseType::Unit)))
+}
+
+fn infer_fields(
+    type_environment: TypeEnvironment,
+    declared_fields: &[RecordField],
+    fields: Vec<(Symbol, Expression)>,
+) -> Result<TypeEnvironment> {
+    fields
+        .into_iter()
+        .try_fold(type_environment, |type_environment, (label, expression)| {
+            let (type_environment, t) = infer_expression(type_environment, expression)?;
+            Ok(type_environment.add_equation(field_type(declared_fields, &label)?, t))
+        })
+}
+
+// ラベルがすべて同じレコード型のもので、重複していないことを確かめてから、その型とフィールドを返す
+fn get_record(
+    type_environment: &TypeEnvironment,
+    labels: &[&Symbol],
+) -> Result<(Type, Vec<RecordField>)> {
+    let mut seen = HashSet::new();
+    let mut expected_type_name = None;
+    for label in labels {
+        let Some(type_name) = type_environment.get_record_name(label) else {
+            bail!(TypeInferenceError::UnboundField((*label).clone()));
+        };
+        let expected_type_name = expected_type_name.get_or_insert(type_name);
+        ensure!(
+            *expected_type_name == type_name,
+            TypeInferenceError::MixedFields(
+                (*label).clone(),
+                type_name.clone(),
+                expected_type_name.clone()
+            )
+        );
+        ensure!(
+            seen.insert(*label),
+            TypeInferenceError::DuplicateField((*label).clone())
+        );
+    }
+
+    labels
+        .first()
+        .and_then(|label| type_environment.get_record(label))
+        .ok_or_else(|| anyhow!(TypeInferenceError::UndefinedFields(String::new())))
+}
+
+fn field_type(declared_fields: &[RecordField], label: &Symbol) -> Result<Type> {
+    declared_fields
+        .iter()
+        .find(|field| field.label == *label)
+        .map(|field| field.field_type.clone())
+        .ok_or_else(|| anyhow!(TypeInferenceError::UnboundField(label.clone())))
+}
 
-    Ok((type_environment, nil_case_type))
+// 型式中の型構築子がすべて定義済みで引数の個数が合っていること、型変数が型パラメータであることを確かめる
+fn check_type(type_environment: &TypeEnvironment, parameters: &[Symbol], t: &Type) -> Result<()> {
+    match t {
+        Type::Base(_) => Ok(()),
+        Type::Variable { name } => {
+            ensure!(
+                parameters.contains(name),
+                TypeInferenceError::UnboundTypeParameter(name.clone())
+            );
+            Ok(())
+        }
+        Type::List(element_type) | Type::Ref(element_type) => {
+            check_type(type_environment, parameters, element_type)
+        }
+        Type::Tuple(types) => types
+            .iter()
+            .try_for_each(|t| check_type(type_environment, parameters, t)),
+        Type::Function { domain, range } => {
+            check_type(type_environment, parameters, domain)?;
+            check_type(type_environment, parameters, range)
+        }
+        Type::Constructor { name, args } => {
+            let Some(definition) = type_environment.get_type_definition(name) else {
+                bail!(TypeInferenceError::UnboundTypeConstructor(name.clone()));
+            };
+            ensure!(
+                args.len() == definition.parameters.len(),
+                TypeInferenceError::TypeArity(
+                    name.clone(),
+                    definition.parameters.len(),
+                    args.len()
+                )
+            );
+            args.iter()
+                .try_for_each(|t| check_type(type_environment, parameters, t))
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::analysis::{
+        parser::{parse, parse_program},
+        tokenizer::tokenize,
+    };
+    use crate::type_system::warning::Warning;
+
+    fn infer_source(source: &str) -> Result<Type> {
+        let expression = parse(tokenize(source.to_string())?)?;
+        let (_, t) = infer(TypeEnvironment::default(), expression)?;
+        Ok(t)
+    }
+
+    // 局所的な型は式の型に現れてはいけないので、型はトップレベルで宣言しておく
+    fn declare_types(definitions: Vec<TypeDefinition>) -> TypeEnvironment {
+        let (type_environment, _) = infer_top_level(
+            TypeEnvironment::default(),
+            TopLevel::TypeDefinition(definitions),
+        )
+        .unwrap();
+        type_environment
+    }
+
+    // |> と @@ のテストで使う関数
+    const LIST_FUNCTIONS: &str = "
+        let rec map f xs = match xs with [] -> [] | x :: rest -> f x :: map f rest in
+        let rec filter p xs =
+          match xs with [] -> [] | x :: rest -> if p x then x :: filter p rest else filter p rest
+        in
+    ";
+
+    fn infer_program(source: &str) -> Result<Type> {
+        let program = parse_program(tokenize(source.to_string())?)?;
+        let (_, t) = program.top_levels.into_iter().try_fold(
+            (TypeEnvironment::default(), Type::Base(BaseType::Unit)),
+            |(type_environment, _), top_level| infer_top_level(type_environment, top_level),
+        )?;
+        Ok(t)
+    }
+
+    // let* と and* のテストで使う option の束縛演算子
+    const OPTION_OPERATORS: &str = "
+        type 'a option = None | Some of 'a
+        let ( let* ) o f = match o with None -> None | Some x -> f x
+        let ( and* ) a b = match (a, b) with (Some x, Some y) -> Some (x, y) | _ -> None
+    ";
 
     #[test]
     fn test_infer_integer() {
@@ -454,43 +2212,217 @@ mod test {
     }
 
     #[test]
-    fn test_infer_invalid_operation() {
-        let expression = Expression::Plus {
-            expression1: Expression::Integer(3).into(),
-            expression2: Expression::Bool(true).into(),
+    fn test_infer_concat() {
+        let expression = Expression::Concat {
+            expression1: Expression::String("foo".to_string()).into(),
+            expression2: Expression::String("bar".to_string()).into(),
         };
 
         let result = infer(TypeEnvironment::default(), expression);
 
-        assert!(result.is_err());
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::String));
     }
 
     #[test]
-    fn test_infer_if() {
-        let expression = Expression::If {
-            predicate: Expression::Bool(true).into(),
-            consequent: Expression::Integer(1).into(),
-            alternative: Expression::Integer(2).into(),
+    fn test_infer_concat_with_char() {
+        let expression = Expression::Concat {
+            expression1: Expression::String("foo".to_string()).into(),
+            expression2: Expression::Char('a').into(),
         };
 
         let result = infer(TypeEnvironment::default(), expression);
 
-        assert!(result.is_ok());
-        let (_, t) = result.unwrap();
-        assert_eq!(t, Type::Base(BaseType::Integer));
+        assert!(result.is_err());
     }
 
     #[test]
-    fn test_infer_if_with_invalid_predicate() {
-        let expression = Expression::If {
-            predicate: Expression::Integer(1).into(),
-            consequent: Expression::Integer(1).into(),
-            alternative: Expression::Integer(2).into(),
+    fn test_infer_append() {
+        let expression = Expression::Append {
+            expression1: Expression::Nil.into(),
+            expression2: Expression::Cons {
+                car: Expression::Char('a').into(),
+                cdr: Expression::Nil.into(),
+            }
+            .into(),
         };
 
         let result = infer(TypeEnvironment::default(), expression);
 
-        assert!(result.is_err());
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::List(Type::Base(BaseType::Char).into()));
+    }
+
+    #[test]
+    fn test_infer_append_with_non_list() {
+        let expression = Expression::Append {
+            expression1: Expression::Nil.into(),
+            expression2: Expression::Integer(1).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_less_than_on_strings() {
+        let expression = Expression::LessThan {
+            expression1: Expression::String("a".to_string()).into(),
+            expression2: Expression::String("b".to_string()).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Bool));
+    }
+
+    #[test]
+    fn test_infer_arithmetic_requires_int() {
+        let expression = Expression::Divide {
+            expression1: Expression::String("a".to_string()).into(),
+            expression2: Expression::String("b".to_string()).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_equal_on_tuples() {
+        let expression = Expression::Equal {
+            expression1: Expression::Tuple(vec![Expression::Integer(1), Expression::Bool(true)])
+                .into(),
+            expression2: Expression::Tuple(vec![Expression::Integer(1), Expression::Bool(false)])
+                .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Bool));
+    }
+
+    #[test]
+    fn test_infer_logical_operation_requires_bool() {
+        let expression = Expression::And {
+            expression1: Expression::Bool(true).into(),
+            expression2: Expression::Integer(1).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_annotation() {
+        // (fun x -> x : int -> int)
+        let expression = Expression::Annotation {
+            expression: Expression::Fun {
+                parameter: "x".to_string(),
+                body: Expression::Variable("x".to_string()).into(),
+            }
+            .into(),
+            annotation: Type::Function {
+                domain: Type::Base(BaseType::Integer).into(),
+                range: Type::Base(BaseType::Integer).into(),
+            },
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(
+            t,
+            Type::Function {
+                domain: Type::Base(BaseType::Integer).into(),
+                range: Type::Base(BaseType::Integer).into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_infer_annotation_mismatch() {
+        // (true : int)
+        let expression = Expression::Annotation {
+            expression: Expression::Bool(true).into(),
+            annotation: Type::Base(BaseType::Integer),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The expression true has type bool but is annotated with type int"
+        );
+    }
+
+    #[test]
+    fn test_infer_annotation_type_variables_are_shared() {
+        // ((1 : 'a), (true : 'a))
+        let annotate = |expression: Expression| Expression::Annotation {
+            expression: expression.into(),
+            annotation: Type::Variable {
+                name: "'a".to_string(),
+            },
+        };
+        let expression = Expression::Tuple(vec![
+            annotate(Expression::Integer(1)),
+            annotate(Expression::Bool(true)),
+        ]);
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_invalid_operation() {
+        let expression = Expression::Plus {
+            expression1: Expression::Integer(3).into(),
+            expression2: Expression::Bool(true).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_if() {
+        let expression = Expression::If {
+            predicate: Expression::Bool(true).into(),
+            consequent: Expression::Integer(1).into(),
+            alternative: Expression::Integer(2).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+    }
+
+    #[test]
+    fn test_infer_if_with_invalid_predicate() {
+        let expression = Expression::If {
+            predicate: Expression::Integer(1).into(),
+            consequent: Expression::Integer(1).into(),
+            alternative: Expression::Integer(2).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
     }
 
     #[test]
@@ -641,33 +2573,34 @@ mod test {
     #[test]
     fn test_infer_let_rec() {
         let expression = Expression::LetRec {
-            variable: "fact".to_string(),
-            bound_function: Expression::Fun {
-                parameter: "n".to_string(),
-                body: Expression::If {
-                    predicate: Expression::LessThan {
-                        expression1: Expression::Variable("n".to_string()).into(),
-                        expression2: Expression::Integer(1).into(),
-                    }
-                    .into(),
-                    consequent: Expression::Integer(1).into(),
-                    alternative: Expression::Times {
-                        expression1: Expression::Variable("n".to_string()).into(),
-                        expression2: Expression::App {
-                            function: Expression::Variable("fact".to_string()).into(),
-                            argument: Expression::Minus {
-                                expression1: Expression::Variable("n".to_string()).into(),
-                                expression2: Expression::Integer(1).into(),
+            bindings: vec![(
+                "fact".to_string(),
+                Expression::Fun {
+                    parameter: "n".to_string(),
+                    body: Expression::If {
+                        predicate: Expression::LessThan {
+                            expression1: Expression::Variable("n".to_string()).into(),
+                            expression2: Expression::Integer(1).into(),
+                        }
+                        .into(),
+                        consequent: Expression::Integer(1).into(),
+                        alternative: Expression::Times {
+                            expression1: Expression::Variable("n".to_string()).into(),
+                            expression2: Expression::App {
+                                function: Expression::Variable("fact".to_string()).into(),
+                                argument: Expression::Minus {
+                                    expression1: Expression::Variable("n".to_string()).into(),
+                                    expression2: Expression::Integer(1).into(),
+                                }
+                                .into(),
                             }
                             .into(),
                         }
                         .into(),
                     }
                     .into(),
-                }
-                .into(),
-            }
-            .into(),
+                },
+            )],
             body: Expression::App {
                 function: Expression::Variable("fact".to_string()).into(),
                 argument: Expression::Integer(5).into(),
@@ -682,6 +2615,103 @@ mod test {
         assert_eq!(t, Type::Base(BaseType::Integer));
     }
 
+    #[test]
+    fn test_infer_mutually_recursive_functions() {
+        // let rec even n = if n = 0 then true else odd (n - 1)
+        // and odd n = if n = 0 then false else even (n - 1) in even
+        // n = 0 のとき base を返し、そうでなければ other (n - 1) を呼ぶ関数
+        let parity = |base: bool, other: &str| Expression::Fun {
+            parameter: "n".to_string(),
+            body: Expression::If {
+                predicate: Expression::Equal {
+                    expression1: Expression::Variable("n".to_string()).into(),
+                    expression2: Expression::Integer(0).into(),
+                }
+                .into(),
+                consequent: Expression::Bool(base).into(),
+                alternative: Expression::App {
+                    function: Expression::Variable(other.to_string()).into(),
+                    argument: Expression::Minus {
+                        expression1: Expression::Variable("n".to_string()).into(),
+                        expression2: Expression::Integer(1).into(),
+                    }
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+        let expression = Expression::LetRec {
+            bindings: vec![
+                ("even".to_string(), parity(true, "odd")),
+                ("odd".to_string(), parity(false, "even")),
+            ],
+            body: Expression::Variable("even".to_string()).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(
+            t,
+            Type::Function {
+                domain: Type::Base(BaseType::Integer).into(),
+                range: Type::Base(BaseType::Bool).into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_infer_let_rec_group_is_generalized() {
+        // let rec f x = x and g y = f y in (g 1, g true)
+        let expression = Expression::LetRec {
+            bindings: vec![
+                (
+                    "f".to_string(),
+                    Expression::Fun {
+                        parameter: "x".to_string(),
+                        body: Expression::Variable("x".to_string()).into(),
+                    },
+                ),
+                (
+                    "g".to_string(),
+                    Expression::Fun {
+                        parameter: "y".to_string(),
+                        body: Expression::App {
+                            function: Expression::Variable("f".to_string()).into(),
+                            argument: Expression::Variable("y".to_string()).into(),
+                        }
+                        .into(),
+                    },
+                ),
+            ],
+            body: Expression::Tuple(vec![
+                Expression::App {
+                    function: Expression::Variable("g".to_string()).into(),
+                    argument: Expression::Integer(1).into(),
+                },
+                Expression::App {
+                    function: Expression::Variable("g".to_string()).into(),
+                    argument: Expression::Bool(true).into(),
+                },
+            ])
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(
+            t,
+            Type::Tuple(vec![
+                Type::Base(BaseType::Integer),
+                Type::Base(BaseType::Bool)
+            ])
+        );
+    }
+
     #[test]
     fn test_infer_nil() {
         let expression = Expression::Nil;
@@ -729,16 +2759,19 @@ mod test {
     fn test_infer_match_nil_case() {
         let expression = Expression::Match {
             scrutinee: Expression::Nil.into(),
-            nil_case: Expression::Integer(0).into(),
-            cons_pattern: (
-                "head".to_string(),
-                "tail".to_string(),
-                Expression::Plus {
-                    expression1: Expression::Variable("head".to_string()).into(),
-                    expression2: Expression::Integer(1).into(),
-                }
-                .into(),
-            ),
+            arms: vec![
+                (Pattern::Nil, Expression::Integer(0)),
+                (
+                    Pattern::Cons {
+                        car: Pattern::Variable("head".to_string()).into(),
+                        cdr: Pattern::Variable("tail".to_string()).into(),
+                    },
+                    Expression::Plus {
+                        expression1: Expression::Variable("head".to_string()).into(),
+                        expression2: Expression::Integer(1).into(),
+                    },
+                ),
+            ],
         };
 
         let result = infer(TypeEnvironment::default(), expression);
@@ -756,16 +2789,19 @@ mod test {
                 cdr: Expression::Nil.into(),
             }
             .into(),
-            nil_case: Expression::Integer(0).into(),
-            cons_pattern: (
-                "head".to_string(),
-                "tail".to_string(),
-                Expression::Plus {
-                    expression1: Expression::Variable("head".to_string()).into(),
-                    expression2: Expression::Integer(1).into(),
-                }
-                .into(),
-            ),
+            arms: vec![
+                (Pattern::Nil, Expression::Integer(0)),
+                (
+                    Pattern::Cons {
+                        car: Pattern::Variable("head".to_string()).into(),
+                        cdr: Pattern::Variable("tail".to_string()).into(),
+                    },
+                    Expression::Plus {
+                        expression1: Expression::Variable("head".to_string()).into(),
+                        expression2: Expression::Integer(1).into(),
+                    },
+                ),
+            ],
         };
 
         let result = infer(TypeEnvironment::default(), expression);
@@ -779,16 +2815,19 @@ mod test {
     fn test_infer_match_with_invalid_scrutinee() {
         let expression = Expression::Match {
             scrutinee: Expression::Integer(5).into(),
-            nil_case: Expression::Integer(0).into(),
-            cons_pattern: (
-                "head".to_string(),
-                "tail".to_string(),
-                Expression::Plus {
-                    expression1: Expression::Variable("head".to_string()).into(),
-                    expression2: Expression::Integer(1).into(),
-                }
-                .into(),
-            ),
+            arms: vec![
+                (Pattern::Nil, Expression::Integer(0)),
+                (
+                    Pattern::Cons {
+                        car: Pattern::Variable("head".to_string()).into(),
+                        cdr: Pattern::Variable("tail".to_string()).into(),
+                    },
+                    Expression::Plus {
+                        expression1: Expression::Variable("head".to_string()).into(),
+                        expression2: Expression::Integer(1).into(),
+                    },
+                ),
+            ],
         };
 
         let result = infer(TypeEnvironment::default(), expression);
@@ -800,12 +2839,16 @@ mod test {
     fn test_infer_match_with_mismatched_cases() {
         let expression = Expression::Match {
             scrutinee: Expression::Nil.into(),
-            nil_case: Expression::Integer(0).into(),
-            cons_pattern: (
-                "head".to_string(),
-                "tail".to_string(),
-                Expression::Bool(true).into(),
-            ),
+            arms: vec![
+                (Pattern::Nil, Expression::Integer(0)),
+                (
+                    Pattern::Cons {
+                        car: Pattern::Variable("head".to_string()).into(),
+                        cdr: Pattern::Variable("tail".to_string()).into(),
+                    },
+                    Expression::Bool(true),
+                ),
+            ],
         };
 
         let result = infer(TypeEnvironment::default(), expression);
@@ -890,20 +2933,23 @@ mod test {
             parameter: "l".to_string(),
             body: Expression::Match {
                 scrutinee: Expression::Variable("l".to_string()).into(),
-                nil_case: Expression::Integer(0).into(),
-                cons_pattern: (
-                    "h".to_string(),
-                    "t".to_string(),
-                    Expression::Plus {
-                        expression1: Expression::Variable("h".to_string()).into(),
-                        expression2: Expression::App {
-                            function: Expression::Variable("sum".to_string()).into(),
-                            argument: Expression::Variable("t".to_string()).into(),
-                        }
-                        .into(),
-                    }
-                    .into(),
-                ),
+                arms: vec![
+                    (Pattern::Nil, Expression::Integer(0)),
+                    (
+                        Pattern::Cons {
+                            car: Pattern::Variable("h".to_string()).into(),
+                            cdr: Pattern::Variable("t".to_string()).into(),
+                        },
+                        Expression::Plus {
+                            expression1: Expression::Variable("h".to_string()).into(),
+                            expression2: Expression::App {
+                                function: Expression::Variable("sum".to_string()).into(),
+                                argument: Expression::Variable("t".to_string()).into(),
+                            }
+                            .into(),
+                        },
+                    ),
+                ],
             }
             .into(),
         };
@@ -922,8 +2968,7 @@ mod test {
         };
 
         let expression = Expression::LetRec {
-            variable: "sum".to_string(),
-            bound_function: sum_function.into(),
+            bindings: vec![("sum".to_string(), sum_function)],
             body: Expression::App {
                 function: Expression::Variable("sum".to_string()).into(),
                 argument: list_expr.into(),
@@ -979,28 +3024,31 @@ mod test {
                 parameter: "xs".to_string(),
                 body: Expression::Match {
                     scrutinee: Expression::Variable("xs".to_string()).into(),
-                    nil_case: Expression::Nil.into(),
-                    cons_pattern: (
-                        "h".to_string(),
-                        "t".to_string(),
-                        Expression::Cons {
-                            car: Expression::App {
-                                function: Expression::Variable("f".to_string()).into(),
-                                argument: Expression::Variable("h".to_string()).into(),
-                            }
-                            .into(),
-                            cdr: Expression::App {
-                                function: Expression::App {
-                                    function: Expression::Variable("map".to_string()).into(),
-                                    argument: Expression::Variable("f".to_string()).into(),
+                    arms: vec![
+                        (Pattern::Nil, Expression::Nil),
+                        (
+                            Pattern::Cons {
+                                car: Pattern::Variable("h".to_string()).into(),
+                                cdr: Pattern::Variable("t".to_string()).into(),
+                            },
+                            Expression::Cons {
+                                car: Expression::App {
+                                    function: Expression::Variable("f".to_string()).into(),
+                                    argument: Expression::Variable("h".to_string()).into(),
                                 }
                                 .into(),
-                                argument: Expression::Variable("t".to_string()).into(),
-                            }
-                            .into(),
-                        }
-                        .into(),
-                    ),
+                                cdr: Expression::App {
+                                    function: Expression::App {
+                                        function: Expression::Variable("map".to_string()).into(),
+                                        argument: Expression::Variable("f".to_string()).into(),
+                                    }
+                                    .into(),
+                                    argument: Expression::Variable("t".to_string()).into(),
+                                }
+                                .into(),
+                            },
+                        ),
+                    ],
                 }
                 .into(),
             }
@@ -1039,8 +3087,7 @@ mod test {
         };
 
         let expression = Expression::LetRec {
-            variable: "map".to_string(),
-            bound_function: map_function.into(),
+            bindings: vec![("map".to_string(), map_function)],
             body: Expression::Let {
                 variable: "int_list".to_string(),
                 bound: int_list.into(),
@@ -1178,4 +3225,1512 @@ mod test {
         let (_, t) = result.unwrap();
         assert_eq!(t, Type::Base(BaseType::Integer));
     }
+
+    #[test]
+    fn test_infer_tuple() {
+        let expression = Expression::Tuple(vec![
+            Expression::Integer(1),
+            Expression::Bool(true),
+            Expression::Nil,
+        ]);
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert!(matches!(
+            t,
+            Type::Tuple(types) if matches!(
+                types.as_slice(),
+                [Type::Base(BaseType::Integer), Type::Base(BaseType::Bool), Type::List(_)]
+            )
+        ));
+    }
+
+    #[test]
+    fn test_infer_polymorphic_swap() {
+        // let swap = fun p -> match p with (a, b) -> (b, a) in swap (swap (1, true))
+        let swap_function = Expression::Fun {
+            parameter: "p".to_string(),
+            body: Expression::Match {
+                scrutinee: Expression::Variable("p".to_string()).into(),
+                arms: vec![(
+                    Pattern::Tuple(vec![
+                        Pattern::Variable("a".to_string()),
+                        Pattern::Variable("b".to_string()),
+                    ]),
+                    Expression::Tuple(vec![
+                        Expression::Variable("b".to_string()),
+                        Expression::Variable("a".to_string()),
+                    ]),
+                )],
+            }
+            .into(),
+        };
+
+        let expression = Expression::Let {
+            variable: "swap".to_string(),
+            bound: swap_function.into(),
+            body: Expression::App {
+                function: Expression::Variable("swap".to_string()).into(),
+                argument: Expression::App {
+                    function: Expression::Variable("swap".to_string()).into(),
+                    argument: Expression::Tuple(vec![
+                        Expression::Integer(1),
+                        Expression::Bool(true),
+                    ])
+                    .into(),
+                }
+                .into(),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(
+            t,
+            Type::Tuple(vec![
+                Type::Base(BaseType::Integer),
+                Type::Base(BaseType::Bool)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_infer_match_tuple_with_mismatched_arity() {
+        let expression = Expression::Match {
+            scrutinee: Expression::Tuple(vec![Expression::Integer(1), Expression::Integer(2)])
+                .into(),
+            arms: vec![(
+                Pattern::Tuple(vec![
+                    Pattern::Variable("a".to_string()),
+                    Pattern::Variable("b".to_string()),
+                    Pattern::Variable("c".to_string()),
+                ]),
+                Expression::Variable("a".to_string()),
+            )],
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_match_nested_patterns() {
+        // fun xs -> match xs with [] -> 0 | (0, _) :: _ -> 1 | (_, b) :: _ -> if b then 2 else 3
+        let expression = Expression::Fun {
+            parameter: "xs".to_string(),
+            body: Expression::Match {
+                scrutinee: Expression::Variable("xs".to_string()).into(),
+                arms: vec![
+                    (Pattern::Nil, Expression::Integer(0)),
+                    (
+                        Pattern::Cons {
+                            car: Pattern::Tuple(vec![Pattern::Integer(0), Pattern::Wildcard])
+                                .into(),
+                            cdr: Pattern::Wildcard.into(),
+                        },
+                        Expression::Integer(1),
+                    ),
+                    (
+                        Pattern::Cons {
+                            car: Pattern::Tuple(vec![
+                                Pattern::Wildcard,
+                                Pattern::Variable("b".to_string()),
+                            ])
+                            .into(),
+                            cdr: Pattern::Wildcard.into(),
+                        },
+                        Expression::If {
+                            predicate: Expression::Variable("b".to_string()).into(),
+                            consequent: Expression::Integer(2).into(),
+                            alternative: Expression::Integer(3).into(),
+                        },
+                    ),
+                ],
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(
+            t,
+            Type::Function {
+                domain: Type::List(
+                    Type::Tuple(vec![
+                        Type::Base(BaseType::Integer),
+                        Type::Base(BaseType::Bool)
+                    ])
+                    .into()
+                )
+                .into(),
+                range: Type::Base(BaseType::Integer).into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_infer_match_with_mismatched_literal_pattern() {
+        // match 1 with true -> 0 | _ -> 1
+        let expression = Expression::Match {
+            scrutinee: Expression::Integer(1).into(),
+            arms: vec![
+                (Pattern::Bool(true), Expression::Integer(0)),
+                (Pattern::Wildcard, Expression::Integer(1)),
+            ],
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_sequence() {
+        // (); 1
+        let expression = Expression::Sequence {
+            expression1: Expression::Unit.into(),
+            expression2: Expression::Integer(1).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (type_environment, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+        assert!(type_environment.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_infer_sequence_warns_non_unit_statement() {
+        // fun x -> x + 1; x
+        let expression = Expression::Fun {
+            parameter: "x".to_string(),
+            body: Expression::Sequence {
+                expression1: Expression::Plus {
+                    expression1: Expression::Variable("x".to_string()).into(),
+                    expression2: Expression::Integer(1).into(),
+                }
+                .into(),
+                expression2: Expression::Variable("x".to_string()).into(),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        assert_eq!(
+            type_environment.warnings(),
+            &[Warning::NonUnitStatement {
+                statement_type: Type::Base(BaseType::Integer),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_infer_reports_non_exhaustive_match() {
+        // fun xs -> match xs with x :: _ -> x
+        let expression = Expression::Fun {
+            parameter: "xs".to_string(),
+            body: Expression::Match {
+                scrutinee: Expression::Variable("xs".to_string()).into(),
+                arms: vec![(
+                    Pattern::Cons {
+                        car: Pattern::Variable("x".to_string()).into(),
+                        cdr: Pattern::Wildcard.into(),
+                    },
+                    Expression::Variable("x".to_string()),
+                )],
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        assert_eq!(
+            type_environment.warnings(),
+            &[Warning::NonExhaustiveMatch {
+                counterexample: Pattern::Nil,
+            }]
+        );
+    }
+
+    fn shape_definition() -> TypeDefinition {
+        // type shape = Circle of int | Square of int | Dot
+        TypeDefinition {
+            name: "shape".to_string(),
+            parameters: vec![],
+            kind: TypeKind::Variant(vec![
+                ("Circle".to_string(), Some(Type::Base(BaseType::Integer))),
+                ("Square".to_string(), Some(Type::Base(BaseType::Integer))),
+                ("Dot".to_string(), None),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_infer_constructor() {
+        // type shape = ...;; Circle 3
+        let expression = Expression::Constructor {
+            name: "Circle".to_string(),
+            argument: Some(Expression::Integer(3).into()),
+        };
+
+        let result = infer(declare_types(vec![shape_definition()]), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t.to_string(), "shape");
+    }
+
+    #[test]
+    fn test_infer_constructor_with_invalid_argument() {
+        // type shape = ... in Circle true
+        let expression = Expression::TypeDefinition {
+            definitions: vec![shape_definition()],
+            body: Expression::Constructor {
+                name: "Circle".to_string(),
+                argument: Some(Expression::Bool(true).into()),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_constructor_with_missing_argument() {
+        // type shape = ... in Circle
+        let expression = Expression::TypeDefinition {
+            definitions: vec![shape_definition()],
+            body: Expression::Constructor {
+                name: "Circle".to_string(),
+                argument: None,
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The constructor Circle expects 1 argument(s), but is applied here to 0 argument(s)"
+        );
+    }
+
+    #[test]
+    fn test_infer_unbound_constructor() {
+        let expression = Expression::Constructor {
+            name: "Circle".to_string(),
+            argument: Some(Expression::Integer(3).into()),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unbound constructor: Circle"
+        );
+    }
+
+    #[test]
+    fn test_infer_match_constructor_patterns() {
+        // type shape = ...;; fun s -> match s with Circle r -> r | Square _ -> 0
+        let expression = Expression::Fun {
+            parameter: "s".to_string(),
+            body: Expression::Match {
+                scrutinee: Expression::Variable("s".to_string()).into(),
+                arms: vec![
+                    (
+                        Pattern::Constructor {
+                            name: "Circle".to_string(),
+                            argument: Some(Pattern::Variable("r".to_string()).into()),
+                        },
+                        Expression::Variable("r".to_string()),
+                    ),
+                    (
+                        Pattern::Constructor {
+                            name: "Square".to_string(),
+                            argument: Some(Pattern::Wildcard.into()),
+                        },
+                        Expression::Integer(0),
+                    ),
+                ],
+            }
+            .into(),
+        };
+
+        let result = infer(declare_types(vec![shape_definition()]), expression);
+
+        assert!(result.is_ok());
+        let (type_environment, t) = result.unwrap();
+        assert_eq!(t.to_string(), "shape -> int");
+        assert_eq!(
+            type_environment.warnings(),
+            &[Warning::NonExhaustiveMatch {
+                counterexample: Pattern::Constructor {
+                    name: "Dot".to_string(),
+                    argument: None,
+                },
+            }]
+        );
+    }
+
+    #[test]
+    fn test_infer_type_definition_with_unbound_type() {
+        // type t = A of u in 1
+        let expression = Expression::TypeDefinition {
+            definitions: vec![TypeDefinition {
+                name: "t".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Variant(vec![(
+                    "A".to_string(),
+                    Some(Type::Constructor {
+                        name: "u".to_string(),
+                        args: vec![],
+                    }),
+                )]),
+            }],
+            body: Expression::Integer(1).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unbound type constructor: u"
+        );
+    }
+
+    fn tree_definition() -> TypeDefinition {
+        // type 'a tree = Leaf | Node of 'a tree * 'a * 'a tree
+        let tree = Type::Constructor {
+            name: "tree".to_string(),
+            args: vec![Type::Variable {
+                name: "'a".to_string(),
+            }],
+        };
+        TypeDefinition {
+            name: "tree".to_string(),
+            parameters: vec!["'a".to_string()],
+            kind: TypeKind::Variant(vec![
+                ("Leaf".to_string(), None),
+                (
+                    "Node".to_string(),
+                    Some(Type::Tuple(vec![
+                        tree.clone(),
+                        Type::Variable {
+                            name: "'a".to_string(),
+                        },
+                        tree,
+                    ])),
+                ),
+            ]),
+        }
+    }
+
+    fn node(left: Expression, value: Expression, right: Expression) -> Expression {
+        Expression::Constructor {
+            name: "Node".to_string(),
+            argument: Some(Expression::Tuple(vec![left, value, right]).into()),
+        }
+    }
+
+    fn leaf() -> Expression {
+        Expression::Constructor {
+            name: "Leaf".to_string(),
+            argument: None,
+        }
+    }
+
+    #[test]
+    fn test_infer_polymorphic_constructor() {
+        // type 'a tree = ...;; (Node (Leaf, 1, Leaf), Node (Leaf, true, Leaf))
+        let expression = Expression::Tuple(vec![
+            node(leaf(), Expression::Integer(1), leaf()),
+            node(leaf(), Expression::Bool(true), leaf()),
+        ]);
+
+        let result = infer(declare_types(vec![tree_definition()]), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t.to_string(), "int tree * bool tree");
+    }
+
+    #[test]
+    fn test_infer_polymorphic_constructor_with_mismatched_elements() {
+        // type 'a tree = ... in Node (Node (Leaf, 1, Leaf), true, Leaf)
+        let expression = Expression::TypeDefinition {
+            definitions: vec![tree_definition()],
+            body: node(
+                node(leaf(), Expression::Integer(1), leaf()),
+                Expression::Bool(true),
+                leaf(),
+            )
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_type_definition_with_wrong_arity() {
+        // type 'a tree = ... and forest = Forest of tree list in 1
+        let expression = Expression::TypeDefinition {
+            definitions: vec![
+                tree_definition(),
+                TypeDefinition {
+                    name: "forest".to_string(),
+                    parameters: vec![],
+                    kind: TypeKind::Variant(vec![(
+                        "Forest".to_string(),
+                        Some(Type::List(
+                            Type::Constructor {
+                                name: "tree".to_string(),
+                                args: vec![],
+                            }
+                            .into(),
+                        )),
+                    )]),
+                },
+            ],
+            body: Expression::Integer(1).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The type constructor tree expects 1 argument(s), but is here applied to 0 argument(s)"
+        );
+    }
+
+    #[test]
+    fn test_infer_mutually_recursive_type_definitions() {
+        // type expr = Num of int | Block of stmt list and stmt = Expr of expr;; Block (Expr (Num 1) :: [])
+        let named = |name: &str| Type::Constructor {
+            name: name.to_string(),
+            args: vec![],
+        };
+        let constructor = |name: &str, argument: Expression| Expression::Constructor {
+            name: name.to_string(),
+            argument: Some(argument.into()),
+        };
+        let definitions = vec![
+            TypeDefinition {
+                name: "expr".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Variant(vec![
+                    ("Num".to_string(), Some(Type::Base(BaseType::Integer))),
+                    ("Block".to_string(), Some(Type::List(named("stmt").into()))),
+                ]),
+            },
+            TypeDefinition {
+                name: "stmt".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Variant(vec![("Expr".to_string(), Some(named("expr")))]),
+            },
+        ];
+        let expression = constructor(
+            "Block",
+            Expression::Cons {
+                car: constructor("Expr", constructor("Num", Expression::Integer(1))).into(),
+                cdr: Expression::Nil.into(),
+            },
+        );
+
+        let result = infer(declare_types(definitions), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, named("expr"));
+    }
+
+    #[test]
+    fn test_infer_local_type_stays_in_scope() {
+        let source = "
+            type t = A | B;;
+            (type u = A | C in match A with A -> 1 | C -> 2) + (match B with A -> 1 | B -> 2)
+        ";
+
+        let result = infer_program(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "int");
+
+        let result = infer_program("type u = C in 1;; C");
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Unbound constructor: C");
+    }
+
+    #[test]
+    fn test_infer_local_type_escaping_its_scope() {
+        let result = infer_program("let x = type t = A in A");
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The type constructor t would escape its scope"
+        );
+    }
+
+    #[test]
+    fn test_infer_redeclared_type_is_a_new_type() {
+        let source = "
+            type t = A of int;;
+            let f x = match x with A n -> n + 1;;
+            type t = A of bool;;
+            f (A true)
+        ";
+
+        let result = infer_program(source);
+
+        assert!(result.is_err());
+
+        let result = infer_program("type t = A;; let x = A;; type t = B;; x = B");
+
+        assert!(result.is_err());
+
+        let result = infer_program("type t = A;; let x = A;; type t = B;; x");
+
+        // 隠れた型の番号は、それまでに隠した型の数で変わる
+        assert!(result.is_ok());
+        assert!(result.unwrap().to_string().starts_with("t/"));
+    }
+
+    fn person_definition() -> TypeDefinition {
+        // type person = { name : int; mutable age : int }
+        TypeDefinition {
+            name: "person".to_string(),
+            parameters: vec![],
+            kind: TypeKind::Record(vec![
+                RecordField {
+                    label: "name".to_string(),
+                    mutable: false,
+                    field_type: Type::Base(BaseType::Integer),
+                },
+                RecordField {
+                    label: "age".to_string(),
+                    mutable: true,
+                    field_type: Type::Base(BaseType::Integer),
+                },
+            ]),
+        }
+    }
+
+    fn person(name: Expression, age: Expression) -> Expression {
+        Expression::Record(vec![("name".to_string(), name), ("age".to_string(), age)])
+    }
+
+    #[test]
+    fn test_infer_record_field() {
+        // type person = ... in { name = 1; age = 30 }.age
+        let expression = Expression::TypeDefinition {
+            definitions: vec![person_definition()],
+            body: Expression::Field {
+                record: person(Expression::Integer(1), Expression::Integer(30)).into(),
+                label: "age".to_string(),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+    }
+
+    #[test]
+    fn test_infer_record_with_undefined_field() {
+        // type person = ... in { name = 1 }
+        let expression = Expression::TypeDefinition {
+            definitions: vec![person_definition()],
+            body: Expression::Record(vec![("name".to_string(), Expression::Integer(1))]).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Some record fields are undefined: age"
+        );
+    }
+
+    #[test]
+    fn test_infer_record_update() {
+        // type person = ... in fun p -> { p with age = true }
+        let expression = Expression::TypeDefinition {
+            definitions: vec![person_definition()],
+            body: Expression::Fun {
+                parameter: "p".to_string(),
+                body: Expression::RecordUpdate {
+                    record: Expression::Variable("p".to_string()).into(),
+                    fields: vec![("age".to_string(), Expression::Bool(true))],
+                }
+                .into(),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_record_update_changes_type_parameter() {
+        let source = "
+            type 'a box = { content : 'a; size : int }
+            let b = { content = 1; size = 1 }
+            let c = { b with content = true }
+        ";
+
+        let result = infer_program(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "bool box");
+    }
+
+    #[test]
+    fn test_infer_record_update_keeps_shared_type_parameter() {
+        // 置き換えない other も 'a を使うので、content だけを別の型にはできない
+        let source = "
+            type 'a pair = { content : 'a; other : 'a }
+            let p = { content = 1; other = 2 }
+            let q = { p with content = true }
+        ";
+
+        let result = infer_program(source);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_record_update_all_fields() {
+        let source = "
+            type ('a, 'b) pair = { first : 'a; second : 'b }
+            let p = { first = 1; second = \"a\" }
+            let q = { p with first = true; second = 'c' }
+        ";
+
+        let result = infer_program(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "(bool, char) pair");
+    }
+
+    #[test]
+    fn test_infer_let_with_pattern() {
+        let source = "
+            let (q, r) = (7 / 2, 7 mod 2) in q + r;;
+            let () = ();;
+            let (f, n) = ((fun x -> x), 1)
+            let b = f true
+        ";
+
+        let result = infer_program(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "bool");
+    }
+
+    #[test]
+    fn test_infer_field_assignment() {
+        // type person = ...;; fun p -> p.age <- 31
+        let assignment = |label: &str| Expression::Fun {
+            parameter: "p".to_string(),
+            body: Expression::FieldAssignment {
+                record: Expression::Variable("p".to_string()).into(),
+                label: label.to_string(),
+                value: Expression::Integer(31).into(),
+            }
+            .into(),
+        };
+
+        let result = infer(declare_types(vec![person_definition()]), assignment("age"));
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t.to_string(), "person -> unit");
+
+        let result = infer(declare_types(vec![person_definition()]), assignment("name"));
+
+        assert!(result.is_err());
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "The record field name is not mutable"
+        );
+    }
+
+    #[test]
+    fn test_infer_let_does_not_leak_binding() {
+        // let x = 1 in x
+        let expression = Expression::Let {
+            variable: "x".to_string(),
+            bound: Expression::Integer(1).into(),
+            body: Expression::Variable("x".to_string()).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        assert!(
+            type_environment
+                .get_variable_type(&"x".to_string())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_infer_top_level_let_is_polymorphic() {
+        // let id = fun x -> x
+        // (id 1, id true)
+        let declaration = TopLevel::Let {
+            variable: "id".to_string(),
+            bound: Expression::Fun {
+                parameter: "x".to_string(),
+                body: Expression::Variable("x".to_string()).into(),
+            },
+        };
+        let app = |argument: Expression| Expression::App {
+            function: Expression::Variable("id".to_string()).into(),
+            argument: argument.into(),
+        };
+        let expression = TopLevel::Expression(Expression::Tuple(vec![
+            app(Expression::Integer(1)),
+            app(Expression::Bool(true)),
+        ]));
+
+        let result = infer_top_level(TypeEnvironment::default(), declaration);
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t.to_string(), "int * bool");
+    }
+
+    #[test]
+    fn test_infer_ref() {
+        // let r = ref 1 in r := !r + 1; r
+        let expression = Expression::Let {
+            variable: "r".to_string(),
+            bound: Expression::Ref(Expression::Integer(1).into()).into(),
+            body: Expression::Sequence {
+                expression1: Expression::Assign {
+                    reference: Expression::Variable("r".to_string()).into(),
+                    value: Expression::Plus {
+                        expression1: Expression::Deref(
+                            Expression::Variable("r".to_string()).into(),
+                        )
+                        .into(),
+                        expression2: Expression::Integer(1).into(),
+                    }
+                    .into(),
+                }
+                .into(),
+                expression2: Expression::Variable("r".to_string()).into(),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Ref(Type::Base(BaseType::Integer).into()));
+    }
+
+    #[test]
+    fn test_infer_value_restriction() {
+        // let r = ref [] in r := 1 :: []; r := true :: []
+        let assign = |element: Expression| Expression::Assign {
+            reference: Expression::Variable("r".to_string()).into(),
+            value: Expression::Cons {
+                car: element.into(),
+                cdr: Expression::Nil.into(),
+            }
+            .into(),
+        };
+        let expression = Expression::Let {
+            variable: "r".to_string(),
+            bound: Expression::Ref(Expression::Nil.into()).into(),
+            body: Expression::Sequence {
+                expression1: assign(Expression::Integer(1)).into(),
+                expression2: assign(Expression::Bool(true)).into(),
+            }
+            .into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_top_level_ref_is_not_generalized() {
+        // let r = ref []
+        // r := 1 :: []
+        // !r
+        let declaration = TopLevel::Let {
+            variable: "r".to_string(),
+            bound: Expression::Ref(Expression::Nil.into()),
+        };
+        let assignment = TopLevel::Expression(Expression::Assign {
+            reference: Expression::Variable("r".to_string()).into(),
+            value: Expression::Cons {
+                car: Expression::Integer(1).into(),
+                cdr: Expression::Nil.into(),
+            }
+            .into(),
+        });
+        let expression = TopLevel::Expression(Expression::Deref(
+            Expression::Variable("r".to_string()).into(),
+        ));
+
+        let type_environment = [declaration, assignment].into_iter().try_fold(
+            TypeEnvironment::default(),
+            |type_environment, top_level| {
+                infer_top_level(type_environment, top_level)
+                    .map(|(type_environment, _)| type_environment)
+            },
+        );
+        assert!(type_environment.is_ok());
+        let result = infer_top_level(type_environment.unwrap(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t.to_string(), "int list");
+    }
+
+    fn error_exception() -> TopLevel {
+        // exception Error of int
+        TopLevel::Exception {
+            name: "Error".to_string(),
+            argument: Some(Type::Base(BaseType::Integer)),
+        }
+    }
+
+    #[test]
+    fn test_infer_try() {
+        // exception Error of int
+        // try raise (Error 1) with Error n -> n
+        let expression = TopLevel::Expression(Expression::Try {
+            body: Expression::Raise(
+                Expression::Constructor {
+                    name: "Error".to_string(),
+                    argument: Some(Expression::Integer(1).into()),
+                }
+                .into(),
+            )
+            .into(),
+            arms: vec![(
+                Pattern::Constructor {
+                    name: "Error".to_string(),
+                    argument: Some(Pattern::Variable("n".to_string()).into()),
+                },
+                Expression::Variable("n".to_string()),
+            )],
+        });
+
+        let result = infer_top_level(TypeEnvironment::default(), error_exception());
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_ok());
+        let (type_environment, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+        assert!(type_environment.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_infer_raise_non_exception() {
+        // exception Error of int
+        // raise 1
+        let expression = TopLevel::Expression(Expression::Raise(Expression::Integer(1).into()));
+
+        let result = infer_top_level(TypeEnvironment::default(), error_exception());
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_try_with_mismatched_handler() {
+        // exception Error of int
+        // try 1 with Error _ -> true
+        let expression = TopLevel::Expression(Expression::Try {
+            body: Expression::Integer(1).into(),
+            arms: vec![(
+                Pattern::Constructor {
+                    name: "Error".to_string(),
+                    argument: Some(Pattern::Wildcard.into()),
+                },
+                Expression::Bool(true),
+            )],
+        });
+
+        let result = infer_top_level(TypeEnvironment::default(), error_exception());
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_assert_false_is_polymorphic() {
+        // if true then 1 else assert false
+        let expression = Expression::If {
+            predicate: Expression::Bool(true).into(),
+            consequent: Expression::Integer(1).into(),
+            alternative: Expression::Assert(Expression::Bool(false).into()).into(),
+        };
+
+        let result = infer(TypeEnvironment::default(), expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+    }
+
+    fn counter_module() -> Vec<TopLevel> {
+        // type t = Count of int
+        // let zero = Count 0
+        // let get = fun c -> match c with Count n -> n
+        vec![
+            TopLevel::TypeDefinition(vec![TypeDefinition {
+                name: "t".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Variant(vec![(
+                    "Count".to_string(),
+                    Some(Type::Base(BaseType::Integer)),
+                )]),
+            }]),
+            TopLevel::Let {
+                variable: "zero".to_string(),
+                bound: Expression::Constructor {
+                    name: "Count".to_string(),
+                    argument: Some(Expression::Integer(0).into()),
+                },
+            },
+            TopLevel::Let {
+                variable: "get".to_string(),
+                bound: Expression::Fun {
+                    parameter: "c".to_string(),
+                    body: Expression::Match {
+                        scrutinee: Expression::Variable("c".to_string()).into(),
+                        arms: vec![(
+                            Pattern::Constructor {
+                                name: "Count".to_string(),
+                                argument: Some(Pattern::Variable("n".to_string()).into()),
+                            },
+                            Expression::Variable("n".to_string()),
+                        )],
+                    }
+                    .into(),
+                },
+            },
+        ]
+    }
+
+    #[test]
+    fn test_infer_module_qualified_names() {
+        // module M = struct ... end
+        // M.get M.zero
+        let declaration = TopLevel::Module {
+            name: "M".to_string(),
+            module: ModuleExpression::Structure(counter_module()),
+        };
+        let expression = TopLevel::Expression(Expression::App {
+            function: Expression::Variable("M.get".to_string()).into(),
+            argument: Expression::Variable("M.zero".to_string()).into(),
+        });
+
+        let result = infer_top_level(TypeEnvironment::default(), declaration);
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        assert!(
+            type_environment
+                .get_variable_type(&"zero".to_string())
+                .is_err()
+        );
+        assert_eq!(
+            type_environment
+                .get_variable_type(&"M.zero".to_string())
+                .unwrap()
+                .to_string(),
+            "M.t"
+        );
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+    }
+
+    #[test]
+    fn test_infer_abstract_type_hides_constructors() {
+        // module M : sig type t val zero : t end = struct ... end
+        // M.Count 1
+        let declaration = TopLevel::Module {
+            name: "M".to_string(),
+            module: ModuleExpression::Ascription {
+                module: ModuleExpression::Structure(counter_module()).into(),
+                module_type: ModuleType::Signature(vec![
+                    Specification::Type(vec![TypeDefinition {
+                        name: "t".to_string(),
+                        parameters: vec![],
+                        kind: TypeKind::Abstract,
+                    }]),
+                    Specification::Value {
+                        name: "zero".to_string(),
+                        value_type: Type::Constructor {
+                            name: "t".to_string(),
+                            args: vec![],
+                        },
+                    },
+                ]),
+            },
+        };
+        let expression = TopLevel::Expression(Expression::Constructor {
+            name: "M.Count".to_string(),
+            argument: Some(Expression::Integer(1).into()),
+        });
+
+        let result = infer_top_level(TypeEnvironment::default(), declaration);
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        assert!(
+            type_environment
+                .get_variable_type(&"M.get".to_string())
+                .is_err()
+        );
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_signature_value_mismatch() {
+        // module M : sig val get : int -> int end = struct ... end
+        let declaration = TopLevel::Module {
+            name: "M".to_string(),
+            module: ModuleExpression::Ascription {
+                module: ModuleExpression::Structure(counter_module()).into(),
+                module_type: ModuleType::Signature(vec![Specification::Value {
+                    name: "get".to_string(),
+                    value_type: Type::Function {
+                        domain: Type::Base(BaseType::Integer).into(),
+                        range: Type::Base(BaseType::Integer).into(),
+                    },
+                }]),
+            },
+        };
+
+        let result = infer_top_level(TypeEnvironment::default(), declaration);
+
+        assert!(result.is_err());
+    }
+
+    fn ordered() -> ModuleType {
+        // sig type t val compare : t -> t -> int end
+        let t = Type::Constructor {
+            name: "t".to_string(),
+            args: vec![],
+        };
+        ModuleType::Signature(vec![
+            Specification::Type(vec![TypeDefinition {
+                name: "t".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Abstract,
+            }]),
+            Specification::Value {
+                name: "compare".to_string(),
+                value_type: Type::Function {
+                    domain: t.clone().into(),
+                    range: Type::Function {
+                        domain: t.into(),
+                        range: Type::Base(BaseType::Integer).into(),
+                    }
+                    .into(),
+                },
+            },
+        ])
+    }
+
+    fn int_ordered() -> ModuleExpression {
+        // struct type t = int let compare = fun a -> fun b -> a - b end
+        ModuleExpression::Structure(vec![
+            TopLevel::TypeDefinition(vec![TypeDefinition {
+                name: "t".to_string(),
+                parameters: vec![],
+                kind: TypeKind::Alias(Type::Base(BaseType::Integer)),
+            }]),
+            TopLevel::Let {
+                variable: "compare".to_string(),
+                bound: Expression::Fun {
+                    parameter: "a".to_string(),
+                    body: Expression::Fun {
+                        parameter: "b".to_string(),
+                        body: Expression::Minus {
+                            expression1: Expression::Variable("a".to_string()).into(),
+                            expression2: Expression::Variable("b".to_string()).into(),
+                        }
+                        .into(),
+                    }
+                    .into(),
+                },
+            },
+        ])
+    }
+
+    fn max_functor() -> TopLevel {
+        // module Max (Ord : ORDERED) = struct
+        //   let max = fun a -> fun b -> if Ord.compare a b < 0 then b else a
+        // end
+        let variable = |name: &str| Expression::Variable(name.to_string());
+        let compare = Expression::App {
+            function: Expression::App {
+                function: variable("Ord.compare").into(),
+                argument: variable("a").into(),
+            }
+            .into(),
+            argument: variable("b").into(),
+        };
+        TopLevel::Module {
+            name: "Max".to_string(),
+            module: ModuleExpression::Functor {
+                parameter: "Ord".to_string(),
+                parameter_type: ordered(),
+                body: ModuleExpression::Structure(vec![TopLevel::Let {
+                    variable: "max".to_string(),
+                    bound: Expression::Fun {
+                        parameter: "a".to_string(),
+                        body: Expression::Fun {
+                            parameter: "b".to_string(),
+                            body: Expression::If {
+                                predicate: Expression::LessThan {
+                                    expression1: compare.into(),
+                                    expression2: Expression::Integer(0).into(),
+                                }
+                                .into(),
+                                consequent: variable("b").into(),
+                                alternative: variable("a").into(),
+                            }
+                            .into(),
+                        }
+                        .into(),
+                    },
+                }])
+                .into(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_infer_functor_application() {
+        // module IntMax = Max (struct type t = int ... end)
+        // IntMax.max
+        let declaration = TopLevel::Module {
+            name: "IntMax".to_string(),
+            module: ModuleExpression::Application {
+                functor: ModuleExpression::Path("Max".to_string()).into(),
+                argument: int_ordered().into(),
+            },
+        };
+        let expression = TopLevel::Expression(Expression::Variable("IntMax.max".to_string()));
+
+        let result = infer_top_level(TypeEnvironment::default(), max_functor());
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, declaration);
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t.to_string(), "int -> int -> int");
+    }
+
+    #[test]
+    fn test_infer_redeclared_module_types_are_distinct() {
+        let source = "
+            module M = struct type t = A of int end;;
+            let x = M.A 1;;
+            module M = struct type t = A of bool end;;
+            let f (M.A b) = if b then 1 else 2;;
+            f x";
+        assert!(infer_program(source).is_err());
+
+        let source = "
+            module M = struct type t = A | B end;;
+            let x = M.A;;
+            module M = struct type t = A | B end;;
+            x = M.B";
+        assert!(infer_program(source).is_err());
+
+        // 前のモジュールの型は M/1.t のような名前になる
+        let source = "
+            module M = struct type t = A of int end;;
+            let x = M.A 1;;
+            module M = struct type t = A of bool end;;
+            x";
+        let result = infer_program(source);
+        assert!(result.is_ok());
+        let t = result.unwrap().to_string();
+        assert!(t.starts_with("M/") && t.ends_with(".t"));
+    }
+
+    #[test]
+    fn test_infer_redeclared_module_refers_to_previous_module() {
+        let source = "
+            module M = struct type t = A | B let v = A end;;
+            module M = struct type t = C let w = M.v let c = C end;;
+            (M.w, M.c)";
+
+        let result = infer_program(source);
+
+        assert!(result.is_ok());
+        let t = result.unwrap().to_string();
+        assert!(t.starts_with("M/") && t.ends_with(".t * M.t"));
+    }
+
+    #[test]
+    fn test_infer_signature_rejects_weak_variable() {
+        // 一般化されていない 'a list ref の値は、多相的な val r : 'a list ref を満たさない
+        let source = "
+            module type S = sig val r : 'a list ref end;;
+            module M : S = struct let r = ref [] end;;
+            M.r := [1];;
+            match !M.r with [] -> \"\" | x :: _ -> x ^ \"a\"";
+
+        let result = infer_program(source);
+
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .starts_with("Values do not match: r :")
+        );
+
+        let source = "
+            module type S = sig val r : int list ref end;;
+            module M : S = struct let r = ref [] end;;
+            M.r := [1];;
+            !M.r";
+
+        let result = infer_program(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "int list");
+    }
+
+    #[test]
+    fn test_infer_functor_argument_rejects_weak_variable() {
+        let source = "
+            module type S = sig val r : 'a list ref end;;
+            module F (X : S) = struct let r = X.r end;;
+            module A = F (struct let r = ref [] end);;
+            A.r := [1];;
+            match !A.r with [] -> \"\" | x :: _ -> x ^ \"a\"";
+
+        let result = infer_program(source);
+
+        assert!(result.is_err());
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .starts_with("Values do not match: r :")
+        );
+    }
+
+    #[test]
+    fn test_infer_functor_argument_mismatch() {
+        // module M = Max (struct end)
+        let declaration = TopLevel::Module {
+            name: "M".to_string(),
+            module: ModuleExpression::Application {
+                functor: ModuleExpression::Path("Max".to_string()).into(),
+                argument: ModuleExpression::Structure(vec![]).into(),
+            },
+        };
+
+        let result = infer_top_level(TypeEnvironment::default(), max_functor());
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, declaration);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_with_type_constraint() {
+        // module IntOrd = (struct type t = int ... end : ORDERED with type t = int)
+        // IntOrd.compare 1 2
+        let declaration = TopLevel::Module {
+            name: "IntOrd".to_string(),
+            module: ModuleExpression::Ascription {
+                module: int_ordered().into(),
+                module_type: ModuleType::With {
+                    module_type: ordered().into(),
+                    constraints: vec![TypeDefinition {
+                        name: "t".to_string(),
+                        parameters: vec![],
+                        kind: TypeKind::Alias(Type::Base(BaseType::Integer)),
+                    }],
+                },
+            },
+        };
+        let expression = TopLevel::Expression(Expression::App {
+            function: Expression::App {
+                function: Expression::Variable("IntOrd.compare".to_string()).into(),
+                argument: Expression::Integer(1).into(),
+            }
+            .into(),
+            argument: Expression::Integer(2).into(),
+        });
+
+        let result = infer_top_level(TypeEnvironment::default(), declaration);
+        assert!(result.is_ok());
+        let (type_environment, _) = result.unwrap();
+        let result = infer_top_level(type_environment, expression);
+
+        assert!(result.is_ok());
+        let (_, t) = result.unwrap();
+        assert_eq!(t, Type::Base(BaseType::Integer));
+    }
+
+    #[test]
+    fn test_infer_cyclic_abbreviation() {
+        // type t = t list
+        let declaration = TopLevel::TypeDefinition(vec![TypeDefinition {
+            name: "t".to_string(),
+            parameters: vec![],
+            kind: TypeKind::Alias(Type::List(
+                Type::Constructor {
+                    name: "t".to_string(),
+                    args: vec![],
+                }
+                .into(),
+            )),
+        }]);
+
+        let result = infer_top_level(TypeEnvironment::default(), declaration);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_pipeline() {
+        let source = format!(
+            "{} [1; 2; 3] |> map (fun x -> x * 2) |> filter (fun x -> x > 2)",
+            LIST_FUNCTIONS
+        );
+
+        let result = infer_source(&source);
+
+        assert!(result.is_ok());
+        assert_eq!(
+            result.unwrap(),
+            Type::List(Type::Base(BaseType::Integer).into())
+        );
+    }
+
+    #[test]
+    fn test_infer_pipeline_is_left_associative() {
+        // 右結合なら 1 |> (is_zero |> to_int) となり型が合わない
+        let source = "let is_zero n = n = 0 in
+                      let to_int b = if b then 1 else 0 in
+                      1 |> is_zero |> to_int";
+
+        let result = infer_source(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Type::Base(BaseType::Integer));
+    }
+
+    #[test]
+    fn test_infer_application_operator() {
+        // @@ は右結合で、関数適用や + よりも弱く結合する
+        let source = "let succ n = n + 1 in
+                      let is_zero n = n = 0 in
+                      let to_int b = if b then 1 else 0 in
+                      succ @@ to_int @@ is_zero @@ 1 + 2";
+
+        let result = infer_source(source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Type::Base(BaseType::Integer));
+    }
+
+    #[test]
+    fn test_infer_application_operator_mismatch() {
+        let source = "let succ n = n + 1 in succ @@ true";
+
+        let result = infer_source(source);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_let_operators() {
+        let source = format!(
+            "{} let r = let* x = Some 1 and* y = Some true and* z = Some \"a\" in Some (x, y, z)",
+            OPTION_OPERATORS
+        );
+
+        let result = infer_program(&source);
+
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().to_string(), "(int * bool * string) option");
+    }
+
+    #[test]
+    fn test_infer_let_operator_body_mismatch() {
+        let source = format!("{} let r = let* x = Some 1 in x + 1", OPTION_OPERATORS);
+
+        let result = infer_program(&source);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_infer_undefined_let_operator() {
+        let result = infer_source("let* x = 1 in x");
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().to_string(), "Undefined variable: let*");
+    }
 }
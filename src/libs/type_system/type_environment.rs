@@ -4,12 +4,18 @@ use anyhow::{Ok, Result, anyhow, bail};
 use thiserror::Error;
 
 use crate::{
-    adapter::{Symbol, TypeTraverseHistory},
+    adapter::{
+        Symbol, TypeTraverseHistory, base_name, split_path, unique_symbol, unique_type_name,
+    },
+    syntax::ast::{
+        ModuleExpression, Pattern, RecordField, Specification, TopLevel, TypeDefinition, TypeKind,
+    },
     type_system::{
         type_scheme::TypeScheme,
-        types::Type,
+        types::{Type, free_type_variables},
         unification::{Equations, get_equation},
         unification::{add_equation, unify},
+        warning::Warning,
     },
 };
 
@@ -21,30 +27,98 @@ enum NormalizeError {
     UnresolvedType,
 }
 
+// 例外の型。exception宣言のたびに構築子が増える
+pub const EXCEPTION_TYPE: &str = "exn";
+
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct TypeEnvironment {
     variable_types: HashMap<Symbol, TypeScheme>,
     equations: Equations,
+    type_definitions: HashMap<Symbol, TypeDefinition>,
+    constructors: HashMap<Symbol, Symbol>,
+    fields: HashMap<Symbol, Symbol>,
+    matches: Vec<(Type, Vec<Pattern>)>,
+    statements: Vec<Type>,
+    warnings: Vec<Warning>,
+    annotation_variables: HashMap<Symbol, Symbol>,
+    // モジュールの中身は、そのモジュールで宣言した名前だけを持つ型環境として入れ子にする
+    modules: HashMap<Symbol, TypeEnvironment>,
+    module_types: HashMap<Symbol, Vec<Specification>>,
+    functors: HashMap<Symbol, Functor>,
+    // 宣言し直して隠した型の、もとの名前と付け替えた名前。隠した順に並べる
+    shadowed_types: Vec<(Symbol, Symbol)>,
+}
+
+// ファンクタは適用するたびに、引数の型を知った上で本体を型検査し直す
+// そのため、本体とファンクタを定義した場所の型環境を持っておく
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Functor {
+    pub parameter: Symbol,
+    pub parameter_type: Vec<Specification>,
+    pub body: ModuleExpression,
+    pub scope: TypeEnvironment,
 }
 
 impl TypeEnvironment {
+    // M.N.x のような経路をたどり、x を探すべき型環境と x を返す
+    fn namespace<'a, 'b>(&'a self, name: &'b str) -> Option<(&'a TypeEnvironment, &'b str)> {
+        match split_path(name) {
+            Some((module, rest)) => self.modules.get(module)?.namespace(rest),
+            None => Some((self, name)),
+        }
+    }
+
     pub fn get_variable_type(&self, variable_name: &Symbol) -> Result<Type> {
-        if let Some(type_scheme) = self.variable_types.get(variable_name).cloned() {
+        if let Some((namespace, name)) = self.namespace(variable_name)
+            && let Some(type_scheme) = namespace.variable_types.get(name).cloned()
+        {
             return Ok(type_scheme.instantiate());
         }
 
         bail!(NormalizeError::UnresolvedType);
     }
 
+    // 値の型のうち一般化されていない型変数。値を使ったところで1つの型に決まる
+    pub fn get_weak_variables(&self, variable_name: &Symbol) -> HashSet<Symbol> {
+        self.namespace(variable_name)
+            .and_then(|(namespace, name)| namespace.variable_types.get(name))
+            .map(|type_scheme| {
+                free_type_variables(type_scheme.base_type().clone())
+                    .into_iter()
+                    .filter(|variable| !type_scheme.variables().contains(variable))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn type_schemes(&self) -> Vec<&TypeScheme> {
+        self.variable_types
+            .values()
+            .chain(
+                self.modules
+                    .values()
+                    .flat_map(TypeEnvironment::type_schemes),
+            )
+            .collect()
+    }
+
+    // 型環境中で自由な型変数を取り除き、一般化してよい型変数だけを返す
+    // 等式が単一化済みであることを前提とする
     pub fn get_unbound_variables<T: Iterator<Item = Symbol>>(
         &self,
         variables: T,
-    ) -> HashSet<Symbol> {
+    ) -> Result<HashSet<Symbol>> {
         let mut free_variables = HashSet::from_iter(variables);
-        self.variable_types.keys().for_each(|variable_name| {
-            free_variables.remove(variable_name);
-        });
-        free_variables
+        for type_scheme in self.type_schemes() {
+            let normalized_type =
+                self.normalize_type(TypeTraverseHistory::new(), type_scheme.base_type().clone())?;
+            free_type_variables(normalized_type)
+                .difference(type_scheme.variables())
+                .for_each(|variable_name| {
+                    free_variables.remove(variable_name);
+                });
+        }
+        Ok(free_variables)
     }
 
     pub fn substitute_variable(
@@ -57,39 +131,627 @@ impl TypeEnvironment {
 
         Ok(Self {
             variable_types,
-            equations: self.equations,
+            ..self
         })
     }
 
-    pub fn add_equation(self, type1: Type, type2: Type) -> Self {
-        let equations = add_equation(self.equations, type1, type2);
+    // モジュールの型は M.t の形の名前を持つが、その型環境の中では t として登録する
+    pub fn define_type(mut self, definition: TypeDefinition) -> Self {
+        let type_name = base_name(&definition.name).to_string();
+        match &definition.kind {
+            TypeKind::Variant(constructors) => {
+                for (constructor, _) in constructors {
+                    self.constructors
+                        .insert(constructor.clone(), type_name.clone());
+                }
+            }
+            TypeKind::Record(fields) => {
+                for field in fields {
+                    self.fields.insert(field.label.clone(), type_name.clone());
+                }
+            }
+            TypeKind::Abstract | TypeKind::Alias(_) => {}
+        }
+        self.type_definitions.insert(type_name, definition);
+        self
+    }
+
+    // 同じ名前の例外を宣言し直すと、前の宣言を置き換える
+    pub fn define_exception(mut self, name: Symbol, argument_type: Option<Type>) -> Self {
+        let definition = self
+            .type_definitions
+            .entry(EXCEPTION_TYPE.to_string())
+            .or_insert_with(|| TypeDefinition {
+                name: EXCEPTION_TYPE.to_string(),
+                parameters: vec![],
+                kind: TypeKind::Variant(vec![]),
+            });
+        if let TypeKind::Variant(constructors) = &mut definition.kind {
+            constructors.retain(|(constructor, _)| constructor != &name);
+            constructors.push((name.clone(), argument_type));
+        }
+        self.constructors.insert(name, EXCEPTION_TYPE.to_string());
+        self
+    }
+
+    pub fn get_type_definition(&self, type_name: &Symbol) -> Option<&TypeDefinition> {
+        let (namespace, type_name) = self.namespace(type_name)?;
+        namespace.type_definitions.get(type_name)
+    }
+
+    // 構築子が作る値の型と、構築子の引数の型を返す
+    // 型パラメータはTypeSchemeで具体化し、呼び出しごとに新しい型変数にする
+    pub fn get_constructor(&self, constructor_name: &Symbol) -> Option<(Type, Option<Type>)> {
+        let (namespace, constructor_name) = self.namespace(constructor_name)?;
+        let type_name = namespace.constructors.get(constructor_name)?;
+        let definition = namespace.type_definitions.get(type_name)?;
+        let TypeKind::Variant(constructors) = &definition.kind else {
+            return None;
+        };
+        let (_, argument_type) = constructors
+            .iter()
+            .find(|(name, _)| name == constructor_name)?;
+
+        let constructed_type = Type::Constructor {
+            name: definition.name.clone(),
+            args: definition
+                .parameters
+                .iter()
+                .map(|parameter| Type::Variable {
+                    name: parameter.clone(),
+                })
+                .collect(),
+        };
+        let base_type = match argument_type {
+            Some(argument_type) => Type::Function {
+                domain: argument_type.clone().into(),
+                range: constructed_type.into(),
+            },
+            None => constructed_type,
+        };
+        let type_scheme = TypeScheme::new_polymorphic_type_scheme(
+            definition.parameters.iter().cloned(),
+            base_type,
+        );
 
+        match (type_scheme.instantiate(), argument_type) {
+            (Type::Function { domain, range }, Some(_)) => Some((*range, Some(*domain))),
+            (t, _) => Some((t, None)),
+        }
+    }
+
+    pub fn get_record_name(&self, label: &Symbol) -> Option<&Symbol> {
+        let (namespace, label) = self.namespace(label)?;
+        let type_name = namespace.fields.get(label)?;
+        Some(&namespace.type_definitions.get(type_name)?.name)
+    }
+
+    // ラベルが属するレコード型と、その型のすべてのフィールドを返す
+    // 型パラメータは新しい型変数で具体化し、レコード型とフィールドの型で共有する
+    pub fn get_record(&self, label: &Symbol) -> Option<(Type, Vec<RecordField>)> {
+        let (namespace, label) = self.namespace(label)?;
+        let type_name = namespace.fields.get(label)?;
+        let definition = namespace.type_definitions.get(type_name)?;
+        let TypeKind::Record(fields) = &definition.kind else {
+            return None;
+        };
+
+        let arguments = definition
+            .parameters
+            .iter()
+            .map(|_| Type::Variable {
+                name: unique_symbol(),
+            })
+            .collect::<Vec<_>>();
+        let instantiate = |t: Type| {
+            definition
+                .parameters
+                .iter()
+                .zip(&arguments)
+                .fold(t, |t, (parameter, argument)| {
+                    t.apply_substitution_for_type(parameter.clone(), argument.clone())
+                })
+        };
+
+        Some((
+            Type::Constructor {
+                name: definition.name.clone(),
+                args: arguments.clone(),
+            },
+            fields
+                .iter()
+                .map(|field| RecordField {
+                    field_type: instantiate(field.field_type.clone()),
+                    ..field.clone()
+                })
+                .collect(),
+        ))
+    }
+
+    // 変数の束縛だけを`scope`のものに戻し、等式は引き継ぐ
+    pub fn restore_scope(self, scope: &TypeEnvironment) -> Self {
         Self {
-            variable_types: self.variable_types,
-            equations,
+            variable_types: scope.variable_types.clone(),
+            ..self
         }
     }
 
+    // モジュールの中で宣言した名前だけを取り出した型環境を作る
+    fn export(&self, declarations: &[TopLevel]) -> TypeEnvironment {
+        let mut namespace = TypeEnvironment::default();
+        for declaration in declarations {
+            match declaration {
+                TopLevel::Let { variable, .. } => namespace.export_variable(self, variable),
+                TopLevel::LetPattern { pattern, .. } => {
+                    for variable in pattern.variables() {
+                        namespace.export_variable(self, &variable);
+                    }
+                }
+                TopLevel::LetRec { bindings } => {
+                    for (variable, _) in bindings {
+                        namespace.export_variable(self, variable);
+                    }
+                }
+                TopLevel::TypeDefinition(definitions) => {
+                    for definition in definitions {
+                        if let Some(definition) = self.type_definitions.get(&definition.name) {
+                            namespace = namespace.define_type(definition.clone());
+                        }
+                    }
+                }
+                TopLevel::Exception { name, .. } => {
+                    namespace
+                        .constructors
+                        .insert(name.clone(), EXCEPTION_TYPE.to_string());
+                    if let Some(definition) = self.type_definitions.get(EXCEPTION_TYPE) {
+                        namespace
+                            .type_definitions
+                            .insert(EXCEPTION_TYPE.to_string(), definition.clone());
+                    }
+                }
+                TopLevel::Module { name, .. } => {
+                    if let Some(module) = self.modules.get(name) {
+                        namespace.modules.insert(name.clone(), module.clone());
+                    }
+                    if let Some(functor) = self.functors.get(name) {
+                        namespace.functors.insert(name.clone(), functor.clone());
+                    }
+                }
+                TopLevel::ModuleType { name, .. } => {
+                    if let Some(module_type) = self.module_types.get(name) {
+                        namespace
+                            .module_types
+                            .insert(name.clone(), module_type.clone());
+                    }
+                }
+                TopLevel::Expression(_) | TopLevel::Open(_) => {}
+            }
+        }
+        namespace
+    }
+
+    fn export_variable(&mut self, scope: &TypeEnvironment, variable: &Symbol) {
+        if let Some(type_scheme) = scope.variable_types.get(variable) {
+            self.variable_types
+                .insert(variable.clone(), type_scheme.clone());
+        }
+    }
+
+    // このモジュールで定義した型の名前。入れ子のモジュール N で定義した型は N.t の形になっている
+    // 例外の型はすべてのモジュールで共有する
+    fn owned_type_names(&self, prefix: &str) -> HashSet<Symbol> {
+        let mut names = self
+            .type_definitions
+            .iter()
+            .filter(|(type_name, definition)| {
+                *type_name != EXCEPTION_TYPE && definition.name == format!("{prefix}{type_name}")
+            })
+            .map(|(_, definition)| definition.name.clone())
+            .collect::<HashSet<_>>();
+        for (name, module) in &self.modules {
+            names.extend(module.owned_type_names(&format!("{prefix}{name}.")));
+        }
+        names
+    }
+
+    fn rename_types(self, rename: &impl Fn(&Symbol) -> Option<Symbol>) -> Self {
+        Self {
+            variable_types: self
+                .variable_types
+                .into_iter()
+                .map(|(variable, type_scheme)| (variable, type_scheme.rename_constructors(rename)))
+                .collect(),
+            equations: self
+                .equations
+                .into_iter()
+                .map(|(type1, type2)| {
+                    (
+                        type1.rename_constructors(rename),
+                        type2.rename_constructors(rename),
+                    )
+                })
+                .collect(),
+            matches: self
+                .matches
+                .into_iter()
+                .map(|(scrutinee_type, patterns)| {
+                    (scrutinee_type.rename_constructors(rename), patterns)
+                })
+                .collect(),
+            statements: self
+                .statements
+                .into_iter()
+                .map(|statement_type| statement_type.rename_constructors(rename))
+                .collect(),
+            type_definitions: self
+                .type_definitions
+                .into_iter()
+                .map(|(type_name, definition)| (type_name, rename_definition(definition, rename)))
+                .collect(),
+            modules: self
+                .modules
+                .into_iter()
+                .map(|(name, module)| (name, module.rename_types(rename)))
+                .collect(),
+            ..self
+        }
+    }
+
+    // この型環境で宣言した型 type_name を new_name という名前の型にする
+    // 型の定義も new_name の名前で引けるように移し、網羅性検査で使えるようにしておく
+    pub fn rename_type(mut self, type_name: &str, new_name: Symbol) -> Self {
+        let Some(definition) = self.type_definitions.remove(type_name) else {
+            return self;
+        };
+        let old_name = definition.name.clone();
+        self.type_definitions.insert(new_name.clone(), definition);
+        for owner in self
+            .constructors
+            .values_mut()
+            .chain(self.fields.values_mut())
+        {
+            if owner == type_name {
+                *owner = new_name.clone();
+            }
+        }
+
+        self.rename_types(&|name| (*name == old_name).then(|| new_name.clone()))
+    }
+
+    // 同じ名前の型を宣言し直す前に、前の型を t/1 のような名前に付け替えて新しい型と区別する
+    // open で見えているだけの型は M.t の名前で区別できるので付け替えない
+    pub fn shadow_type(self, type_name: &str) -> Self {
+        match self.type_definitions.get(type_name) {
+            Some(definition) if type_name != EXCEPTION_TYPE && definition.name == type_name => {
+                let hidden_name = unique_type_name(type_name);
+                let mut environment = self.rename_type(type_name, hidden_name.clone());
+                environment
+                    .shadowed_types
+                    .push((type_name.to_string(), hidden_name));
+                environment
+            }
+            _ => self,
+        }
+    }
+
+    // 同じ名前のモジュールを宣言し直す前に、前のモジュールの型 M.t を M/1.t のような名前に付け替える
+    // 前のモジュールは M/1 という名前でも残し、付け替えた型の定義を引けるようにしておく
+    // 新しいモジュールの中では、宣言し終えるまで前のモジュールを M で参照できる
+    pub fn shadow_module(mut self, module_name: &str) -> Self {
+        let Some(module) = self.modules.get(module_name) else {
+            return self;
+        };
+        let prefix = format!("{module_name}.");
+        let owned_types = module.owned_type_names(&prefix);
+        if owned_types.is_empty() {
+            return self;
+        }
+        let hidden_name = unique_type_name(module_name);
+        let rename = |name: &Symbol| {
+            owned_types
+                .contains(name)
+                .then(|| format!("{hidden_name}.{}", &name[prefix.len()..]))
+        };
+        let module = module.clone().rename_types(&rename);
+        self.modules.insert(hidden_name.clone(), module);
+
+        self.rename_types(&rename)
+    }
+
+    // `scope`の後で隠した型のうち、`scope`で見えていた型の付け替えた名前ともとの名前
+    // 同じ名前を何度か隠したときは、最初に隠したものが`scope`の型になる
+    fn revealed_types(&self, scope: &TypeEnvironment) -> HashMap<Symbol, Symbol> {
+        let mut revealed = HashMap::new();
+        let mut seen = HashSet::new();
+        for (type_name, hidden_name) in &self.shadowed_types[scope.shadowed_types.len()..] {
+            if seen.insert(type_name) {
+                revealed.insert(hidden_name.clone(), type_name.clone());
+            }
+        }
+        revealed
+    }
+
+    // `scope`の後で隠した`scope`の型を、もとの名前に戻す
+    pub fn reveal_types(self, scope: &TypeEnvironment) -> Self {
+        let revealed = self.revealed_types(scope);
+        let environment =
+            revealed
+                .into_iter()
+                .fold(self, |environment, (hidden_name, type_name)| {
+                    environment.rename_type(&hidden_name, type_name)
+                });
+        Self {
+            shadowed_types: scope.shadowed_types.clone(),
+            ..environment
+        }
+    }
+
+    // 型、構築子、ラベルの束縛を`scope`のものに戻す。名前を付け替えて隠した型の定義は残す
+    pub fn restore_types(mut self, scope: &TypeEnvironment) -> Self {
+        for (type_name, definition) in &scope.type_definitions {
+            self.type_definitions
+                .insert(type_name.clone(), definition.clone());
+        }
+        Self {
+            constructors: scope.constructors.clone(),
+            fields: scope.fields.clone(),
+            ..self
+        }
+    }
+
+    // struct ... end を抜けるとき、中で宣言した名前をモジュールの型環境に移し、外の名前の束縛を`scope`のものに戻す
+    // モジュール M の中で定義した型 t は、外から見た名前 M.t にする。例外の宣言と等式は引き継ぐ
+    pub fn leave_module(
+        self,
+        scope: &TypeEnvironment,
+        module_name: &str,
+        declarations: &[TopLevel],
+    ) -> (Self, TypeEnvironment) {
+        // モジュールの中で隠した外の型は、外の名前に戻す
+        let revealed = self.revealed_types(scope);
+        let namespace = self.export(declarations);
+        let owned_types = namespace.owned_type_names("");
+        let rename = |name: &Symbol| match revealed.get(name) {
+            Some(type_name) => Some(type_name.clone()),
+            None => owned_types
+                .contains(name)
+                .then(|| format!("{module_name}.{name}")),
+        };
+        let namespace = namespace.rename_types(&rename);
+
+        let mut type_definitions = scope.type_definitions.clone();
+        if let Some(definition) = self.type_definitions.get(EXCEPTION_TYPE) {
+            type_definitions.insert(
+                EXCEPTION_TYPE.to_string(),
+                rename_definition(definition.clone(), &rename),
+            );
+        }
+        let environment = Self {
+            variable_types: scope.variable_types.clone(),
+            type_definitions,
+            constructors: scope.constructors.clone(),
+            fields: scope.fields.clone(),
+            modules: scope.modules.clone(),
+            module_types: scope.module_types.clone(),
+            functors: scope.functors.clone(),
+            shadowed_types: scope.shadowed_types.clone(),
+            ..self
+        }
+        .rename_types(&|name| revealed.get(name).cloned());
+
+        (environment, namespace)
+    }
+
+    pub fn define_module(mut self, name: Symbol, namespace: TypeEnvironment) -> Self {
+        self.modules.insert(name, namespace);
+        self
+    }
+
+    pub fn get_module(&self, path: &Symbol) -> Option<&TypeEnvironment> {
+        let (namespace, name) = self.namespace(path)?;
+        namespace.modules.get(name)
+    }
+
+    pub fn define_functor(mut self, name: Symbol, functor: Functor) -> Self {
+        self.functors.insert(name, functor);
+        self
+    }
+
+    pub fn get_functor(&self, path: &Symbol) -> Option<&Functor> {
+        let (namespace, name) = self.namespace(path)?;
+        namespace.functors.get(name)
+    }
+
+    pub fn define_module_type(mut self, name: Symbol, specifications: Vec<Specification>) -> Self {
+        self.module_types.insert(name, specifications);
+        self
+    }
+
+    pub fn get_module_type(&self, path: &Symbol) -> Option<&Vec<Specification>> {
+        let (namespace, name) = self.namespace(path)?;
+        namespace.module_types.get(name)
+    }
+
+    // open M で、モジュールの中の名前を経路なしで使えるようにする
+    // 例外の型の定義は置き換えず、モジュールの例外の構築子を加える
+    pub fn open_module(mut self, namespace: &TypeEnvironment) -> Self {
+        if let Some(TypeDefinition {
+            kind: TypeKind::Variant(exceptions),
+            ..
+        }) = namespace.type_definitions.get(EXCEPTION_TYPE)
+        {
+            for (name, argument_type) in exceptions {
+                self = self.define_exception(name.clone(), argument_type.clone());
+            }
+        }
+        self.variable_types.extend(namespace.variable_types.clone());
+        self.type_definitions.extend(
+            namespace
+                .type_definitions
+                .iter()
+                .filter(|(type_name, _)| *type_name != EXCEPTION_TYPE)
+                .map(|(type_name, definition)| (type_name.clone(), definition.clone())),
+        );
+        self.constructors.extend(namespace.constructors.clone());
+        self.fields.extend(namespace.fields.clone());
+        self.modules.extend(namespace.modules.clone());
+        self.module_types.extend(namespace.module_types.clone());
+        self.functors.extend(namespace.functors.clone());
+        self
+    }
+
+    // 型式に書かれた型の名前を、M.t のような型の正式な名前にし、型の別名は展開する
+    // 別名の定義の右辺は、定義したときに解決してある
+    pub fn resolve_type(&self, t: Type) -> Type {
+        t.substitute_constructors(&|name, args| {
+            let definition = self.get_type_definition(name)?;
+            match &definition.kind {
+                TypeKind::Alias(t) => Some(
+                    t.clone()
+                        .instantiate_parameters(&definition.parameters, &args),
+                ),
+                _ => Some(Type::Constructor {
+                    name: definition.name.clone(),
+                    args,
+                }),
+            }
+        })
+    }
+
+    pub fn add_equation(self, type1: Type, type2: Type) -> Self {
+        let equations = add_equation(self.equations, type1, type2);
+
+        Self { equations, ..self }
+    }
+
     pub fn unify_equations(self) -> Result<Self> {
         let equations = unify(self.equations.clone(), Equations::new())?;
 
+        Ok(Self { equations, ..self })
+    }
+
+    // 単一化済みの等式を型環境中の型スキーマに当てはめてから捨てる
+    // 等式を残しておくと、宣言のたびにそれまでの宣言の等式まで単一化し直すことになる
+    pub fn apply_equations(mut self) -> Result<Self> {
+        let solved = Self {
+            equations: std::mem::take(&mut self.equations),
+            ..Self::default()
+        };
+        self.normalize_type_schemes(&solved)
+    }
+
+    fn normalize_type_schemes(self, solved: &TypeEnvironment) -> Result<Self> {
+        let variable_types = self
+            .variable_types
+            .into_iter()
+            .map(|(variable, type_scheme)| {
+                let base_type = solved
+                    .normalize_type(TypeTraverseHistory::new(), type_scheme.base_type().clone())?;
+                let type_scheme = TypeScheme::new_polymorphic_type_scheme(
+                    type_scheme.variables().iter().cloned(),
+                    base_type,
+                );
+                Ok((variable, type_scheme))
+            })
+            .collect::<Result<_>>()?;
+        let modules = self
+            .modules
+            .into_iter()
+            .map(|(name, namespace)| Ok((name, namespace.normalize_type_schemes(solved)?)))
+            .collect::<Result<_>>()?;
+
         Ok(Self {
-            variable_types: self.variable_types,
-            equations,
+            variable_types,
+            modules,
+            ..self
         })
     }
 
+    // 網羅性検査のため、match式の検査対象の型とパターンを記録しておく
+    pub fn record_match(mut self, scrutinee_type: Type, patterns: Vec<Pattern>) -> Self {
+        self.matches.push((scrutinee_type, patterns));
+        self
+    }
+
+    pub fn take_matches(mut self) -> (Self, Vec<(Type, Vec<Pattern>)>) {
+        let matches = std::mem::take(&mut self.matches);
+        (self, matches)
+    }
+
+    // e1; e2 の e1 の型を覚えておき、単一化の後でunitかどうかを検査する
+    pub fn record_statement(mut self, statement_type: Type) -> Self {
+        self.statements.push(statement_type);
+        self
+    }
+
+    pub fn take_statements(mut self) -> (Self, Vec<Type>) {
+        let statements = std::mem::take(&mut self.statements);
+        (self, statements)
+    }
+
+    // 型注釈の 'a などの名前付き型変数は、同じトップレベルの宣言の中では同じ型を表す
+    pub fn annotation_type(mut self, annotation: Type) -> (Self, Type) {
+        let mut t = annotation.clone();
+        for name in free_type_variables(annotation) {
+            let variable = self
+                .annotation_variables
+                .entry(name.clone())
+                .or_insert_with(unique_symbol)
+                .clone();
+            t = t.apply_substitution(name, variable);
+        }
+
+        (self, t)
+    }
+
+    pub fn clear_annotation_variables(self) -> Self {
+        Self {
+            annotation_variables: HashMap::new(),
+            ..self
+        }
+    }
+
+    pub fn add_warning(mut self, warning: Warning) -> Self {
+        self.warnings.push(warning);
+        self
+    }
+
+    pub fn clear_warnings(self) -> Self {
+        Self {
+            warnings: Vec::new(),
+            ..self
+        }
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
     pub fn normalize_type(&self, mut visited: TypeTraverseHistory, t: Type) -> Result<Type> {
         match t {
             Type::Base(base_type) => Ok(Type::Base(base_type)),
             Type::List(t) => Ok(Type::List(self.normalize_type(visited, *t)?.into())),
+            Type::Ref(t) => Ok(Type::Ref(self.normalize_type(visited, *t)?.into())),
+            Type::Tuple(types) => Ok(Type::Tuple(
+                types
+                    .into_iter()
+                    .map(|t| self.normalize_type(visited.clone(), t))
+                    .collect::<Result<_>>()?,
+            )),
+            Type::Constructor { name, args } => Ok(Type::Constructor {
+                name,
+                args: args
+                    .into_iter()
+                    .map(|t| self.normalize_type(visited.clone(), t))
+                    .collect::<Result<_>>()?,
+            }),
             variable @ Type::Variable { .. } => {
                 if visited.contains(&variable) {
                     bail!(NormalizeError::CyclicTypeReference);
                 }
                 visited.insert(variable.clone());
                 get_equation(&self.equations, variable)
-                    .ok_or(anyhow!(NormalizeError::UnresolvedType))
+                    .ok_or_else(|| anyhow!(NormalizeError::UnresolvedType))
             }
             Type::Function { domain, range } => Ok(Type::Function {
                 domain: self.normalize_type(visited.clone(), *domain)?.into(),
@@ -98,3 +760,43 @@ impl TypeEnvironment {
         }
     }
 }
+
+pub fn rename_definition(
+    definition: TypeDefinition,
+    rename: &impl Fn(&Symbol) -> Option<Symbol>,
+) -> TypeDefinition {
+    let definition = map_definition_types(definition, &|t| t.rename_constructors(rename));
+
+    TypeDefinition {
+        name: rename(&definition.name).unwrap_or(definition.name),
+        ..definition
+    }
+}
+
+// 型定義に現れる型(構築子の引数、フィールド、別名の右辺)をすべて変換する
+pub fn map_definition_types(
+    definition: TypeDefinition,
+    map: &impl Fn(Type) -> Type,
+) -> TypeDefinition {
+    let kind = match definition.kind {
+        TypeKind::Variant(constructors) => TypeKind::Variant(
+            constructors
+                .into_iter()
+                .map(|(constructor, argument_type)| (constructor, argument_type.map(map)))
+                .collect(),
+        ),
+        TypeKind::Record(fields) => TypeKind::Record(
+            fields
+                .into_iter()
+                .map(|field| RecordField {
+                    field_type: map(field.field_type),
+                    ..field
+                })
+                .collect(),
+        ),
+        TypeKind::Abstract => TypeKind::Abstract,
+        TypeKind::Alias(t) => TypeKind::Alias(map(t)),
+    };
+
+    TypeDefinition { kind, ..definition }
+}
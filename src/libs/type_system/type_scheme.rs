@@ -29,6 +29,21 @@ impl TypeScheme {
         }
     }
 
+    pub fn variables(&self) -> &HashSet<Symbol> {
+        &self.variables
+    }
+
+    pub fn base_type(&self) -> &Type {
+        &self.base_type
+    }
+
+    pub fn rename_constructors(self, rename: &impl Fn(&Symbol) -> Option<Symbol>) -> Self {
+        Self {
+            base_type: self.base_type.rename_constructors(rename),
+            ..self
+        }
+    }
+
     pub fn instantiate(self) -> Type {
         let variables = self.variables.clone();
         let mut base_type = self.base_type;
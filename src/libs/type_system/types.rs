@@ -1,19 +1,25 @@
 use std::{collections::HashSet, fmt::Display};
 
-use crate::adapter::Symbol;
+use crate::adapter::{Symbol, unique_symbol};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BaseType {
     Integer,
     Bool,
+    String,
+    Char,
+    Unit,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Type {
     Base(BaseType),
     List(Box<Type>),
+    Ref(Box<Type>),
+    Tuple(Vec<Type>),
     Variable { name: Symbol },
     Function { domain: Box<Type>, range: Box<Type> },
+    Constructor { name: Symbol, args: Vec<Type> },
 }
 
 impl Type {
@@ -34,6 +40,39 @@ impl Type {
                     .apply_substitution(target_variable_name.clone(), new_variable_name.clone())
                     .into(),
             },
+            Type::List(element_type) => Type::List(
+                element_type
+                    .apply_substitution(target_variable_name, new_variable_name)
+                    .into(),
+            ),
+            Type::Ref(content_type) => Type::Ref(
+                content_type
+                    .apply_substitution(target_variable_name, new_variable_name)
+                    .into(),
+            ),
+            Type::Tuple(types) => Type::Tuple(
+                types
+                    .into_iter()
+                    .map(|t| {
+                        t.apply_substitution(
+                            target_variable_name.clone(),
+                            new_variable_name.clone(),
+                        )
+                    })
+                    .collect(),
+            ),
+            Type::Constructor { name, args } => Type::Constructor {
+                name,
+                args: args
+                    .into_iter()
+                    .map(|t| {
+                        t.apply_substitution(
+                            target_variable_name.clone(),
+                            new_variable_name.clone(),
+                        )
+                    })
+                    .collect(),
+            },
             t => t,
         }
     }
@@ -49,9 +88,105 @@ impl Type {
                     .apply_substitution_for_type(target_variable_name.clone(), new_type.clone())
                     .into(),
             },
+            Type::List(element_type) => Type::List(
+                element_type
+                    .apply_substitution_for_type(target_variable_name, new_type)
+                    .into(),
+            ),
+            Type::Ref(content_type) => Type::Ref(
+                content_type
+                    .apply_substitution_for_type(target_variable_name, new_type)
+                    .into(),
+            ),
+            Type::Tuple(types) => Type::Tuple(
+                types
+                    .into_iter()
+                    .map(|t| {
+                        t.apply_substitution_for_type(
+                            target_variable_name.clone(),
+                            new_type.clone(),
+                        )
+                    })
+                    .collect(),
+            ),
+            Type::Constructor { name, args } => Type::Constructor {
+                name,
+                args: args
+                    .into_iter()
+                    .map(|t| {
+                        t.apply_substitution_for_type(
+                            target_variable_name.clone(),
+                            new_type.clone(),
+                        )
+                    })
+                    .collect(),
+            },
+            t => t,
+        }
+    }
+
+    // 型構築子の名前を置き換える。モジュールの外から見た M.t の形の名前にするときに使う
+    pub fn rename_constructors(self, rename: &impl Fn(&Symbol) -> Option<Symbol>) -> Self {
+        self.substitute_constructors(&|name, args| {
+            let name = rename(name)?;
+            Some(Type::Constructor { name, args })
+        })
+    }
+
+    // 型構築子の適用を別の型に置き換える。引数は先に置き換えてから渡す
+    // 置き換えない型構築子には None を返す
+    pub fn substitute_constructors(
+        self,
+        substitute: &impl Fn(&Symbol, Vec<Type>) -> Option<Type>,
+    ) -> Self {
+        match self {
+            Type::Constructor { name, args } => {
+                let args = args
+                    .into_iter()
+                    .map(|t| t.substitute_constructors(substitute))
+                    .collect::<Vec<_>>();
+                substitute(&name, args.clone()).unwrap_or(Type::Constructor { name, args })
+            }
+            Type::Function { domain, range } => Type::Function {
+                domain: domain.substitute_constructors(substitute).into(),
+                range: range.substitute_constructors(substitute).into(),
+            },
+            Type::List(element_type) => {
+                Type::List(element_type.substitute_constructors(substitute).into())
+            }
+            Type::Ref(content_type) => {
+                Type::Ref(content_type.substitute_constructors(substitute).into())
+            }
+            Type::Tuple(types) => Type::Tuple(
+                types
+                    .into_iter()
+                    .map(|t| t.substitute_constructors(substitute))
+                    .collect(),
+            ),
             t => t,
         }
     }
+
+    // 型パラメータを同時に引数の型で置き換える
+    // 引数に同じ名前の型変数があっても混ざらないように、いったん新しい名前にする
+    pub fn instantiate_parameters(self, parameters: &[Symbol], arguments: &[Type]) -> Self {
+        let fresh = parameters
+            .iter()
+            .map(|_| unique_symbol())
+            .collect::<Vec<_>>();
+        let t = parameters
+            .iter()
+            .zip(&fresh)
+            .fold(self, |t, (parameter, fresh)| {
+                t.apply_substitution(parameter.clone(), fresh.clone())
+            });
+        fresh
+            .into_iter()
+            .zip(arguments)
+            .fold(t, |t, (fresh, argument)| {
+                t.apply_substitution_for_type(fresh, argument.clone())
+            })
+    }
 }
 
 pub fn free_type_variables(t: Type) -> HashSet<Symbol> {
@@ -61,7 +196,9 @@ pub fn free_type_variables(t: Type) -> HashSet<Symbol> {
             .union(&free_type_variables(*range))
             .cloned()
             .collect(),
-        Type::List(element_type) => free_type_variables(*element_type),
+        Type::List(element_type) | Type::Ref(element_type) => free_type_variables(*element_type),
+        Type::Tuple(types) => types.into_iter().flat_map(free_type_variables).collect(),
+        Type::Constructor { args, .. } => args.into_iter().flat_map(free_type_variables).collect(),
         Type::Base(_) => HashSet::new(),
     }
 }
@@ -71,8 +208,33 @@ impl Display for Type {
         match self {
             Type::Base(BaseType::Integer) => write!(f, "int"),
             Type::Base(BaseType::Bool) => write!(f, "bool"),
+            Type::Base(BaseType::String) => write!(f, "string"),
+            Type::Base(BaseType::Char) => write!(f, "char"),
+            Type::Base(BaseType::Unit) => write!(f, "unit"),
             Type::Variable { name } => write!(f, "{}", name),
-            Type::List(ty) => write!(f, "{} list", ty),
+            Type::List(ty) | Type::Ref(ty) => {
+                let name = if matches!(self, Type::List(_)) {
+                    "list"
+                } else {
+                    "ref"
+                };
+                let needs_paren = matches!(**ty, Type::Function { .. } | Type::Tuple(_));
+                if needs_paren {
+                    write!(f, "({}) {}", ty, name)
+                } else {
+                    write!(f, "{} {}", ty, name)
+                }
+            }
+            Type::Tuple(types) => {
+                let components = types
+                    .iter()
+                    .map(|t| match t {
+                        Type::Function { .. } | Type::Tuple(_) => format!("({})", t),
+                        t => t.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{}", components.join(" * "))
+            }
             Type::Function { domain, range } => {
                 let needs_paren = matches!(**domain, Type::Function { .. });
                 if needs_paren {
@@ -81,6 +243,15 @@ impl Display for Type {
                     write!(f, "{} -> {}", domain, range)
                 }
             }
+            Type::Constructor { name, args } => match args.as_slice() {
+                [] => write!(f, "{}", name),
+                [arg @ (Type::Function { .. } | Type::Tuple(_))] => write!(f, "({}) {}", arg, name),
+                [arg] => write!(f, "{} {}", arg, name),
+                args => {
+                    let args = args.iter().map(Type::to_string).collect::<Vec<_>>();
+                    write!(f, "({}) {}", args.join(", "), name)
+                }
+            },
         }
     }
 }
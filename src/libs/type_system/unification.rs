@@ -42,13 +42,10 @@ fn get_equation_internal(
     }
     visited.insert(name.clone());
 
+    // 単一化済みの等式は (型変数, 型) の形なので、左辺から右辺へだけ辿る
     let replacement = equations
         .iter()
-        .find_map(|(t1, t2)| match (*t1 == t, *t2 == t) {
-            (true, _) => Some(t2.clone()),
-            (_, true) => Some(t1.clone()),
-            _ => None,
-        });
+        .find_map(|(t1, t2)| (*t1 == t).then(|| t2.clone()));
 
     match replacement {
         Some(new_type) if new_type != t => get_equation_internal(equations, new_type, visited),
@@ -99,6 +96,40 @@ pub fn unify(equations: Equations, substitutions: Equations) -> Result<Equations
             let new_equations = add_equation(remaining, *t1, *t2);
             unify(new_equations, substitutions)
         }
+        // (EU{(Ref(t1),Ref(t2))},S) => (EU{(t1,t2)},S)
+        (Type::Ref(t1), Type::Ref(t2)) => {
+            let new_equations = add_equation(remaining, *t1, *t2);
+            unify(new_equations, substitutions)
+        }
+        // (EU{(t1*...*tn,s1*...*sn)},S) => (EU{(t1,s1),...,(tn,sn)},S)
+        (Type::Tuple(types1), Type::Tuple(types2)) if types1.len() == types2.len() => {
+            let new_equations = types1
+                .into_iter()
+                .zip(types2)
+                .fold(remaining, |equations, (t1, t2)| {
+                    add_equation(equations, t1, t2)
+                });
+            unify(new_equations, substitutions)
+        }
+        // (EU{(t1..tn c,s1..sn c)},S) => (EU{(t1,s1),...,(tn,sn)},S)
+        (
+            Type::Constructor {
+                name: name1,
+                args: args1,
+            },
+            Type::Constructor {
+                name: name2,
+                args: args2,
+            },
+        ) if name1 == name2 && args1.len() == args2.len() => {
+            let new_equations = args1
+                .into_iter()
+                .zip(args2)
+                .fold(remaining, |equations, (t1, t2)| {
+                    add_equation(equations, t1, t2)
+                });
+            unify(new_equations, substitutions)
+        }
         _ => bail!(UnificationError::Impossible),
     }
 }
@@ -131,7 +162,12 @@ fn unify2(
 fn occurs_check(variable_name: Symbol, t: Type) -> bool {
     match t {
         Type::Base(_) => false,
-        Type::List(element_type) => occurs_check(variable_name.clone(), *element_type),
+        Type::List(element_type) | Type::Ref(element_type) => {
+            occurs_check(variable_name.clone(), *element_type)
+        }
+        Type::Tuple(types) | Type::Constructor { args: types, .. } => types
+            .into_iter()
+            .any(|t| occurs_check(variable_name.clone(), t)),
         Type::Variable { name } => variable_name == name,
         Type::Function { domain, range } => {
             occurs_check(variable_name.clone(), *domain)
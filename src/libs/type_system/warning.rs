@@ -0,0 +1,78 @@
+use std::fmt::Display;
+
+use crate::{
+    adapter::{escape_char, escape_string},
+    syntax::ast::Pattern,
+    type_system::types::Type,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    NonExhaustiveMatch { counterexample: Pattern },
+    UnusedMatchCase { pattern: Pattern },
+    NonUnitStatement { statement_type: Type },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::NonExhaustiveMatch { counterexample } => write!(
+                f,
+                "this pattern-matching is not exhaustive. Here is an example of a case that is not matched: {}",
+                source_pattern(counterexample)
+            ),
+            Warning::UnusedMatchCase { pattern } => {
+                write!(f, "this match case is unused: {}", source_pattern(pattern))
+            }
+            Warning::NonUnitStatement { statement_type } => write!(
+                f,
+                "this expression should have type unit, but has type {}",
+                statement_type
+            ),
+        }
+    }
+}
+
+// 警告はプログラムの書き手に向けたものなので、ASTの表示ではなくソースコードの記法で表示する
+fn source_pattern(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Variable(variable) => variable.clone(),
+        Pattern::Integer(i) => i.to_string(),
+        Pattern::Bool(b) => b.to_string(),
+        Pattern::String(s) => format!("\"{}\"", escape_string(s)),
+        Pattern::Char(c) => format!("'{}'", escape_char(*c)),
+        Pattern::Unit => "()".to_string(),
+        Pattern::Nil => "[]".to_string(),
+        Pattern::Cons { car, cdr } => {
+            let car = match **car {
+                Pattern::Cons { .. } => format!("({})", source_pattern(car)),
+                _ => source_pattern(car),
+            };
+            format!("{} :: {}", car, source_pattern(cdr))
+        }
+        Pattern::Tuple(patterns) => {
+            let components = patterns.iter().map(source_pattern).collect::<Vec<_>>();
+            format!("({})", components.join(", "))
+        }
+        Pattern::Constructor { name, argument } => match argument.as_deref() {
+            None => name.clone(),
+            Some(
+                argument @ (Pattern::Cons { .. }
+                | Pattern::Constructor {
+                    argument: Some(_), ..
+                }),
+            ) => {
+                format!("{} ({})", name, source_pattern(argument))
+            }
+            Some(argument) => format!("{} {}", name, source_pattern(argument)),
+        },
+        Pattern::Record(fields) => {
+            let fields = fields
+                .iter()
+                .map(|(label, pattern)| format!("{} = {}", label, source_pattern(pattern)))
+                .collect::<Vec<_>>();
+            format!("{{ {} }}", fields.join("; "))
+        }
+    }
+}